@@ -1,5 +1,7 @@
-use criterion::{self, criterion_group, criterion_main, Criterion};
-use gutenberg_rs::rdf_parser::parse_rdfs_from_content;
+use criterion::{self, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gutenberg_rs::rdf_parser::{
+    parse_rdfs_from_content, parse_rdfs_from_content_with, ParseOptions,
+};
 
 static SAMPLE_1: &str = include_str!("../tests/documents/pg1.rdf");
 static SAMPLE_2: &str = include_str!("../tests/documents/pg25.rdf");
@@ -23,5 +25,34 @@ pub fn bench_parse(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_parse);
+/// number of times the samples are repeated, so there is enough work to split between threads
+const REPEAT: usize = 200;
+
+/// Parse a catalog of a few thousand rdfs with different thread counts (0 is every core)
+pub fn bench_parse_threads(c: &mut Criterion) {
+    let samples = [SAMPLE_1, SAMPLE_2, SAMPLE_3, SAMPLE_4, SAMPLE_5, SAMPLE_6];
+    let documents = (0..REPEAT)
+        .flat_map(|_| samples.iter().map(|x| x.to_string()))
+        .collect::<Vec<String>>();
+
+    let mut group = c.benchmark_group("parse_threads");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(documents.len() as u64));
+    for threads in [1, 2, 4, 0] {
+        let options = ParseOptions {
+            threads,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::new("parse_rdfs_from_content", threads),
+            &options,
+            |b, options| {
+                b.iter(|| parse_rdfs_from_content_with(&documents, options, false).unwrap())
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_parse_threads);
 criterion_main!(benches);
@@ -1,9 +1,9 @@
+use gutenberg_rs::book_query::BookQuery;
 use gutenberg_rs::error::Error;
 use gutenberg_rs::settings::GutenbergCacheSettings;
 use gutenberg_rs::setup_sqlite;
 use gutenberg_rs::sqlite_cache::SQLiteCache;
 use gutenberg_rs::text_get::{get_text_from_link, strip_headers};
-use serde_json::json;
 use std::fs;
 
 // this is a helper function that converts a time (hours:minutes) into spoken english time
@@ -80,13 +80,21 @@ async fn exec() -> Result<(), Error> {
     let mut cache = SQLiteCache::get_cache(&settings).unwrap();
 
     // we query the cache for our particular interests to get the book ids we need
-    let res = cache.query(&json!({
-                    "language": "\"en\"",
-                    "bookshelve": "'Romantic Fiction',
-                    'Astounding Stories','Mystery Fiction','Erotic Fiction',
-                    'Mythology','Adventure','Humor','Bestsellers, American, 1895-1923',
-                    'Short Stories','Harvard Classics','Science Fiction','Gothic Fiction','Fantasy'",
-                }))?;
+    let res = cache.find(&BookQuery::new().language("en").bookshelf_any([
+        "Romantic Fiction",
+        "Astounding Stories",
+        "Mystery Fiction",
+        "Erotic Fiction",
+        "Mythology",
+        "Adventure",
+        "Humor",
+        "Bestsellers, American, 1895-1923",
+        "Short Stories",
+        "Harvard Classics",
+        "Science Fiction",
+        "Gothic Fiction",
+        "Fantasy",
+    ]))?;
 
     // we get the first 10 english books from above categories and concat them into a big pile of text
     let max_number_of_texts = 10;
@@ -94,11 +102,10 @@ async fn exec() -> Result<(), Error> {
     for (idx, r) in res.iter().enumerate() {
         println!("getting text for gutenberg idx: {}", r);
         let links = cache.get_download_links(vec![*r])?;
-        for link in links {
-            let text = get_text_from_link(&settings, &link).await?;
+        if let Some(link) = links.first() {
+            let text = get_text_from_link(&settings, link).await?;
             let stripped_text = strip_headers(text);
             big_string.push_str(&stripped_text);
-            break;
         }
         if idx >= max_number_of_texts {
             break;
@@ -151,6 +158,6 @@ async fn exec() -> Result<(), Error> {
 async fn main() {
     match exec().await {
         Ok(_e) => {}
-        Err(_e) => println!("program failed with error: {}", _e.to_string()),
+        Err(_e) => println!("program failed with error: {}", _e),
     }
 }
@@ -1,12 +1,26 @@
-#[derive(Debug, Copy, Clone)]
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GutenbergFileEntry {
     pub file_link_id: i32,
+    /// the first format of the file
     pub file_type_id: i32,
+    /// every format of the file in rdf order (ex: `text/html` and `application/zip` for a zipped html)
+    pub file_type_ids: Vec<i32>,
+    /// size in bytes
+    pub extent: Option<i64>,
+    /// last modification time, as written in the rdf (`2021-01-28T05:38:53`)
+    pub modified: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Book {
     pub publisher_id: i32,
+    /// the first main title (`dcterms:title`), -1 when there is none
     pub title_id: i32,
+    pub title_ids: Vec<usize>,
+    /// the `dcterms:alternative` titles
+    pub alternative_title_ids: Vec<usize>,
     pub rights_id: i32,
     pub gutenberg_book_id: usize,
     pub date_issued: String,
@@ -15,7 +29,20 @@ pub struct Book {
     pub language_ids: Vec<usize>,
     pub subject_ids: Vec<usize>,
     pub author_ids: Vec<usize>,
+    /// the creators, indices in `ParseResult::agents_dictionary`
+    pub agent_ids: Vec<usize>,
+    /// the other contributors with their MARC relator code (ex: `trl`), indices in `ParseResult::agents_dictionary`
+    pub contributors: Vec<(usize, String)>,
     pub bookshelf_ids: Vec<usize>,
+    /// `dcterms:description`, `dcterms:tableOfContents` and `pgterms:marc520` dictionary indices
+    pub description_ids: Vec<usize>,
+    pub table_of_contents_ids: Vec<usize>,
+    pub summary_ids: Vec<usize>,
+    /// the DCMI type (`Text`, `Sound`, `Image`, `StillImage`...)
+    pub book_type: Option<String>,
+    pub license: Option<String>,
+    /// the values of the custom fields, indices in `ParseResult::custom_dictionaries[key]`
+    pub custom_fields: BTreeMap<String, Vec<usize>>,
 
     pub files: Vec<GutenbergFileEntry>,
 }
@@ -0,0 +1,1281 @@
+use crate::error::Error;
+use crate::language::{language_code, macrolanguage_code, LANGUAGES};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The fields of the cache a `BookQuery` can filter on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryField {
+    /// a language by any of its codes or names (`en`, `eng` and `English` match the same books)
+    Language,
+    /// a language or a member of the macrolanguage (ex: `zh` matches Chinese, Mandarin and Cantonese books)
+    MacroLanguage,
+    Author,
+    /// any title, main or alternative
+    Title,
+    /// a `dcterms:alternative` title
+    AlternativeTitle,
+    /// the subtitle split from the main title (see `split_title`)
+    Subtitle,
+    /// a subject of any vocabulary, LCSH heading or LCC code
+    Subject,
+    Publisher,
+    Bookshelf,
+    Rights,
+    DownloadLinksType,
+    /// the DCMI type of the book (`Text`, `Sound`, `Image`, `StillImage`...)
+    Type,
+    /// a Library of Congress subject heading
+    Lcsh,
+    /// a Library of Congress classification code (ex: `PR` for English literature)
+    Lcc,
+    /// the name of a contributor with any role
+    Contributor,
+    /// the MARC relator code of a contributor role (ex: `trl` matches every translated book)
+    ContributorRole,
+    Translator,
+    Illustrator,
+    Editor,
+}
+
+impl QueryField {
+    /// the key used for this field in the json query form
+    pub fn json_key(&self) -> &'static str {
+        match self {
+            QueryField::Language => "language",
+            QueryField::MacroLanguage => "macrolanguage",
+            QueryField::Author => "author",
+            QueryField::Title => "title",
+            QueryField::AlternativeTitle => "alternativetitle",
+            QueryField::Subtitle => "subtitle",
+            QueryField::Subject => "subject",
+            QueryField::Publisher => "publisher",
+            QueryField::Bookshelf => "bookshelve",
+            QueryField::Rights => "rights",
+            QueryField::DownloadLinksType => "downloadlinkstype",
+            QueryField::Type => "type",
+            QueryField::Lcsh => "lcsh",
+            QueryField::Lcc => "lcc",
+            QueryField::Contributor => "contributor",
+            QueryField::ContributorRole => "contributorrole",
+            QueryField::Translator => "translator",
+            QueryField::Illustrator => "illustrator",
+            QueryField::Editor => "editor",
+        }
+    }
+
+    /// returns how the field is stored: the `books.id` column, the tables, their join and the column we match on
+    pub(crate) fn sql_parts(&self) -> FieldSql {
+        let (book_id, tables, join, column) = match self {
+            QueryField::Language => (
+                "book_languages.bookid",
+                "languages, book_languages",
+                "languages.id = book_languages.languageid",
+                "languages.name",
+            ),
+            QueryField::MacroLanguage => (
+                "book_languages.bookid",
+                "languages, book_languages",
+                "languages.id = book_languages.languageid",
+                "languages.macrolanguage",
+            ),
+            QueryField::Author => (
+                "book_authors.bookid",
+                "authors, book_authors",
+                "authors.id = book_authors.authorid",
+                "authors.name",
+            ),
+            QueryField::Title => ("titles.bookid", "titles", "", "titles.name"),
+            QueryField::AlternativeTitle => (
+                "titles.bookid",
+                "titles",
+                "titles.kind = 'alternative'",
+                "titles.name",
+            ),
+            QueryField::Subtitle => ("books.id", "books", "", "books.subtitle"),
+            QueryField::Subject => (
+                "book_subjects.bookid",
+                "subjects, book_subjects",
+                "subjects.id = book_subjects.subjectid",
+                "subjects.name",
+            ),
+            QueryField::Publisher => (
+                "books.id",
+                "books, publishers",
+                "publishers.id = books.publisherid",
+                "publishers.name",
+            ),
+            QueryField::Bookshelf => (
+                "book_bookshelves.bookid",
+                "bookshelves, book_bookshelves",
+                "bookshelves.id = book_bookshelves.bookshelfid",
+                "bookshelves.name",
+            ),
+            QueryField::Rights => (
+                "books.id",
+                "books, rights",
+                "rights.id = books.rightsid",
+                "rights.name",
+            ),
+            QueryField::DownloadLinksType => (
+                "downloadlinks.bookid",
+                "downloadlinks, downloadlink_formats, downloadlinkstype",
+                "downloadlink_formats.downloadlinkid = downloadlinks.id AND downloadlinkstype.id = downloadlink_formats.downloadtypeid",
+                "downloadlinkstype.name",
+            ),
+            QueryField::Type => ("books.id", "books", "", "books.type"),
+            QueryField::Lcsh => (
+                "book_subjects.bookid",
+                "subjects, book_subjects",
+                "subjects.id = book_subjects.subjectid AND subjects.vocabulary = 'LCSH'",
+                "subjects.name",
+            ),
+            QueryField::Lcc => (
+                "book_subjects.bookid",
+                "subjects, book_subjects",
+                "subjects.id = book_subjects.subjectid AND subjects.vocabulary = 'LCC'",
+                "subjects.name",
+            ),
+            QueryField::Contributor => (
+                "book_contributors.bookid",
+                "authors, book_contributors",
+                "authors.id = book_contributors.authorid",
+                "authors.name",
+            ),
+            QueryField::ContributorRole => (
+                "book_contributors.bookid",
+                "book_contributors",
+                "",
+                "book_contributors.role",
+            ),
+            QueryField::Translator => (
+                "book_contributors.bookid",
+                "authors, book_contributors",
+                "authors.id = book_contributors.authorid AND book_contributors.role = 'trl'",
+                "authors.name",
+            ),
+            QueryField::Illustrator => (
+                "book_contributors.bookid",
+                "authors, book_contributors",
+                "authors.id = book_contributors.authorid AND book_contributors.role = 'ill'",
+                "authors.name",
+            ),
+            QueryField::Editor => (
+                "book_contributors.bookid",
+                "authors, book_contributors",
+                "authors.id = book_contributors.authorid AND book_contributors.role = 'edt'",
+                "authors.name",
+            ),
+        };
+        FieldSql {
+            book_id,
+            tables,
+            join,
+            column,
+        }
+    }
+
+    /// returns where the field is matched, author names are matched by any of their forms (see `parse_author_name`)
+    /// so "Whyte-Melville, G. J. (George John)" is also found as "G. J. Whyte-Melville" and "George John Whyte-Melville"
+    pub(crate) fn match_parts(&self) -> FieldSql {
+        let (book_id, tables, join) = match self {
+            QueryField::Author => (
+                "book_authors.bookid",
+                "author_names, book_authors",
+                "author_names.authorid = book_authors.authorid",
+            ),
+            QueryField::Contributor => (
+                "book_contributors.bookid",
+                "authors, author_names, book_contributors",
+                "authors.id = book_contributors.authorid AND author_names.authorid = authors.id",
+            ),
+            QueryField::Translator => (
+                "book_contributors.bookid",
+                "authors, author_names, book_contributors",
+                "authors.id = book_contributors.authorid AND author_names.authorid = authors.id AND book_contributors.role = 'trl'",
+            ),
+            QueryField::Illustrator => (
+                "book_contributors.bookid",
+                "authors, author_names, book_contributors",
+                "authors.id = book_contributors.authorid AND author_names.authorid = authors.id AND book_contributors.role = 'ill'",
+            ),
+            QueryField::Editor => (
+                "book_contributors.bookid",
+                "authors, author_names, book_contributors",
+                "authors.id = book_contributors.authorid AND author_names.authorid = authors.id AND book_contributors.role = 'edt'",
+            ),
+            _ => return self.sql_parts(),
+        };
+        FieldSql {
+            book_id,
+            tables,
+            join,
+            column: "author_names.name",
+        }
+    }
+
+    /// the column exact matches compare and the values in the form it holds them,
+    /// languages are compared by ISO 639-3 code so any of their codes and names can be used
+    fn exact_values(&self, values: &[String]) -> (&'static str, Vec<String>) {
+        match self {
+            QueryField::Language => (
+                "languages.code",
+                values.iter().map(|x| language_code(x)).collect(),
+            ),
+            QueryField::MacroLanguage => (
+                "languages.macrolanguage",
+                values.iter().map(|x| macrolanguage_code(x)).collect(),
+            ),
+            _ => (self.match_parts().column, values.to_vec()),
+        }
+    }
+
+    /// the codes of the bundled languages a prefix or contains match finds by any of their codes or names,
+    /// as `exact_values` holds them. None for the other fields and for exact matches
+    fn matching_language_codes(
+        &self,
+        matcher: &TextMatch,
+        ignore_case: bool,
+    ) -> Option<Vec<String>> {
+        let macrolanguage = match self {
+            QueryField::Language => false,
+            QueryField::MacroLanguage => true,
+            _ => return None,
+        };
+        let fold = |x: &str| match ignore_case {
+            true => x.to_lowercase(),
+            false => x.to_string(),
+        };
+        let found: Box<dyn Fn(&str) -> bool> = match matcher {
+            TextMatch::Exact(_) => return None,
+            TextMatch::Prefix(value) => {
+                let value = fold(value);
+                Box::new(move |x| fold(x).starts_with(&value))
+            }
+            TextMatch::Contains(value) => {
+                let value = fold(value);
+                Box::new(move |x| fold(x).contains(&value))
+            }
+        };
+        let mut codes = LANGUAGES
+            .iter()
+            .filter(|x| {
+                [
+                    Some(x.code),
+                    x.part1,
+                    x.part2b,
+                    Some(x.english_name),
+                    Some(x.native_name),
+                ]
+                .into_iter()
+                .flatten()
+                .any(&found)
+            })
+            .map(|x| match macrolanguage {
+                true => x.group().to_string(),
+                false => x.code.to_string(),
+            })
+            .collect::<Vec<String>>();
+        codes.sort();
+        codes.dedup();
+        Some(codes)
+    }
+
+    pub const ALL: [QueryField; 19] = [
+        QueryField::Language,
+        QueryField::MacroLanguage,
+        QueryField::Author,
+        QueryField::Title,
+        QueryField::AlternativeTitle,
+        QueryField::Subtitle,
+        QueryField::Subject,
+        QueryField::Publisher,
+        QueryField::Bookshelf,
+        QueryField::Rights,
+        QueryField::DownloadLinksType,
+        QueryField::Type,
+        QueryField::Lcsh,
+        QueryField::Lcc,
+        QueryField::Contributor,
+        QueryField::ContributorRole,
+        QueryField::Translator,
+        QueryField::Illustrator,
+        QueryField::Editor,
+    ];
+}
+
+/// How a condition compares the field values against the stored strings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextMatch {
+    /// the stored value is equal to any of these values
+    Exact(Vec<String>),
+    /// the stored value starts with this value
+    Prefix(String),
+    /// the stored value contains this value
+    Contains(String),
+}
+
+/// The sql pieces describing where a `QueryField` is stored
+pub(crate) struct FieldSql {
+    pub book_id: &'static str,
+    pub tables: &'static str,
+    /// empty when the field is in a single table
+    pub join: &'static str,
+    pub column: &'static str,
+}
+
+impl FieldSql {
+    /// the where clause for `filter`, joined with the tables
+    pub fn where_sql(&self, filter: &str) -> String {
+        match self.join.is_empty() {
+            true => filter.to_string(),
+            false => format!("{} AND {}", self.join, filter),
+        }
+    }
+}
+
+/// A single filter on one field of the cache
+#[derive(Debug, Clone)]
+pub struct QueryCondition {
+    pub field: QueryField,
+    pub matcher: TextMatch,
+    /// compare ignoring (ascii) case
+    pub ignore_case: bool,
+}
+
+/// The fields of the cache a `BookQuery` can filter by range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeField {
+    /// `YYYY-MM-DD` text
+    DateIssued,
+    Downloads,
+    /// the birth year of any of the authors
+    AuthorBirthYear,
+    /// the death year of any of the authors
+    AuthorDeathYear,
+}
+
+impl RangeField {
+    pub fn json_key(&self) -> &'static str {
+        match self {
+            RangeField::DateIssued => "dateissued",
+            RangeField::Downloads => "downloads",
+            RangeField::AuthorBirthYear => "authorbirth",
+            RangeField::AuthorDeathYear => "authordeath",
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            RangeField::DateIssued => "books.dateissued",
+            RangeField::Downloads => "books.numdownloads",
+            RangeField::AuthorBirthYear => "authors.birthdate",
+            RangeField::AuthorDeathYear => "authors.deathdate",
+        }
+    }
+
+    /// the field the range column belongs to, None for the columns of `books`
+    fn linked_field(&self) -> Option<QueryField> {
+        match self {
+            RangeField::AuthorBirthYear | RangeField::AuthorDeathYear => Some(QueryField::Author),
+            _ => None,
+        }
+    }
+
+    pub const ALL: [RangeField; 4] = [
+        RangeField::DateIssued,
+        RangeField::Downloads,
+        RangeField::AuthorBirthYear,
+        RangeField::AuthorDeathYear,
+    ];
+}
+
+/// Matches books whose `field` is between `min` and `max` (both inclusive, a missing bound is open)
+#[derive(Debug, Clone)]
+pub struct RangeCondition {
+    pub field: RangeField,
+    pub min: Option<SqlValue>,
+    pub max: Option<SqlValue>,
+}
+
+/// A boolean expression of conditions
+#[derive(Debug, Clone)]
+pub enum QueryExpr {
+    Condition(QueryCondition),
+    Range(RangeCondition),
+    And(Vec<QueryExpr>),
+    Or(Vec<QueryExpr>),
+    Not(Box<QueryExpr>),
+}
+
+impl QueryExpr {
+    fn condition(field: QueryField, matcher: TextMatch) -> QueryExpr {
+        QueryExpr::Condition(QueryCondition {
+            field,
+            matcher,
+            ignore_case: false,
+        })
+    }
+
+    /// matches books having exactly `value` on `field`
+    pub fn is(field: QueryField, value: &str) -> QueryExpr {
+        QueryExpr::any(field, [value])
+    }
+
+    /// matches books having any of `values` on `field`
+    pub fn any<I: IntoIterator<Item = S>, S: Into<String>>(
+        field: QueryField,
+        values: I,
+    ) -> QueryExpr {
+        QueryExpr::condition(
+            field,
+            TextMatch::Exact(values.into_iter().map(|x| x.into()).collect()),
+        )
+    }
+
+    /// matches books having a value on `field` that starts with `value`
+    pub fn prefix(field: QueryField, value: &str) -> QueryExpr {
+        QueryExpr::condition(field, TextMatch::Prefix(value.to_string()))
+    }
+
+    /// matches books having a value on `field` that contains `value`
+    pub fn contains(field: QueryField, value: &str) -> QueryExpr {
+        QueryExpr::condition(field, TextMatch::Contains(value.to_string()))
+    }
+
+    /// matches books issued between `from` and `to` (inclusive), the dates are `YYYY`, `YYYY-MM` or `YYYY-MM-DD`
+    pub fn issued_between(from: Option<&str>, to: Option<&str>) -> Result<QueryExpr, Error> {
+        let min = match from {
+            Some(date) => Some(SqlValue::Text(date_bound(date, false)?)),
+            None => None,
+        };
+        let max = match to {
+            Some(date) => Some(SqlValue::Text(date_bound(date, true)?)),
+            None => None,
+        };
+        Ok(QueryExpr::Range(RangeCondition {
+            field: RangeField::DateIssued,
+            min,
+            max,
+        }))
+    }
+
+    /// matches books with a number of downloads between `min` and `max` (inclusive)
+    pub fn downloads_between(min: Option<i64>, max: Option<i64>) -> QueryExpr {
+        QueryExpr::Range(RangeCondition {
+            field: RangeField::Downloads,
+            min: min.map(SqlValue::Integer),
+            max: max.map(SqlValue::Integer),
+        })
+    }
+
+    /// matches books with an author born between `min` and `max` (inclusive)
+    pub fn author_born_between(min: Option<i64>, max: Option<i64>) -> QueryExpr {
+        QueryExpr::Range(RangeCondition {
+            field: RangeField::AuthorBirthYear,
+            min: min.map(SqlValue::Integer),
+            max: max.map(SqlValue::Integer),
+        })
+    }
+
+    /// matches books with an author who died between `min` and `max` (inclusive)
+    pub fn author_died_between(min: Option<i64>, max: Option<i64>) -> QueryExpr {
+        QueryExpr::Range(RangeCondition {
+            field: RangeField::AuthorDeathYear,
+            min: min.map(SqlValue::Integer),
+            max: max.map(SqlValue::Integer),
+        })
+    }
+
+    /// matches books whose authors all died before `year`.
+    /// Authors without a known death year (ex: anonymous works) are not taken into account
+    pub fn authors_died_before(year: i64) -> QueryExpr {
+        QueryExpr::all([
+            QueryExpr::author_died_between(None, Some(year - 1)),
+            QueryExpr::negate(QueryExpr::author_died_between(Some(year), None)),
+        ])
+    }
+
+    /// makes every condition in this expression case insensitive
+    pub fn ignore_case(self) -> QueryExpr {
+        match self {
+            QueryExpr::Condition(mut c) => {
+                c.ignore_case = true;
+                QueryExpr::Condition(c)
+            }
+            QueryExpr::Range(range) => QueryExpr::Range(range),
+            QueryExpr::And(items) => {
+                QueryExpr::And(items.into_iter().map(|x| x.ignore_case()).collect())
+            }
+            QueryExpr::Or(items) => {
+                QueryExpr::Or(items.into_iter().map(|x| x.ignore_case()).collect())
+            }
+            QueryExpr::Not(item) => QueryExpr::Not(Box::new(item.ignore_case())),
+        }
+    }
+
+    pub fn all<I: IntoIterator<Item = QueryExpr>>(items: I) -> QueryExpr {
+        QueryExpr::And(items.into_iter().collect())
+    }
+
+    pub fn either<I: IntoIterator<Item = QueryExpr>>(items: I) -> QueryExpr {
+        QueryExpr::Or(items.into_iter().collect())
+    }
+
+    pub fn negate(item: QueryExpr) -> QueryExpr {
+        QueryExpr::Not(Box::new(item))
+    }
+
+    /// Builds an expression from its json form. An object is the AND of its keys, the keys can be
+    /// query fields or the `and`, `or` (arrays of objects) and `not` (object) groups.
+    /// A field value is either a string of quoted values, an array of strings or an object
+    /// with one of `any`, `prefix`, `contains` and an optional `ignore_case` flag.
+    /// The range fields (`dateissued`, `downloads`, `authorbirth`, `authordeath`) take an object with optional
+    /// `min` and `max` bounds.
+    pub fn from_json(json: &Value) -> Result<QueryExpr, Error> {
+        let object = match json.as_object() {
+            Some(object) => object,
+            None => return Err(Error::InvalidQuery("query must be an object".to_string())),
+        };
+        let mut items = Vec::new();
+        for (key, value) in object {
+            match key.as_str() {
+                "and" | "or" => {
+                    let mut group = Vec::new();
+                    match value.as_array() {
+                        Some(values) => {
+                            for v in values {
+                                group.push(QueryExpr::from_json(v)?);
+                            }
+                        }
+                        None => {
+                            return Err(Error::InvalidQuery(format!(
+                                "{} must be an array of queries",
+                                key
+                            )))
+                        }
+                    }
+                    if key == "and" {
+                        items.push(QueryExpr::And(group));
+                    } else {
+                        items.push(QueryExpr::Or(group));
+                    }
+                }
+                "not" => items.push(QueryExpr::negate(QueryExpr::from_json(value)?)),
+                _ if RangeField::ALL.iter().any(|f| f.json_key() == key) => {
+                    items.push(range_from_json(key, value)?)
+                }
+                _ => match QueryField::ALL.iter().find(|f| f.json_key() == key) {
+                    Some(field) => items.push(field_from_json(*field, value)?),
+                    None => {
+                        return Err(Error::InvalidQuery(format!("unknown query key {}", key)));
+                    }
+                },
+            }
+        }
+        if items.len() == 1 {
+            return Ok(items.remove(0));
+        }
+        Ok(QueryExpr::And(items))
+    }
+
+    fn to_sql(&self, params: &mut Vec<SqlValue>) -> String {
+        match self {
+            QueryExpr::Condition(condition) => condition_sql(condition, params),
+            QueryExpr::Range(range) => range_sql(range, params),
+            QueryExpr::And(items) => group_sql(items, " AND ", "1", params),
+            QueryExpr::Or(items) => group_sql(items, " OR ", "0", params),
+            QueryExpr::Not(item) => format!("NOT ({})", item.to_sql(params)),
+        }
+    }
+}
+
+/// The keys query results can be ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Downloads,
+    GutenbergId,
+    DateIssued,
+    Title,
+}
+
+impl SortField {
+    /// the sql expression we order by, it never evaluates to NULL so keyset pagination stays correct
+    fn sql(&self) -> &'static str {
+        match self {
+            SortField::Downloads => "COALESCE(books.numdownloads, 0)",
+            SortField::GutenbergId => "books.gutenbergbookid",
+            SortField::DateIssued => "COALESCE(books.dateissued, '')",
+            SortField::Title => {
+                "COALESCE((SELECT MIN(titles.name) FROM titles WHERE titles.bookid = books.id AND titles.kind = 'title'), '')"
+            }
+        }
+    }
+
+    fn from_json_key(key: &str) -> Option<SortField> {
+        match key {
+            "downloads" => Some(SortField::Downloads),
+            "gutenbergid" => Some(SortField::GutenbergId),
+            "dateissued" => Some(SortField::DateIssued),
+            "title" => Some(SortField::Title),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// The sort key value of a row, used by the cursor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CursorKey {
+    Integer(i64),
+    Text(String),
+}
+
+/// Points after the last row of a page, pass it to `BookQuery::after` (with the same ordering) to get the next page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryCursor {
+    pub key: CursorKey,
+    pub gutenberg_id: i64,
+}
+
+/// One page of query results
+#[derive(Debug, Clone)]
+pub struct QueryPage {
+    pub ids: Vec<i32>,
+    /// set when the page is full, there may be more results after it
+    pub next_cursor: Option<QueryCursor>,
+}
+
+/// Typed query over the sqlite cache, every filter is ANDed together.
+/// All values are sent to sqlite as bound parameters, so they are never spliced into the sql text.
+///
+/// ```ignore
+/// let query = BookQuery::new()
+///     .language("en")
+///     .author("Jefferson, Thomas")
+///     .bookshelf_any(["Fantasy", "Humor"]);
+/// let ids = cache.find(&query)?;
+///
+/// // english or french, not erotic fiction, author starting with Dickens
+/// let query = BookQuery::new()
+///     .language_any(["en", "fr"])
+///     .exclude(QueryExpr::is(QueryField::Bookshelf, "Erotic Fiction"))
+///     .prefix(QueryField::Author, "Dickens")
+///     .with(QueryExpr::contains(QueryField::Subject, "science fiction").ignore_case());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BookQuery {
+    pub filters: Vec<QueryExpr>,
+    pub order: Option<(SortField, SortDirection)>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub after: Option<QueryCursor>,
+}
+
+impl BookQuery {
+    pub fn new() -> BookQuery {
+        BookQuery::default()
+    }
+
+    /// adds an expression the books must match
+    pub fn with(mut self, expr: QueryExpr) -> BookQuery {
+        self.filters.push(expr);
+        self
+    }
+
+    /// adds an expression the books must not match
+    pub fn exclude(self, expr: QueryExpr) -> BookQuery {
+        self.with(QueryExpr::negate(expr))
+    }
+
+    /// adds a group of expressions, the books must match at least one of them
+    pub fn with_any<I: IntoIterator<Item = QueryExpr>>(self, items: I) -> BookQuery {
+        self.with(QueryExpr::either(items))
+    }
+
+    /// orders the results, ties are broken by gutenberg id in the same direction
+    pub fn order_by(mut self, field: SortField, direction: SortDirection) -> BookQuery {
+        self.order = Some((field, direction));
+        self
+    }
+
+    /// at most `limit` results, without an `order_by` they are ordered by gutenberg id
+    pub fn limit(mut self, limit: usize) -> BookQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// skips the first `offset` results, without an `order_by` they are ordered by gutenberg id
+    pub fn offset(mut self, offset: usize) -> BookQuery {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// only returns the results that come after the cursor (keyset pagination)
+    pub fn after(mut self, cursor: QueryCursor) -> BookQuery {
+        self.after = Some(cursor);
+        self
+    }
+
+    /// adds a filter that matches books having any of `values` on `field`
+    pub fn filter_any<I, S>(self, field: QueryField, values: I) -> BookQuery
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.with(QueryExpr::any(field, values))
+    }
+
+    pub fn filter(self, field: QueryField, value: &str) -> BookQuery {
+        self.filter_any(field, [value])
+    }
+
+    /// see `QueryExpr::issued_between`
+    pub fn issued_between(self, from: Option<&str>, to: Option<&str>) -> Result<BookQuery, Error> {
+        Ok(self.with(QueryExpr::issued_between(from, to)?))
+    }
+
+    pub fn downloads_between(self, min: Option<i64>, max: Option<i64>) -> BookQuery {
+        self.with(QueryExpr::downloads_between(min, max))
+    }
+
+    pub fn author_born_between(self, min: Option<i64>, max: Option<i64>) -> BookQuery {
+        self.with(QueryExpr::author_born_between(min, max))
+    }
+
+    pub fn author_died_between(self, min: Option<i64>, max: Option<i64>) -> BookQuery {
+        self.with(QueryExpr::author_died_between(min, max))
+    }
+
+    /// see `QueryExpr::authors_died_before`
+    pub fn authors_died_before(self, year: i64) -> BookQuery {
+        self.with(QueryExpr::authors_died_before(year))
+    }
+
+    pub fn prefix(self, field: QueryField, value: &str) -> BookQuery {
+        self.with(QueryExpr::prefix(field, value))
+    }
+
+    pub fn contains(self, field: QueryField, value: &str) -> BookQuery {
+        self.with(QueryExpr::contains(field, value))
+    }
+
+    pub fn language(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Language, value)
+    }
+    pub fn language_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Language, values)
+    }
+    /// books in the language or, for a macrolanguage, in any of its members
+    pub fn macrolanguage(self, value: &str) -> BookQuery {
+        self.filter(QueryField::MacroLanguage, value)
+    }
+    pub fn author(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Author, value)
+    }
+    pub fn author_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Author, values)
+    }
+    pub fn title(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Title, value)
+    }
+    pub fn title_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Title, values)
+    }
+    pub fn alternative_title(self, value: &str) -> BookQuery {
+        self.filter(QueryField::AlternativeTitle, value)
+    }
+    pub fn subtitle(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Subtitle, value)
+    }
+    pub fn subject(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Subject, value)
+    }
+    pub fn subject_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Subject, values)
+    }
+    pub fn publisher(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Publisher, value)
+    }
+    pub fn publisher_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Publisher, values)
+    }
+    pub fn bookshelf(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Bookshelf, value)
+    }
+    pub fn bookshelf_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Bookshelf, values)
+    }
+    pub fn rights(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Rights, value)
+    }
+    pub fn rights_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Rights, values)
+    }
+    pub fn download_links_type(self, value: &str) -> BookQuery {
+        self.filter(QueryField::DownloadLinksType, value)
+    }
+    pub fn download_links_type_any<I: IntoIterator<Item = S>, S: Into<String>>(
+        self,
+        values: I,
+    ) -> BookQuery {
+        self.filter_any(QueryField::DownloadLinksType, values)
+    }
+
+    pub fn book_type(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Type, value)
+    }
+    pub fn book_type_any<I: IntoIterator<Item = S>, S: Into<String>>(self, values: I) -> BookQuery {
+        self.filter_any(QueryField::Type, values)
+    }
+    /// leaves out the audio books and the image collections, books with no type are kept
+    pub fn exclude_sound_and_images(self) -> BookQuery {
+        self.exclude(QueryExpr::any(
+            QueryField::Type,
+            ["Sound", "Image", "StillImage", "MovingImage"],
+        ))
+    }
+    pub fn lcsh(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Lcsh, value)
+    }
+    pub fn lcc(self, code: &str) -> BookQuery {
+        self.filter(QueryField::Lcc, code)
+    }
+    /// books with a classification code starting with `prefix` (ex: `PR` for English literature, `Q` for science)
+    pub fn lcc_prefix(self, prefix: &str) -> BookQuery {
+        self.prefix(QueryField::Lcc, prefix)
+    }
+    pub fn contributor(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Contributor, value)
+    }
+    /// books having a contributor with this MARC relator code (ex: `trl` for translations)
+    pub fn contributor_role(self, code: &str) -> BookQuery {
+        self.filter(QueryField::ContributorRole, code)
+    }
+    pub fn translator(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Translator, value)
+    }
+    pub fn illustrator(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Illustrator, value)
+    }
+    pub fn editor(self, value: &str) -> BookQuery {
+        self.filter(QueryField::Editor, value)
+    }
+
+    /// Builds a query from the json form accepted by `SQLiteCache::query`, see `QueryExpr::from_json`.
+    /// The simple form is still supported: each field value is a json array of strings or a string
+    /// holding a comma separated list of quoted values (ex: `"'Fantasy','Humor'"` or `"\"en\""`).
+    /// The results may be ordered with `order_by` (`downloads`, `gutenbergid`, `dateissued`, `title`),
+    /// `order` (`asc`, `desc`) and paged with `limit` and `offset`.
+    pub fn from_json(json: &Value) -> Result<BookQuery, Error> {
+        let mut object = match json.as_object() {
+            Some(object) => object.clone(),
+            None => return Err(Error::InvalidQuery("query must be an object".to_string())),
+        };
+        let mut query = BookQuery::new();
+
+        let direction = match object.remove("order") {
+            None => SortDirection::Ascending,
+            Some(value) => match value.as_str() {
+                Some("asc") => SortDirection::Ascending,
+                Some("desc") => SortDirection::Descending,
+                _ => return Err(Error::InvalidQuery("order must be asc or desc".to_string())),
+            },
+        };
+        if let Some(value) = object.remove("order_by") {
+            match value.as_str().and_then(SortField::from_json_key) {
+                Some(field) => query = query.order_by(field, direction),
+                None => {
+                    return Err(Error::InvalidQuery(
+                        "order_by must be one of downloads, gutenbergid, dateissued, title"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+        if let Some(value) = object.remove("limit") {
+            match value.as_u64() {
+                Some(limit) => query = query.limit(limit as usize),
+                None => return Err(Error::InvalidQuery("limit must be a number".to_string())),
+            }
+        }
+        if let Some(value) = object.remove("offset") {
+            match value.as_u64() {
+                Some(offset) => query = query.offset(offset as usize),
+                None => return Err(Error::InvalidQuery("offset must be a number".to_string())),
+            }
+        }
+
+        if !object.is_empty() {
+            query = query.with(QueryExpr::from_json(&Value::Object(object))?);
+        }
+        Ok(query)
+    }
+
+    /// compiles only the filters to a where clause over `books`, None when there are no filters
+    pub(crate) fn filter_sql(&self, params: &mut Vec<SqlValue>) -> Option<String> {
+        if self.filters.is_empty() {
+            return None;
+        }
+        Some(group_sql(&self.filters, " AND ", "1", params))
+    }
+
+    /// compiles the query to sql returning the statement and its positional parameters.
+    /// The statement selects the gutenberg id and, when the query is ordered, the sort key
+    pub fn to_sql(&self) -> (String, Vec<SqlValue>) {
+        // pages need a stable row order, sqlite gives none without an ORDER BY
+        let paged = self.after.is_some() || self.limit.is_some() || self.offset.is_some();
+        let (sort_field, direction) = match self.order {
+            Some(order) => (Some(order.0), order.1),
+            None if paged => (Some(SortField::GutenbergId), SortDirection::Ascending),
+            None => (None, SortDirection::Ascending),
+        };
+        let mut sql = match sort_field {
+            Some(field) => format!("SELECT books.gutenbergbookid, {} FROM books", field.sql()),
+            None => "SELECT DISTINCT books.gutenbergbookid FROM books".to_string(),
+        };
+        let mut params = Vec::new();
+        let mut where_parts = Vec::new();
+        if let Some(filter) = self.filter_sql(&mut params) {
+            where_parts.push(filter);
+        }
+        if let (Some(field), Some(cursor)) = (sort_field, &self.after) {
+            let compare = match direction {
+                SortDirection::Ascending => ">",
+                SortDirection::Descending => "<",
+            };
+            where_parts.push(format!(
+                "({key} {cmp} ? OR ({key} = ? AND books.gutenbergbookid {cmp} ?))",
+                key = field.sql(),
+                cmp = compare
+            ));
+            let key = match &cursor.key {
+                CursorKey::Integer(v) => SqlValue::Integer(*v),
+                CursorKey::Text(v) => SqlValue::Text(v.clone()),
+            };
+            params.push(key.clone());
+            params.push(key);
+            params.push(SqlValue::Integer(cursor.gutenberg_id));
+        }
+        if !where_parts.is_empty() {
+            sql = format!("{} WHERE {}", sql, where_parts.join(" AND "));
+        }
+        if let Some(field) = sort_field {
+            let dir = match direction {
+                SortDirection::Ascending => "ASC",
+                SortDirection::Descending => "DESC",
+            };
+            sql = format!(
+                "{} ORDER BY {} {}, books.gutenbergbookid {}",
+                sql,
+                field.sql(),
+                dir,
+                dir
+            );
+        }
+        if self.limit.is_some() || self.offset.is_some() {
+            // sqlite needs a LIMIT for OFFSET to be used, -1 means no limit
+            sql = format!("{} LIMIT ? OFFSET ?", sql);
+            params.push(SqlValue::Integer(self.limit.map_or(-1, |x| x as i64)));
+            params.push(SqlValue::Integer(self.offset.unwrap_or(0) as i64));
+        }
+        (sql, params)
+    }
+}
+
+fn field_from_json(field: QueryField, value: &Value) -> Result<QueryExpr, Error> {
+    let key = field.json_key();
+    let type_error = || {
+        Error::InvalidQuery(format!(
+            "{} must be a string, an array of strings or a match object",
+            key
+        ))
+    };
+    let string_list = |items: &Vec<Value>| -> Result<Vec<String>, Error> {
+        let mut values = Vec::new();
+        for item in items {
+            match item.as_str() {
+                Some(s) => values.push(s.to_string()),
+                None => return Err(type_error()),
+            }
+        }
+        Ok(values)
+    };
+    match value {
+        Value::String(s) => Ok(QueryExpr::any(
+            field,
+            parse_value_list(s).map_err(|e| Error::InvalidQuery(format!("{}: {}", key, e)))?,
+        )),
+        Value::Array(items) => Ok(QueryExpr::any(field, string_list(items)?)),
+        Value::Object(object) => {
+            let mut expr = None;
+            for (match_key, match_value) in object {
+                let current = match (match_key.as_str(), match_value) {
+                    ("any", Value::Array(items)) => QueryExpr::any(field, string_list(items)?),
+                    ("any", Value::String(s)) => QueryExpr::is(field, s),
+                    ("prefix", Value::String(s)) => QueryExpr::prefix(field, s),
+                    ("contains", Value::String(s)) => QueryExpr::contains(field, s),
+                    ("ignore_case", Value::Bool(_)) => continue,
+                    _ => {
+                        return Err(Error::InvalidQuery(format!(
+                            "{}: bad match {}",
+                            key, match_key
+                        )))
+                    }
+                };
+                if expr.is_some() {
+                    return Err(Error::InvalidQuery(format!(
+                        "{}: only one of any, prefix, contains is allowed",
+                        key
+                    )));
+                }
+                expr = Some(current);
+            }
+            let mut expr = expr.ok_or_else(type_error)?;
+            if let Some(Value::Bool(true)) = object.get("ignore_case") {
+                expr = expr.ignore_case();
+            }
+            Ok(expr)
+        }
+        _ => Err(type_error()),
+    }
+}
+
+fn range_from_json(key: &str, value: &Value) -> Result<QueryExpr, Error> {
+    let min = value.get("min");
+    let max = value.get("max");
+    if !value.is_object() || (min.is_none() && max.is_none()) {
+        return Err(Error::InvalidQuery(format!(
+            "{} must be an object with min and/or max",
+            key
+        )));
+    }
+    let bad_bound = || Error::InvalidQuery(format!("{}: bad range bound", key));
+    if key == RangeField::DateIssued.json_key() {
+        let min = match min {
+            Some(v) => Some(v.as_str().ok_or_else(bad_bound)?),
+            None => None,
+        };
+        let max = match max {
+            Some(v) => Some(v.as_str().ok_or_else(bad_bound)?),
+            None => None,
+        };
+        return QueryExpr::issued_between(min, max);
+    }
+    let min = match min {
+        Some(v) => Some(v.as_i64().ok_or_else(bad_bound)?),
+        None => None,
+    };
+    let max = match max {
+        Some(v) => Some(v.as_i64().ok_or_else(bad_bound)?),
+        None => None,
+    };
+    Ok(match RangeField::ALL.iter().find(|f| f.json_key() == key) {
+        Some(RangeField::AuthorBirthYear) => QueryExpr::author_born_between(min, max),
+        Some(RangeField::AuthorDeathYear) => QueryExpr::author_died_between(min, max),
+        _ => QueryExpr::downloads_between(min, max),
+    })
+}
+
+/// completes a partial date so it can be compared with the stored `YYYY-MM-DD` dates
+fn date_bound(date: &str, is_max: bool) -> Result<String, Error> {
+    let parts = date.split('-').collect::<Vec<&str>>();
+    let valid = !parts.is_empty()
+        && parts.len() <= 3
+        && parts.iter().enumerate().all(|(idx, p)| {
+            p.len() == if idx == 0 { 4 } else { 2 } && p.chars().all(|c| c.is_ascii_digit())
+        });
+    if !valid {
+        return Err(Error::InvalidQuery(format!(
+            "bad date {}, expected YYYY, YYYY-MM or YYYY-MM-DD",
+            date
+        )));
+    }
+    let padding = match (parts.len(), is_max) {
+        (1, false) => "-01-01",
+        (2, false) => "-01",
+        (1, true) => "-12-31",
+        (2, true) => "-31",
+        _ => "",
+    };
+    Ok(format!("{}{}", date, padding))
+}
+
+fn range_sql(range: &RangeCondition, params: &mut Vec<SqlValue>) -> String {
+    let column = range.field.sql();
+    let mut parts = Vec::new();
+    if let Some(min) = &range.min {
+        params.push(min.clone());
+        parts.push(format!("{} >= ?", column));
+    }
+    if let Some(max) = &range.max {
+        params.push(max.clone());
+        parts.push(format!("{} <= ?", column));
+    }
+    if parts.is_empty() {
+        parts.push(format!("{} IS NOT NULL", column));
+    }
+    let filter = parts.join(" AND ");
+    match range.field.linked_field() {
+        Some(field) => {
+            let linked = field.sql_parts();
+            format!(
+                "books.id IN (SELECT {} FROM {} WHERE {})",
+                linked.book_id,
+                linked.tables,
+                linked.where_sql(&filter)
+            )
+        }
+        None => filter,
+    }
+}
+
+fn group_sql(
+    items: &[QueryExpr],
+    separator: &str,
+    empty: &str,
+    params: &mut Vec<SqlValue>,
+) -> String {
+    if items.is_empty() {
+        return empty.to_string();
+    }
+    let parts = items
+        .iter()
+        .map(|x| format!("({})", x.to_sql(params)))
+        .collect::<Vec<String>>();
+    parts.join(separator)
+}
+
+/// escapes the LIKE wildcards so the value is matched literally
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn condition_sql(condition: &QueryCondition, params: &mut Vec<SqlValue>) -> String {
+    let parts = condition.field.match_parts();
+    let column = parts.column;
+    let filter = match (&condition.matcher, condition.ignore_case) {
+        (TextMatch::Exact(values), _) if values.is_empty() => return "0".to_string(),
+        (TextMatch::Exact(values), ignore_case) => {
+            let (column, values) = condition.field.exact_values(values);
+            let placeholders = values.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
+            params.extend(values.into_iter().map(SqlValue::Text));
+            match ignore_case {
+                true => format!("{} COLLATE NOCASE IN ({})", column, placeholders),
+                false => format!("{} IN ({})", column, placeholders),
+            }
+        }
+        (TextMatch::Prefix(value), false) => {
+            params.push(SqlValue::Text(value.clone()));
+            format!("instr({}, ?) = 1", column)
+        }
+        (TextMatch::Contains(value), false) => {
+            params.push(SqlValue::Text(value.clone()));
+            format!("instr({}, ?) > 0", column)
+        }
+        (TextMatch::Prefix(value), true) => {
+            params.push(SqlValue::Text(format!("{}%", escape_like(value))));
+            format!("{} LIKE ? ESCAPE '\\'", column)
+        }
+        (TextMatch::Contains(value), true) => {
+            params.push(SqlValue::Text(format!("%{}%", escape_like(value))));
+            format!("{} LIKE ? ESCAPE '\\'", column)
+        }
+    };
+    // languages are also found by the codes and names of the bundled languages, like exact matches
+    let filter = match condition
+        .field
+        .matching_language_codes(&condition.matcher, condition.ignore_case)
+    {
+        Some(codes) if !codes.is_empty() => {
+            let (code_column, _) = condition.field.exact_values(&[]);
+            let placeholders = codes.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
+            params.extend(codes.into_iter().map(SqlValue::Text));
+            format!("({} OR {} IN ({}))", filter, code_column, placeholders)
+        }
+        _ => filter,
+    };
+    format!(
+        "books.id IN (SELECT {} FROM {} WHERE {})",
+        parts.book_id,
+        parts.tables,
+        parts.where_sql(&filter)
+    )
+}
+
+/// parses a comma separated list of sql style quoted values (`'a','b'` or `"a"`).
+/// Quotes are escaped by doubling them, unquoted values are taken as they are (trimmed).
+fn parse_value_list(text: &str) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while let Some(c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let quote = match chars.peek() {
+            None => break,
+            Some('\'') | Some('"') => chars.next(),
+            Some(_) => None,
+        };
+        let mut value = String::new();
+        match quote {
+            Some(q) => {
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == q {
+                        if chars.peek() == Some(&q) {
+                            chars.next();
+                            value.push(q);
+                        } else {
+                            closed = true;
+                            break;
+                        }
+                    } else {
+                        value.push(c);
+                    }
+                }
+                if !closed {
+                    return Err(format!("unterminated quoted value in {}", text));
+                }
+            }
+            None => {
+                while let Some(c) = chars.peek() {
+                    if *c == ',' {
+                        break;
+                    }
+                    value.push(*c);
+                    chars.next();
+                }
+                value = value.trim().to_string();
+            }
+        }
+        values.push(value);
+
+        while let Some(c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        match chars.next() {
+            None => break,
+            Some(',') => continue,
+            Some(c) => return Err(format!("unexpected character '{}' in {}", c, text)),
+        }
+    }
+    Ok(values)
+}
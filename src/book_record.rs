@@ -0,0 +1,438 @@
+use crate::book::Book;
+use crate::fst_parser::ParseResult;
+use crate::fst_parser_type::ParseType;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// All the information we have about a book, with every field resolved to its string value
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BookRecord {
+    pub gutenberg_id: i32,
+    /// every title as written in the rdf, the main titles first and then the alternative ones
+    pub titles: Vec<String>,
+    /// the main title without its subtitle, see `split_title`
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    /// the `dcterms:alternative` titles
+    pub alternative_titles: Vec<String>,
+    pub authors: Vec<String>,
+    pub languages: Vec<String>,
+    /// the subject headings (LCSH)
+    pub subjects: Vec<String>,
+    /// the Library of Congress classification codes (ex: `PR`, `E201`), see `lcc_class_name`
+    pub lcc: Vec<String>,
+    pub bookshelves: Vec<String>,
+    /// contributor names by MARC relator code (ex: `trl` for the translators, see `role_name`)
+    pub contributors: BTreeMap<String, Vec<String>>,
+    pub descriptions: Vec<String>,
+    pub table_of_contents: Vec<String>,
+    /// the `pgterms:marc520` summaries
+    pub summaries: Vec<String>,
+    /// the DCMI type (`Text`, `Sound`, `Image`, `StillImage`...)
+    pub book_type: Option<String>,
+    pub license: Option<String>,
+    /// the values of the custom fields by field key, see `CustomField`
+    pub custom_fields: BTreeMap<String, Vec<String>>,
+    pub rights: Option<String>,
+    pub publisher: Option<String>,
+    pub date_issued: Option<String>,
+    pub num_downloads: i32,
+    /// download links grouped by their mime type
+    pub files: BTreeMap<String, Vec<String>>,
+    /// every download link with its formats, size and modification time
+    pub downloads: Vec<FileRecord>,
+}
+
+/// A downloadable file of a book
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileRecord {
+    pub url: String,
+    /// mime types in rdf order, a zipped html file is `["text/html", "application/zip"]`
+    pub formats: Vec<String>,
+    /// size in bytes
+    pub extent: Option<i64>,
+    /// last modification time (ex: `2021-01-28T05:38:53`)
+    pub modified: Option<String>,
+}
+
+impl FileRecord {
+    /// true if the file is a zip archive of one of the other formats
+    pub fn is_zipped(&self) -> bool {
+        self.formats.iter().any(|x| x == "application/zip")
+    }
+}
+
+/// A person or organization credited on books, with what the catalog knows about them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuthorRecord {
+    /// the gutenberg agent id (`2009/agents/<id>`)
+    pub agent_id: Option<i64>,
+    pub name: String,
+    pub birth_year: Option<i32>,
+    pub death_year: Option<i32>,
+    pub aliases: Vec<String>,
+    pub webpages: Vec<String>,
+    /// the forms of `name`, see `parse_author_name`
+    pub surname: Option<String>,
+    pub given_names: Option<String>,
+    pub fuller_name: Option<String>,
+    pub display_name: String,
+    pub corporate: bool,
+}
+
+/// The parts and reading forms of a catalog author name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuthorName {
+    /// ex: `Whyte-Melville` for "Whyte-Melville, G. J. (George John)"
+    pub surname: Option<String>,
+    /// ex: `G. J.`
+    pub given_names: Option<String>,
+    /// the name with the given names of the parenthetical, ex: `George John Whyte-Melville`
+    pub fuller_name: Option<String>,
+    /// the name in reading order, ex: `G. J. Whyte-Melville`
+    pub display_name: String,
+    /// an organization (ex: "United States. Central Intelligence Agency") rather than a person
+    pub corporate: bool,
+}
+
+/// the words that make a name without a comma the name of an organization
+static CORPORATE_WORDS: [&str; 30] = [
+    "Academy",
+    "Agency",
+    "Association",
+    "Board",
+    "Bureau",
+    "Church",
+    "Club",
+    "Co.",
+    "College",
+    "Commission",
+    "Committee",
+    "Company",
+    "Congress",
+    "Council",
+    "Department",
+    "Foundation",
+    "Government",
+    "Inc.",
+    "Institute",
+    "Institution",
+    "League",
+    "Library",
+    "Ltd.",
+    "Ministry",
+    "Museum",
+    "Office",
+    "Parliament",
+    "Society",
+    "Survey",
+    "University",
+];
+
+/// Parses a catalog author name ("Surname, Given Names (Fuller given names), Suffix") into its parts and reading forms.
+/// Names without a comma are kept as they are, the ones made of dotted parts (ex: "United States. Central
+/// Intelligence Agency") or naming an organization are corporate.
+pub fn parse_author_name(name: &str) -> AuthorName {
+    let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let (base, parenthetical) = match name.find('(') {
+        Some(idx) if name.ends_with(')') => {
+            (name[..idx].trim_end(), Some(&name[idx + 1..name.len() - 1]))
+        }
+        _ => (name.as_str(), None),
+    };
+    let has_comma = base.contains(',');
+    let hierarchical = base.match_indices(". ").any(|(idx, _)| {
+        base[..idx]
+            .rsplit(' ')
+            .next()
+            .map_or(false, |x| x.chars().count() > 3)
+    });
+    let corporate =
+        !has_comma && (hierarchical || base.split(' ').any(|word| CORPORATE_WORDS.contains(&word)));
+    let mut parts = base.split(',').map(|x| x.trim()).filter(|x| !x.is_empty());
+    let (surname, given_names) = match (parts.next(), parts.next()) {
+        (Some(surname), Some(given_names)) if !corporate => (surname, given_names),
+        _ => {
+            return AuthorName {
+                display_name: name.clone(),
+                corporate,
+                ..Default::default()
+            }
+        }
+    };
+    // ", Jr.", ", Baron"...
+    let suffix = parts.map(|x| format!(", {}", x)).collect::<String>();
+    // the parenthetical is the fuller form of initials ("G. J. (George John)") or of the given names
+    // ("John (John Alfred)"), anything else is a qualifier ("Alexandre (père)")
+    let (fuller_given_names, qualifier) = match parenthetical.map(|x| x.trim()) {
+        Some(x) if !x.is_empty() && (given_names.contains('.') || x.starts_with(given_names)) => {
+            (Some(x), String::new())
+        }
+        Some(x) if !x.is_empty() => (None, format!(" ({})", x)),
+        _ => (None, String::new()),
+    };
+    AuthorName {
+        surname: Some(surname.to_string()),
+        given_names: Some(given_names.to_string()),
+        fuller_name: fuller_given_names.map(|x| format!("{} {}{}", x, surname, suffix)),
+        display_name: format!("{} {}{}{}", given_names, surname, suffix, qualifier),
+        corporate,
+    }
+}
+
+/// An agent credited on a book with a role other than author
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ContributorRecord {
+    /// the MARC relator code of the role (ex: `trl`), see `role_name`
+    pub role: String,
+    pub author: AuthorRecord,
+}
+
+/// Returns the name of the top level class of a Library of Congress classification code (ex: `PR` and `E201` are in "Language and Literature" and "History of the Americas")
+pub fn lcc_class_name(code: &str) -> Option<&'static str> {
+    let name = match code.trim().chars().next()?.to_ascii_uppercase() {
+        'A' => "General Works",
+        'B' => "Philosophy, Psychology, Religion",
+        'C' => "Auxiliary Sciences of History",
+        'D' => "World History",
+        'E' | 'F' => "History of the Americas",
+        'G' => "Geography, Anthropology, Recreation",
+        'H' => "Social Sciences",
+        'J' => "Political Science",
+        'K' => "Law",
+        'L' => "Education",
+        'M' => "Music",
+        'N' => "Fine Arts",
+        'P' => "Language and Literature",
+        'Q' => "Science",
+        'R' => "Medicine",
+        'S' => "Agriculture",
+        'T' => "Technology",
+        'U' => "Military Science",
+        'V' => "Naval Science",
+        'Z' => "Bibliography, Library Science",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// the separator of the gutenberg "Title; Or, Subtitle" titles
+static OR_SEPARATOR: &str = "; or,";
+
+/// Splits a title into the title and its subtitle, on the gutenberg conventions: the subtitle is on the next line,
+/// after a `; Or,` (ex: "Frankenstein; Or, The Modern Prometheus") or after the first colon
+pub fn split_title(title: &str) -> (String, Option<String>) {
+    let title = title.trim();
+    let or_idx = title.char_indices().map(|(idx, _)| idx).find(|idx| {
+        title[*idx..]
+            .get(..OR_SEPARATOR.len())
+            .map_or(false, |x| x.eq_ignore_ascii_case(OR_SEPARATOR))
+    });
+    let split = if let Some(idx) = title.find('\n') {
+        Some((&title[..idx], &title[idx + 1..]))
+    } else if let Some(idx) = or_idx {
+        Some((&title[..idx], &title[idx + OR_SEPARATOR.len()..]))
+    } else {
+        title
+            .find(':')
+            .map(|idx| (&title[..idx], &title[idx + 1..]))
+    };
+    let (main, subtitle) = match split {
+        Some(split) => split,
+        None => return (title.to_string(), None),
+    };
+    let main =
+        main.trim_end_matches(|c: char| c.is_whitespace() || c == ':' || c == ';' || c == ',');
+    let subtitle = subtitle.split_whitespace().collect::<Vec<&str>>().join(" ");
+    match (main.is_empty(), subtitle.is_empty()) {
+        (false, false) => (main.to_string(), Some(subtitle)),
+        (false, true) => (main.to_string(), None),
+        _ => (title.to_string(), None),
+    }
+}
+
+/// Returns the name of a MARC relator code (ex: `trl` is "Translator"), for the roles used by gutenberg
+pub fn role_name(code: &str) -> Option<&'static str> {
+    let name = match code {
+        "adp" => "Adapter",
+        "aft" => "Author of afterword, colophon, etc.",
+        "ann" => "Annotator",
+        "arr" => "Arranger",
+        "art" => "Artist",
+        "aui" => "Author of introduction, etc.",
+        "aut" => "Author",
+        "clb" => "Collaborator",
+        "cmm" => "Commentator",
+        "cmp" => "Composer",
+        "cnd" => "Conductor",
+        "com" => "Compiler",
+        "ctb" => "Contributor",
+        "ctg" => "Cartographer",
+        "dub" => "Dubious author",
+        "edc" => "Editor of compilation",
+        "edt" => "Editor",
+        "egr" => "Engraver",
+        "frg" => "Forger",
+        "ill" => "Illustrator",
+        "lbt" => "Librettist",
+        "oth" => "Other",
+        "pbl" => "Publisher",
+        "pht" => "Photographer",
+        "prf" => "Performer",
+        "prt" => "Printer",
+        "res" => "Researcher",
+        "trc" => "Transcriber",
+        "trl" => "Translator",
+        "unk" => "Unknown role",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl BookRecord {
+    /// a hash of every field, the cache stores it to find the books an update changed
+    pub fn content_hash(&self) -> String {
+        // FNV-1a, the std hashers may change between rust releases and the hash outlives the build
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in serde_json::to_vec(self).unwrap_or_default() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// resolves a parsed book against the dictionaries of its parse result
+    pub(crate) fn from_parsed(parse_results: &ParseResult, book: &Book) -> BookRecord {
+        let strings = |parse_type: ParseType, ids: &[usize]| -> Vec<String> {
+            let dictionary = &parse_results.field_dictionaries[parse_type as usize];
+            ids.iter()
+                .filter_map(|id| dictionary.get_index(*id).map(|x| x.0.to_string()))
+                .collect()
+        };
+        // publisher and rights ids are stored as row ids (dictionary index + 1)
+        let row_string = |parse_type: ParseType, id: i32| -> Option<String> {
+            if id < 1 {
+                return None;
+            }
+            parse_results.field_dictionaries[parse_type as usize]
+                .get_index(id as usize - 1)
+                .map(|x| x.0.to_string())
+        };
+
+        let file_type = |id: i32| -> String {
+            match id {
+                id if id > 0 => parse_results
+                    .file_types_dictionary
+                    .get_index(id as usize - 1)
+                    .map(|x| x.0.to_string())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            }
+        };
+
+        let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut downloads = Vec::new();
+        for file in &book.files {
+            let link = parse_results
+                .files_dictionary
+                .get_index(file.file_link_id as usize)
+                .map(|x| x.0.to_string())
+                .unwrap_or_default();
+            files
+                .entry(file_type(file.file_type_id))
+                .or_default()
+                .push(link.clone());
+            downloads.push(FileRecord {
+                url: link,
+                formats: file.file_type_ids.iter().map(|x| file_type(*x)).collect(),
+                extent: file.extent,
+                modified: file.modified.clone(),
+            });
+        }
+
+        let (lcc, headings): (Vec<usize>, Vec<usize>) = book.subject_ids.iter().partition(|id| {
+            parse_results
+                .subject_vocabularies
+                .get(*id)
+                .map_or(false, |x| x == "LCC")
+        });
+
+        let mut contributors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (agent, role) in &book.contributors {
+            if let Some((_, agent)) = parse_results.agents_dictionary.get_index(*agent) {
+                contributors
+                    .entry(role.clone())
+                    .or_default()
+                    .push(agent.name.clone());
+            }
+        }
+
+        let mut custom_fields = BTreeMap::new();
+        for (key, ids) in &book.custom_fields {
+            if let Some(dictionary) = parse_results.custom_dictionaries.get(key) {
+                let values = ids
+                    .iter()
+                    .filter_map(|id| dictionary.get_index(*id).map(|x| x.0.to_string()))
+                    .collect::<Vec<String>>();
+                custom_fields.insert(key.clone(), values);
+            }
+        }
+
+        let main_titles = strings(ParseType::Title, &book.title_ids);
+        let alternative_titles = strings(ParseType::AlternativeTitle, &book.alternative_title_ids);
+        let (title, subtitle) = match main_titles.first() {
+            Some(title) => {
+                let (title, subtitle) = split_title(title);
+                (Some(title), subtitle)
+            }
+            None => (None, None),
+        };
+
+        BookRecord {
+            gutenberg_id: book.gutenberg_book_id as i32,
+            titles: [main_titles, alternative_titles.clone()].concat(),
+            title,
+            subtitle,
+            alternative_titles,
+            authors: book
+                .agent_ids
+                .iter()
+                .filter_map(|id| parse_results.agents_dictionary.get_index(*id))
+                .map(|x| x.1.name.clone())
+                .collect(),
+            languages: strings(ParseType::Language, &book.language_ids),
+            subjects: strings(ParseType::Subject, &headings),
+            lcc: strings(ParseType::Subject, &lcc),
+            bookshelves: strings(ParseType::Bookshelf, &book.bookshelf_ids),
+            contributors,
+            descriptions: strings(ParseType::Description, &book.description_ids),
+            table_of_contents: strings(ParseType::TableOfContents, &book.table_of_contents_ids),
+            summaries: strings(ParseType::Summary, &book.summary_ids),
+            book_type: book.book_type.clone(),
+            license: book.license.clone(),
+            custom_fields,
+            rights: row_string(ParseType::Rights, book.rights_id),
+            publisher: row_string(ParseType::Publisher, book.publisher_id),
+            date_issued: normalize_date(&book.date_issued),
+            num_downloads: book.num_downloads,
+            files,
+            downloads,
+        }
+    }
+}
+
+/// Returns the `YYYY-MM-DD` part of an issue date, None if the text is not a date
+pub(crate) fn normalize_date(text: &str) -> Option<String> {
+    let date = text.trim().get(0..10)?;
+    let bytes = date.as_bytes();
+    for (idx, c) in bytes.iter().enumerate() {
+        let valid = match idx {
+            4 | 7 => *c == b'-',
+            _ => c.is_ascii_digit(),
+        };
+        if !valid {
+            return None;
+        }
+    }
+    Some(date.to_string())
+}
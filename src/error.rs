@@ -5,6 +5,12 @@ use thiserror::Error;
 pub enum Error {
     #[error("Rdf parsing error: `{0}`")]
     InvalidRdf(String),
+    #[error("Rdf parsing error in `{file}` at byte {position}: {reason}")]
+    InvalidRdfFile {
+        file: String,
+        position: usize,
+        reason: String,
+    },
     #[error("Invalid result: `{0}`")]
     InvalidResult(String),
     #[error("Settings error: `{0}`")]
@@ -25,6 +31,10 @@ pub enum Error {
     InvalidUrl(String),
     #[error("Invalid Query: `{0}`")]
     InvalidQuery(String),
+    #[error("Cache schema error: `{0}`")]
+    InvalidCacheSchema(String),
+    #[error("Invalid path: `{0}`")]
+    InvalidPath(String),
 }
 
 impl std::convert::From<url::ParseError> for Error {
@@ -59,7 +69,7 @@ impl std::convert::From<rusqlite::Error> for Error {
 
 impl std::convert::From<quick_xml::Error> for Error {
     fn from(err: quick_xml::Error) -> Self {
-        Error::InvalidRdf(format!("fastxml error: {}", err.to_string()).to_string())
+        Error::InvalidRdf(format!("fastxml error: {}", err).to_string())
     }
 }
 
@@ -71,7 +81,7 @@ impl std::convert::From<std::str::Utf8Error> for Error {
 
 impl std::convert::From<quick_xml::events::attributes::AttrError> for Error {
     fn from(err: quick_xml::events::attributes::AttrError) -> Self {
-        Error::InvalidRdf(format!("fastxml attribute error: {}", err.to_string()).to_string())
+        Error::InvalidRdf(format!("fastxml attribute error: {}", err).to_string())
     }
 }
 
@@ -1,7 +1,12 @@
 use crate::book::{Book, GutenbergFileEntry};
 use crate::error::Error;
+use crate::fst_parser_custom_node::FSTParserCustomNode;
 use crate::fst_parser_type::ParseType;
+use crate::fst_path::FSTPath;
 use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct ParseItemResult {
@@ -26,20 +31,238 @@ impl ParseItemResult {
     }
 }
 
-#[derive(Default)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct DictionaryItemContent {
     pub book_links: Vec<usize>,
 }
 
+/// A person or organization described by a `pgterms:agent` node
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Agent {
+    /// the gutenberg agent id, from `rdf:about="2009/agents/<id>"`
+    pub agent_id: Option<i64>,
+    pub name: String,
+    pub birth_year: Option<i32>,
+    pub death_year: Option<i32>,
+    pub aliases: Vec<String>,
+    pub webpages: Vec<String>,
+    pub book_links: Vec<usize>,
+}
+
+impl Agent {
+    /// parses the id out of an agent reference like `2009/agents/1638`
+    pub fn id_from_about(about: &str) -> Option<i64> {
+        about.rsplit('/').next()?.parse::<i64>().ok()
+    }
+
+    /// the key of the agent in `ParseResult::agents_dictionary`
+    fn key(&self) -> String {
+        match self.agent_id {
+            Some(id) => id.to_string(),
+            None => format!("name:{}", self.name),
+        }
+    }
+
+    /// fills what we don't know yet about this agent from another description of it
+    fn merge(&mut self, other: Agent) {
+        if self.name.is_empty() {
+            self.name = other.name;
+        }
+        if self.birth_year.is_none() {
+            self.birth_year = other.birth_year;
+        }
+        if self.death_year.is_none() {
+            self.death_year = other.death_year;
+        }
+        if self.aliases.is_empty() {
+            self.aliases = other.aliases;
+        }
+        if self.webpages.is_empty() {
+            self.webpages = other.webpages;
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ParseResult {
     pub books: Vec<Book>,
     pub field_dictionaries: Vec<IndexMap<String, DictionaryItemContent>>,
     pub files_dictionary: IndexMap<String, DictionaryItemContent>,
     pub file_types_dictionary: IndexMap<String, DictionaryItemContent>,
+    /// every agent found in the rdfs, merged by agent id
+    pub agents_dictionary: IndexMap<String, Agent>,
+    /// the vocabulary of the subjects (`LCSH` or `LCC`) by subject dictionary index
+    pub subject_vocabularies: HashMap<usize, String>,
+    /// the values of the custom fields (see `CustomField`) by field key
+    pub custom_dictionaries: IndexMap<String, IndexMap<String, DictionaryItemContent>>,
+}
+
+/// The sizes of the `ParseResult` dictionaries, to drop what a failed rdf added
+pub(crate) struct ParseMark {
+    fields: Vec<usize>,
+    files: usize,
+    file_types: usize,
+    agents: usize,
+    custom: Vec<usize>,
 }
 
 impl ParseResult {
+    /// appends the result of parsing the rdfs that come after ours (ex: on another thread),
+    /// the values get the ids they would have had if everything was parsed in one go
+    pub(crate) fn append(&mut self, other: ParseResult) {
+        let book_offset = self.books.len();
+        let relink = |links: Vec<usize>| -> Vec<usize> {
+            links.into_iter().map(|x| x + book_offset).collect()
+        };
+        // every dictionary of `other` is merged in its insertion order, we keep the new index of its values
+        let merge = |map: &mut IndexMap<String, DictionaryItemContent>,
+                     other: IndexMap<String, DictionaryItemContent>|
+         -> Vec<usize> {
+            other
+                .into_iter()
+                .map(|(key, item)| match map.get_full_mut(key.as_str()) {
+                    Some((idx, _, existing)) => {
+                        existing.book_links.extend(relink(item.book_links));
+                        idx
+                    }
+                    None => {
+                        let book_links = relink(item.book_links);
+                        map.insert_full(key, DictionaryItemContent { book_links }).0
+                    }
+                })
+                .collect()
+        };
+        let fields = self
+            .field_dictionaries
+            .iter_mut()
+            .zip(other.field_dictionaries)
+            .map(|(map, other)| merge(map, other))
+            .collect::<Vec<Vec<usize>>>();
+        let files = merge(&mut self.files_dictionary, other.files_dictionary);
+        let file_types = merge(&mut self.file_types_dictionary, other.file_types_dictionary);
+        let agents = other
+            .agents_dictionary
+            .into_iter()
+            .map(|(key, mut agent)| {
+                let book_links = relink(std::mem::take(&mut agent.book_links));
+                match self.agents_dictionary.get_full_mut(key.as_str()) {
+                    Some((idx, _, existing)) => {
+                        existing.merge(agent);
+                        existing.book_links.extend(book_links);
+                        idx
+                    }
+                    None => {
+                        agent.book_links = book_links;
+                        self.agents_dictionary.insert_full(key, agent).0
+                    }
+                }
+            })
+            .collect::<Vec<usize>>();
+        let mut custom = HashMap::new();
+        for (key, dictionary) in other.custom_dictionaries {
+            let map = self.custom_dictionaries.entry(key.clone()).or_default();
+            custom.insert(key, merge(map, dictionary));
+        }
+        for (idx, vocabulary) in other.subject_vocabularies {
+            self.subject_vocabularies
+                .insert(fields[ParseType::Subject as usize][idx], vocabulary);
+        }
+
+        let index = |ids: &[usize], id: i32| -> i32 {
+            match id {
+                id if id >= 0 => ids[id as usize] as i32,
+                _ => id,
+            }
+        };
+        // publisher, rights and file type ids are stored as dictionary index + 1
+        let row = |ids: &[usize], id: i32| -> i32 {
+            match id {
+                id if id > 0 => ids[id as usize - 1] as i32 + 1,
+                _ => id,
+            }
+        };
+        let remap = |ids: &[usize], values: &mut Vec<usize>| {
+            for value in values.iter_mut() {
+                *value = ids[*value];
+            }
+        };
+        for mut book in other.books {
+            let field = |parse_type: ParseType| &fields[parse_type as usize];
+            book.publisher_id = row(field(ParseType::Publisher), book.publisher_id);
+            book.rights_id = row(field(ParseType::Rights), book.rights_id);
+            book.title_id = index(field(ParseType::Title), book.title_id);
+            remap(field(ParseType::Title), &mut book.title_ids);
+            remap(
+                field(ParseType::AlternativeTitle),
+                &mut book.alternative_title_ids,
+            );
+            remap(field(ParseType::Language), &mut book.language_ids);
+            remap(field(ParseType::Subject), &mut book.subject_ids);
+            remap(field(ParseType::Author), &mut book.author_ids);
+            remap(field(ParseType::Bookshelf), &mut book.bookshelf_ids);
+            remap(field(ParseType::Description), &mut book.description_ids);
+            remap(
+                field(ParseType::TableOfContents),
+                &mut book.table_of_contents_ids,
+            );
+            remap(field(ParseType::Summary), &mut book.summary_ids);
+            remap(&agents, &mut book.agent_ids);
+            for (agent, _) in book.contributors.iter_mut() {
+                *agent = agents[*agent];
+            }
+            for (key, values) in book.custom_fields.iter_mut() {
+                remap(&custom[key], values);
+            }
+            for file in book.files.iter_mut() {
+                file.file_link_id = index(&files, file.file_link_id);
+                file.file_type_id = row(&file_types, file.file_type_id);
+                for file_type_id in file.file_type_ids.iter_mut() {
+                    *file_type_id = row(&file_types, *file_type_id);
+                }
+            }
+            self.books.push(book);
+        }
+    }
+
+    pub(crate) fn mark(&self) -> ParseMark {
+        ParseMark {
+            fields: self.field_dictionaries.iter().map(|x| x.len()).collect(),
+            files: self.files_dictionary.len(),
+            file_types: self.file_types_dictionary.len(),
+            agents: self.agents_dictionary.len(),
+            custom: self.custom_dictionaries.values().map(|x| x.len()).collect(),
+        }
+    }
+
+    /// removes the values and book links added by `book_id` since `mark`
+    pub(crate) fn rollback(&mut self, mark: ParseMark, book_id: usize) {
+        let unlink = |map: &mut IndexMap<String, DictionaryItemContent>, len: usize| {
+            map.truncate(len);
+            for (_, item) in map.iter_mut() {
+                while item.book_links.last() == Some(&book_id) {
+                    item.book_links.pop();
+                }
+            }
+        };
+        for (map, len) in self.field_dictionaries.iter_mut().zip(mark.fields) {
+            unlink(map, len);
+        }
+        unlink(&mut self.files_dictionary, mark.files);
+        unlink(&mut self.file_types_dictionary, mark.file_types);
+        self.custom_dictionaries.truncate(mark.custom.len());
+        for (map, len) in self.custom_dictionaries.values_mut().zip(mark.custom) {
+            unlink(map, len);
+        }
+        self.agents_dictionary.truncate(mark.agents);
+        for (_, agent) in self.agents_dictionary.iter_mut() {
+            while agent.book_links.last() == Some(&book_id) {
+                agent.book_links.pop();
+            }
+        }
+        let subjects = self.field_dictionaries[ParseType::Subject as usize].len();
+        self.subject_vocabularies.retain(|idx, _| *idx < subjects);
+    }
+
     pub fn add(
         map: &mut IndexMap<String, DictionaryItemContent>,
         data: String,
@@ -58,11 +281,9 @@ impl ParseResult {
         match map_entry {
             Some(data) => {
                 data.2.book_links.push(book_id as usize);
-                return Ok(data.0);
-            }
-            None => {
-                return Err(Error::InvalidRdf("Book links".to_string()));
+                Ok(data.0)
             }
+            None => Err(Error::InvalidRdf("Book links".to_string())),
         }
     }
 
@@ -74,6 +295,22 @@ impl ParseResult {
         ParseResult::add(&mut self.file_types_dictionary, data, book_id)
     }
 
+    pub fn add_agent(&mut self, agent: Agent, book_id: i32) -> usize {
+        let key = agent.key();
+        match self.agents_dictionary.get_full_mut(key.as_str()) {
+            Some((idx, _, existing)) => {
+                existing.merge(agent);
+                existing.book_links.push(book_id as usize);
+                idx
+            }
+            None => {
+                let mut agent = agent;
+                agent.book_links = vec![book_id as usize];
+                self.agents_dictionary.insert_full(key, agent).0
+            }
+        }
+    }
+
     pub fn add_field(
         &mut self,
         field: ParseType,
@@ -82,6 +319,23 @@ impl ParseResult {
     ) -> Result<usize, Error> {
         ParseResult::add(&mut self.field_dictionaries[field as usize], data, book_id)
     }
+
+    /// adds a value of the custom field `key`, returns its index in `custom_dictionaries[key]`
+    pub fn add_custom_field(
+        &mut self,
+        key: &str,
+        data: String,
+        book_id: i32,
+    ) -> Result<usize, Error> {
+        if !self.custom_dictionaries.contains_key(key) {
+            self.custom_dictionaries
+                .insert(key.to_string(), IndexMap::new());
+        }
+        match self.custom_dictionaries.get_mut(key) {
+            Some(map) => ParseResult::add(map, data, book_id),
+            None => Err(Error::InvalidResult(format!("no custom field {}", key))),
+        }
+    }
 }
 pub trait FSTParser {
     fn text(
@@ -92,6 +346,20 @@ pub trait FSTParser {
     ) -> Result<(), Error>;
     fn reset(&mut self);
     fn start_node(&mut self, text: &str);
+    /// a node starts with all of its attributes, by default `start_node` followed by `attribute` for every attribute
+    fn start_element(
+        &mut self,
+        node_name: &str,
+        attributes: &[(&str, &str)],
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.start_node(node_name);
+        for (name, value) in attributes {
+            self.attribute(name, value, parse_result, book_id)?;
+        }
+        Ok(())
+    }
     fn attribute(
         &mut self,
         attribute_name: &str,
@@ -104,5 +372,84 @@ pub trait FSTParser {
     fn has_results(&self) -> bool;
     fn get_parse_type(&self) -> ParseType;
     fn get_result(&self) -> Result<&ParseItemResult, Error>;
+    /// every dictionary index found by the parser, for parsers that match more than one path
+    fn get_all_results(&self) -> Vec<usize> {
+        match self.get_result() {
+            Ok(result) => result.item_links.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
     fn get_files(&self) -> Result<Vec<GutenbergFileEntry>, Error>;
+    /// the agents found by the parser with the node they were found under, for parsers that collect agents
+    fn get_agents(&self) -> Vec<(String, Agent)> {
+        Vec::new()
+    }
+    /// the key of the custom field the parser fills, its `get_all_results` are indices in
+    /// `ParseResult::custom_dictionaries[key]`
+    fn get_custom_key(&self) -> Option<&str> {
+        None
+    }
+}
+
+type BuildParser = dyn Fn() -> Box<dyn FSTParser> + Send + Sync;
+
+/// A field that is not parsed by this crate, registered in `ParseOptions::custom_fields`.
+/// Its values are stored by key in `ParseResult::custom_dictionaries`, `Book::custom_fields`
+/// and `BookRecord::custom_fields`.
+#[derive(Clone)]
+pub struct CustomField {
+    pub key: String,
+    build: Arc<BuildParser>,
+}
+
+impl CustomField {
+    /// the text of the nodes at `path` (ex: `pgterms:ebook/pgterms:marc010`), the path may hold
+    /// wildcards (`*`), descendant steps (`a//b`), attribute predicates (`a[@attr=value]`)
+    /// and child predicates (`a[b/@attr=value]`)
+    pub fn path(key: &str, path: &str) -> Result<CustomField, Error> {
+        let (key, path) = (key.to_string(), FSTPath::parse(path)?);
+        Ok(CustomField {
+            key: key.clone(),
+            build: Arc::new(move || FSTParserCustomNode::build(&key, path.clone(), None)),
+        })
+    }
+
+    /// the value of `attribute` on the nodes at `path` (ex: `pgterms:ebook/dcterms:license`, `rdf:resource`)
+    pub fn attribute(key: &str, path: &str, attribute: &str) -> Result<CustomField, Error> {
+        let (key, path, attribute) = (
+            key.to_string(),
+            FSTPath::parse(path)?,
+            attribute.to_string(),
+        );
+        Ok(CustomField {
+            key: key.clone(),
+            build: Arc::new(move || {
+                FSTParserCustomNode::build(&key, path.clone(), Some(&attribute))
+            }),
+        })
+    }
+
+    /// a parser of your own, `build` is called once per parsing thread. The parser adds its values with
+    /// `ParseResult::add_custom_field` and returns `key` from `FSTParser::get_custom_key`
+    pub fn parser<F>(key: &str, build: F) -> CustomField
+    where
+        F: Fn() -> Box<dyn FSTParser> + Send + Sync + 'static,
+    {
+        CustomField {
+            key: key.to_string(),
+            build: Arc::new(build),
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn FSTParser> {
+        (self.build)()
+    }
+}
+
+impl fmt::Debug for CustomField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomField")
+            .field("key", &self.key)
+            .finish()
+    }
 }
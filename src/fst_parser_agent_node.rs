@@ -0,0 +1,165 @@
+use crate::book::GutenbergFileEntry;
+use crate::error::Error;
+use crate::fst_parser::{Agent, FSTParser, ParseItemResult, ParseResult};
+use crate::fst_parser_type::ParseType;
+use crate::fst_path::FSTPath;
+
+use std::str;
+
+/// Collects the `pgterms:agent` nodes (or `rdf:resource` references to agents) under a root node.
+/// The root is a path, `marcrel:*` matches every node of that namespace (all the contributor roles)
+pub(crate) struct FSTParserAgentNode {
+    /// the root nodes, the agents right under them and the fields of these agents
+    root: FSTPath,
+    agent: FSTPath,
+    field: FSTPath,
+    /// the name of the root node we are in
+    root_name: String,
+    /// the name of the agent field we are in (`pgterms:name`, `pgterms:webpage`, ...)
+    field_name: String,
+    parse_type: ParseType,
+
+    /// the agents with the root node they were found under
+    agents: Vec<(String, Agent)>,
+}
+
+impl FSTParser for FSTParserAgentNode {
+    fn text(
+        &mut self,
+        text: &str,
+        _parse_result: &mut ParseResult,
+        _book_id: i32,
+    ) -> Result<(), Error> {
+        if !self.field.is_found() {
+            return Ok(());
+        }
+        let agent = match self.agents.last_mut() {
+            Some((_, agent)) => agent,
+            None => return Err(Error::InvalidRdf("No agent".to_string())),
+        };
+        match self.field_name.as_str() {
+            "pgterms:name" => agent.name.push_str(text),
+            "pgterms:birthdate" => agent.birth_year = text.trim().parse::<i32>().ok(),
+            "pgterms:deathdate" => agent.death_year = text.trim().parse::<i32>().ok(),
+            "pgterms:alias" => agent.aliases.push(text.to_string()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.root.reset();
+        self.agent.reset();
+        self.field.reset();
+        self.agents.clear();
+    }
+
+    fn attribute(
+        &mut self,
+        attribute_name: &str,
+        attribute_value: &str,
+        _parse_result: &mut ParseResult,
+        _book_id: i32,
+    ) -> Result<(), Error> {
+        if attribute_name == "rdf:resource" && self.root.is_found() {
+            // <dcterms:creator rdf:resource="2009/agents/1638"/> points to an agent described elsewhere
+            self.agents.push((
+                self.root_name.clone(),
+                Agent {
+                    agent_id: Agent::id_from_about(attribute_value),
+                    ..Default::default()
+                },
+            ));
+        } else if attribute_name == "rdf:about" && self.agent.is_found() {
+            if let Some((_, agent)) = self.agents.last_mut() {
+                agent.agent_id = Agent::id_from_about(attribute_value);
+            }
+        } else if attribute_name == "rdf:resource"
+            && self.field.is_found()
+            && self.field_name == "pgterms:webpage"
+        {
+            if let Some((_, agent)) = self.agents.last_mut() {
+                agent.webpages.push(attribute_value.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn start_node(&mut self, node_name: &str) {
+        self.start(node_name, &[]);
+    }
+
+    fn start_element(
+        &mut self,
+        node_name: &str,
+        attributes: &[(&str, &str)],
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.start(node_name, attributes);
+        for (name, value) in attributes {
+            self.attribute(name, value, parse_result, book_id)?;
+        }
+        Ok(())
+    }
+
+    fn end_node(&mut self, _node_name: &str) {
+        self.root.end();
+        self.agent.end();
+        self.field.end();
+    }
+
+    fn is_found(&self) -> bool {
+        self.root.is_found() || self.agent.is_found() || self.field.is_found()
+    }
+
+    fn has_results(&self) -> bool {
+        !self.agents.is_empty()
+    }
+
+    fn get_parse_type(&self) -> ParseType {
+        self.parse_type
+    }
+
+    fn get_result(&self) -> Result<&ParseItemResult, Error> {
+        Err(Error::InvalidResult(
+            "no result in agent parser".to_string(),
+        ))
+    }
+
+    fn get_files(&self) -> Result<Vec<GutenbergFileEntry>, Error> {
+        Err(Error::InvalidResult("no files".to_string()))
+    }
+
+    fn get_agents(&self) -> Vec<(String, Agent)> {
+        self.agents.clone()
+    }
+}
+
+impl FSTParserAgentNode {
+    pub fn build(root: &'static str, parse_type: ParseType) -> Box<dyn FSTParser> {
+        let parse = |path: &str| FSTPath::parse(path).expect("invalid parser path");
+        Box::new(FSTParserAgentNode {
+            root: parse(root),
+            agent: parse(&format!("{}/pgterms:agent", root)),
+            field: parse(&format!("{}/pgterms:agent/*", root)),
+            root_name: String::new(),
+            field_name: String::new(),
+            parse_type,
+            agents: Vec::new(),
+        })
+    }
+
+    fn start(&mut self, node_name: &str, attributes: &[(&str, &str)]) {
+        self.root.start(node_name, attributes);
+        self.agent.start(node_name, attributes);
+        self.field.start(node_name, attributes);
+        if self.root.is_found() {
+            self.root_name = node_name.to_string();
+        } else if self.agent.is_found() {
+            self.agents.push((self.root_name.clone(), Agent::default()));
+        } else if self.field.is_found() {
+            self.field_name = node_name.to_string();
+        }
+    }
+}
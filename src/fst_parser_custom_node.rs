@@ -0,0 +1,137 @@
+use crate::book::GutenbergFileEntry;
+use crate::error::Error;
+use crate::fst_parser::{FSTParser, ParseItemResult, ParseResult};
+use crate::fst_parser_type::ParseType;
+use crate::fst_path::FSTPath;
+
+/// Parses the values of a `CustomField` like `FSTParserNode`, they go to `ParseResult::custom_dictionaries`
+pub(crate) struct FSTParserCustomNode {
+    key: String,
+    path: FSTPath,
+    result: ParseItemResult,
+    has_result: bool,
+    /// when set the value is read from this attribute of the last node instead of its text
+    attribute: Option<String>,
+}
+
+impl FSTParser for FSTParserCustomNode {
+    fn text(
+        &mut self,
+        text: &str,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        if !(self.is_found() || self.path.is_pending()) || self.attribute.is_some() {
+            return Ok(());
+        }
+        self.value_found(text.to_string(), parse_result, book_id)
+    }
+
+    fn reset(&mut self) {
+        self.has_result = false;
+        self.path.reset();
+        self.result.reset();
+    }
+
+    fn attribute(
+        &mut self,
+        attribute_name: &str,
+        attribute_value: &str,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        if !(self.is_found() || self.path.is_pending())
+            || self.attribute.as_deref() != Some(attribute_name)
+        {
+            return Ok(());
+        }
+        self.value_found(attribute_value.to_string(), parse_result, book_id)
+    }
+
+    fn start_node(&mut self, node_name: &str) {
+        self.path.start(node_name, &[]);
+    }
+
+    fn start_element(
+        &mut self,
+        node_name: &str,
+        attributes: &[(&str, &str)],
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.path.start(node_name, attributes);
+        for value in self.path.take_confirmed() {
+            self.add_value(value, parse_result, book_id)?;
+        }
+        for (name, value) in attributes {
+            self.attribute(name, value, parse_result, book_id)?;
+        }
+        Ok(())
+    }
+
+    fn end_node(&mut self, _node_name: &str) {
+        self.path.end();
+    }
+
+    fn is_found(&self) -> bool {
+        self.path.is_found()
+    }
+
+    fn has_results(&self) -> bool {
+        self.has_result
+    }
+
+    fn get_parse_type(&self) -> ParseType {
+        ParseType::Custom
+    }
+
+    fn get_result(&self) -> Result<&ParseItemResult, Error> {
+        Ok(&self.result)
+    }
+
+    fn get_files(&self) -> Result<Vec<GutenbergFileEntry>, Error> {
+        Err(Error::InvalidResult("no results".to_string()))
+    }
+
+    fn get_custom_key(&self) -> Option<&str> {
+        Some(&self.key)
+    }
+}
+
+impl FSTParserCustomNode {
+    /// a value found before the child predicates of its path are met waits for them
+    fn value_found(
+        &mut self,
+        value: String,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        if !self.is_found() {
+            self.path.defer(value);
+            return Ok(());
+        }
+        self.add_value(value, parse_result, book_id)
+    }
+
+    fn add_value(
+        &mut self,
+        value: String,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.has_result = true;
+        let idx = parse_result.add_custom_field(&self.key, value, book_id)?;
+        self.result.item_links.push(idx);
+        Ok(())
+    }
+
+    pub fn build(key: &str, path: FSTPath, attribute: Option<&str>) -> Box<dyn FSTParser> {
+        Box::new(FSTParserCustomNode {
+            key: key.to_string(),
+            path,
+            result: Default::default(),
+            has_result: false,
+            attribute: attribute.map(String::from),
+        })
+    }
+}
@@ -2,16 +2,22 @@ use crate::book::GutenbergFileEntry;
 use crate::error::Error;
 use crate::fst_parser::{FSTParser, ParseItemResult, ParseResult};
 use crate::fst_parser_type::ParseType;
+use crate::fst_path::FSTPath;
 
 use std::str;
 
 pub(crate) struct FSTParserFileNode {
-    pos: i32,
-    attribute_states_idx: i32,
-    states: Vec<String>,
+    /// the file nodes, their link is in `attribute`
+    file: FSTPath,
+    /// the mime types of a file
+    format: FSTPath,
+    /// the metadata nodes of a file (`dcterms:extent`, `dcterms:modified`)
+    metadata: FSTPath,
     attribute: &'static str,
     has_node: bool,
     parse_type: ParseType,
+    /// the metadata node of the current file we are in
+    file_field: Option<String>,
 
     files: Vec<GutenbergFileEntry>,
 }
@@ -23,13 +29,26 @@ impl FSTParser for FSTParserFileNode {
         parse_result: &mut ParseResult,
         book_id: i32,
     ) -> Result<(), Error> {
+        if self.metadata.is_found() {
+            if let (Some(field), Some(last_file)) = (&self.file_field, self.files.last_mut()) {
+                match field.as_str() {
+                    "dcterms:extent" => last_file.extent = text.trim().parse::<i64>().ok(),
+                    _ => last_file.modified = Some(text.trim().to_string()),
+                }
+            }
+            return Ok(());
+        }
         if !self.is_found() {
             return Ok(());
         }
         self.has_node = true;
         let idx = parse_result.add_file_type(text.to_string(), book_id)?;
         if let Some(last_file) = self.files.last_mut() {
-            last_file.file_type_id = (idx + 1) as i32;
+            let file_type_id = (idx + 1) as i32;
+            if last_file.file_type_ids.is_empty() {
+                last_file.file_type_id = file_type_id;
+            }
+            last_file.file_type_ids.push(file_type_id);
             return Ok(());
         }
         Err(Error::InvalidRdf("No files".to_string()))
@@ -37,7 +56,10 @@ impl FSTParser for FSTParserFileNode {
 
     fn reset(&mut self) {
         self.has_node = false;
-        self.pos = -1;
+        self.file.reset();
+        self.format.reset();
+        self.metadata.reset();
+        self.file_field = None;
         self.files.clear();
     }
 
@@ -48,11 +70,7 @@ impl FSTParser for FSTParserFileNode {
         parse_result: &mut ParseResult,
         book_id: i32,
     ) -> Result<(), Error> {
-        if self.attribute_states_idx != self.pos {
-            return Ok(());
-        }
-
-        if attribute_name != self.attribute {
+        if !self.file.is_found() || attribute_name != self.attribute {
             return Ok(());
         }
 
@@ -62,41 +80,43 @@ impl FSTParser for FSTParserFileNode {
         self.files.push(GutenbergFileEntry {
             file_link_id: file_link_id as i32,
             file_type_id: -1,
+            file_type_ids: Vec::new(),
+            extent: None,
+            modified: None,
         });
         Ok(())
     }
 
     fn start_node(&mut self, node_name: &str) {
-        if self.pos == -1 && node_name == self.states[0] {
-            self.pos = 0;
-            return;
-        }
+        self.start(node_name, &[]);
+    }
 
-        if self.pos != -1 {
-            let check_index = self.pos + 1;
-            if check_index >= self.states.len() as i32 {
-                return;
-            }
-            if node_name == self.states[check_index as usize] {
-                self.pos += 1;
-            }
+    fn start_element(
+        &mut self,
+        node_name: &str,
+        attributes: &[(&str, &str)],
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.start(node_name, attributes);
+        for (name, value) in attributes {
+            self.attribute(name, value, parse_result, book_id)?;
         }
+        Ok(())
     }
 
-    fn end_node(&mut self, node_name: &str) {
-        if self.pos > -1 {
-            if self.states[self.pos as usize] == node_name {
-                self.pos -= 1;
-            }
-        }
+    fn end_node(&mut self, _node_name: &str) {
+        self.file.end();
+        self.format.end();
+        self.metadata.end();
     }
 
     fn is_found(&self) -> bool {
-        self.pos == self.states.len() as i32 - 1
+        self.format.is_found()
     }
 
     fn has_results(&self) -> bool {
-        return self.has_node && !self.files.is_empty();
+        self.has_node && !self.files.is_empty()
     }
 
     fn get_parse_type(&self) -> ParseType {
@@ -113,20 +133,37 @@ impl FSTParser for FSTParserFileNode {
 }
 
 impl FSTParserFileNode {
+    /// `path` goes down to the file nodes (`dcterms:hasFormat/pgterms:file`), their link is read from `attribute`
     pub fn build(
         path: &'static str,
         attribute: &'static str,
         parse_type: ParseType,
     ) -> Box<dyn FSTParser> {
-        let states: Vec<String> = path.split("/").map(|s| String::from(s)).collect();
+        let parse = |path: &str| FSTPath::parse(path).expect("invalid parser path");
         Box::new(FSTParserFileNode {
-            pos: -1,
-            states,
+            file: parse(path),
+            format: parse(&format!(
+                "{}/dcterms:format/rdf:Description/rdf:value",
+                path
+            )),
+            metadata: parse(&format!(
+                "{}/dcterms:extent|{}/dcterms:modified",
+                path, path
+            )),
             has_node: false,
             parse_type,
             attribute,
-            attribute_states_idx: 1,
+            file_field: None,
             files: Vec::new(),
         })
     }
+
+    fn start(&mut self, node_name: &str, attributes: &[(&str, &str)]) {
+        self.file.start(node_name, attributes);
+        self.format.start(node_name, attributes);
+        self.metadata.start(node_name, attributes);
+        if self.metadata.is_found() {
+            self.file_field = Some(node_name.to_string());
+        }
+    }
 }
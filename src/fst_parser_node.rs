@@ -2,15 +2,18 @@ use crate::book::GutenbergFileEntry;
 use crate::error::Error;
 use crate::fst_parser::{FSTParser, ParseItemResult, ParseResult};
 use crate::fst_parser_type::ParseType;
+use crate::fst_path::FSTPath;
+use crate::language::normalize_language_tag;
 
 use std::str;
 
 pub struct FSTParserNode {
-    pub pos: i32,
-    pub states: Vec<String>,
+    pub path: FSTPath,
     pub result: ParseItemResult,
     pub has_result: bool,
     pub parse_type: ParseType,
+    /// when set the value is read from this attribute of the last node instead of its text
+    pub attribute: Option<&'static str>,
 }
 
 impl FSTParser for FSTParserNode {
@@ -20,62 +23,68 @@ impl FSTParser for FSTParserNode {
         parse_result: &mut ParseResult,
         book_id: i32,
     ) -> Result<(), Error> {
-        if !self.is_found() {
+        if !(self.is_found() || self.path.is_pending()) || self.attribute.is_some() {
             return Ok(());
         }
-        self.has_result = true;
 
-        self.result
-            .add(parse_result, self.parse_type, text.to_string(), book_id)?;
-        Ok(())
+        // the languages are kept as BCP 47 tags whatever form the rdf has them in
+        let value = match self.parse_type {
+            ParseType::Language => normalize_language_tag(text),
+            _ => text.to_string(),
+        };
+        self.value_found(value, parse_result, book_id)
     }
 
     fn reset(&mut self) {
         self.has_result = false;
-        self.pos = -1;
+        self.path.reset();
         self.result.reset();
     }
 
     fn attribute(
         &mut self,
-        _attribute_name: &str,
-        _attribute_value: &str,
-        _parse_result: &mut ParseResult,
-        _book_id: i32,
+        attribute_name: &str,
+        attribute_value: &str,
+        parse_result: &mut ParseResult,
+        book_id: i32,
     ) -> Result<(), Error> {
-        Ok(())
+        if !(self.is_found() || self.path.is_pending()) || self.attribute != Some(attribute_name) {
+            return Ok(());
+        }
+        self.value_found(attribute_value.to_string(), parse_result, book_id)
     }
 
     fn start_node(&mut self, node_name: &str) {
-        if self.pos == -1 && node_name == self.states[0] {
-            self.pos = 0;
-            return;
+        self.path.start(node_name, &[]);
+    }
+
+    fn start_element(
+        &mut self,
+        node_name: &str,
+        attributes: &[(&str, &str)],
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.path.start(node_name, attributes);
+        for value in self.path.take_confirmed() {
+            self.add_value(value, parse_result, book_id)?;
         }
-        if self.pos != -1 {
-            let check_index = self.pos + 1;
-            if check_index >= self.states.len() as i32 {
-                return;
-            }
-            if node_name == self.states[check_index as usize] {
-                self.pos += 1;
-            }
+        for (name, value) in attributes {
+            self.attribute(name, value, parse_result, book_id)?;
         }
+        Ok(())
     }
 
-    fn end_node(&mut self, node_name: &str) {
-        if self.pos > -1 {
-            if self.states[self.pos as usize].eq(node_name) {
-                self.pos -= 1;
-            }
-        }
+    fn end_node(&mut self, _node_name: &str) {
+        self.path.end();
     }
 
     fn is_found(&self) -> bool {
-        self.pos == self.states.len() as i32 - 1
+        self.path.is_found()
     }
 
     fn has_results(&self) -> bool {
-        return self.has_result;
+        self.has_result
     }
     fn get_parse_type(&self) -> ParseType {
         self.parse_type
@@ -91,14 +100,56 @@ impl FSTParser for FSTParserNode {
 }
 
 impl FSTParserNode {
-    pub fn build(path: &'static str, parse_type: ParseType) -> Box<dyn FSTParser> {
-        let states: Vec<String> = path.split("/").map(|s| String::from(s)).collect();
-        Box::new(FSTParserNode {
-            pos: -1,
-            states,
+    /// `path` is one of our own paths (see `FSTPath` for the syntax), an invalid one is a bug
+    pub fn new(
+        path: &'static str,
+        attribute: Option<&'static str>,
+        parse_type: ParseType,
+    ) -> FSTParserNode {
+        FSTParserNode {
+            path: FSTPath::parse(path).expect("invalid parser path"),
             has_result: false,
             parse_type,
             result: Default::default(),
-        })
+            attribute,
+        }
+    }
+
+    /// a value found before the child predicates of its path are met waits for them
+    fn value_found(
+        &mut self,
+        value: String,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        if !self.is_found() {
+            self.path.defer(value);
+            return Ok(());
+        }
+        self.add_value(value, parse_result, book_id)
+    }
+
+    fn add_value(
+        &mut self,
+        value: String,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        self.has_result = true;
+        self.result
+            .add(parse_result, self.parse_type, value, book_id)
+    }
+
+    pub fn build(path: &'static str, parse_type: ParseType) -> Box<dyn FSTParser> {
+        Box::new(FSTParserNode::new(path, None, parse_type))
+    }
+
+    /// like `build`, for values held in an attribute (ex: `<dcterms:license rdf:resource="license"/>`)
+    pub fn build_attribute(
+        path: &'static str,
+        attribute: &'static str,
+        parse_type: ParseType,
+    ) -> Box<dyn FSTParser> {
+        Box::new(FSTParserNode::new(path, Some(attribute), parse_type))
     }
 }
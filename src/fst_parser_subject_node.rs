@@ -0,0 +1,141 @@
+use crate::book::GutenbergFileEntry;
+use crate::error::Error;
+use crate::fst_parser::{FSTParser, ParseItemResult, ParseResult};
+use crate::fst_parser_type::ParseType;
+use crate::fst_path::FSTPath;
+
+/// Parses the subjects like `FSTParserNode` and records the vocabulary (LCSH or LCC) of each of them.
+/// Every vocabulary has a path to its values, with a predicate on the `dcam:memberOf` child of the description
+/// (`rdf:Description[dcam:memberOf/@rdf:resource='http://purl.org/dc/terms/LCC']/rdf:value`).
+pub(crate) struct FSTParserSubjectNode {
+    path: FSTPath,
+    /// the vocabularies with the path to their values, a subject found before its `dcam:memberOf` waits for it
+    vocabularies: Vec<(&'static str, FSTPath<usize>)>,
+    result: ParseItemResult,
+    has_result: bool,
+    parse_type: ParseType,
+}
+
+impl FSTParser for FSTParserSubjectNode {
+    fn text(
+        &mut self,
+        text: &str,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        if !self.is_found() {
+            return Ok(());
+        }
+        self.has_result = true;
+        let idx = parse_result.add_field(self.parse_type, text.to_string(), book_id)?;
+        self.result.item_links.push(idx);
+        for (vocabulary, path) in &mut self.vocabularies {
+            if path.is_found() {
+                parse_result
+                    .subject_vocabularies
+                    .insert(idx, vocabulary.to_string());
+            } else if path.is_pending() {
+                path.defer(idx);
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.has_result = false;
+        self.path.reset();
+        for (_, path) in &mut self.vocabularies {
+            path.reset();
+        }
+        self.result.reset();
+    }
+
+    fn attribute(
+        &mut self,
+        _attribute_name: &str,
+        _attribute_value: &str,
+        _parse_result: &mut ParseResult,
+        _book_id: i32,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn start_node(&mut self, node_name: &str) {
+        self.path.start(node_name, &[]);
+        for (_, path) in &mut self.vocabularies {
+            path.start(node_name, &[]);
+        }
+    }
+
+    fn start_element(
+        &mut self,
+        node_name: &str,
+        attributes: &[(&str, &str)],
+        parse_result: &mut ParseResult,
+        _book_id: i32,
+    ) -> Result<(), Error> {
+        self.path.start(node_name, attributes);
+        for (vocabulary, path) in &mut self.vocabularies {
+            path.start(node_name, attributes);
+            for idx in path.take_confirmed() {
+                parse_result
+                    .subject_vocabularies
+                    .insert(idx, vocabulary.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn end_node(&mut self, _node_name: &str) {
+        self.path.end();
+        for (_, path) in &mut self.vocabularies {
+            path.end();
+        }
+    }
+
+    fn is_found(&self) -> bool {
+        self.path.is_found()
+    }
+
+    fn has_results(&self) -> bool {
+        self.has_result
+    }
+
+    fn get_parse_type(&self) -> ParseType {
+        self.parse_type
+    }
+
+    fn get_result(&self) -> Result<&ParseItemResult, Error> {
+        Ok(&self.result)
+    }
+
+    fn get_files(&self) -> Result<Vec<GutenbergFileEntry>, Error> {
+        Err(Error::InvalidResult("no results".to_string()))
+    }
+}
+
+impl FSTParserSubjectNode {
+    /// `path` goes down to the value of any subject (`dcterms:subject/rdf:Description/rdf:value`),
+    /// `vocabularies` pairs every vocabulary with the path to the values of its subjects
+    pub fn build(
+        path: &'static str,
+        vocabularies: &[(&'static str, &'static str)],
+        parse_type: ParseType,
+    ) -> Box<dyn FSTParser> {
+        Box::new(FSTParserSubjectNode {
+            path: FSTPath::parse(path).expect("invalid parser path"),
+            vocabularies: vocabularies
+                .iter()
+                .map(|(vocabulary, path)| {
+                    (
+                        *vocabulary,
+                        FSTPath::parse(path).expect("invalid parser path"),
+                    )
+                })
+                .collect(),
+            result: Default::default(),
+            has_result: false,
+            parse_type,
+        })
+    }
+}
@@ -1,3 +1,5 @@
+// num-derive 0.3 puts the generated impls in a const block
+#![allow(non_local_definitions)]
 use num_derive::FromPrimitive;
 use std::fmt;
 
@@ -13,6 +15,22 @@ pub enum ParseType {
     Rights,
     DateIssued,
     Downloads,
+    Description,
+    TableOfContents,
+    /// the `pgterms:marc520` summary
+    Summary,
+    /// the DCMI type of the book (`Text`, `Sound`, `Image`, `StillImage`...)
+    Type,
+    License,
+    /// `dcterms:alternative`, the titles other than the main title
+    AlternativeTitle,
+    /// a field registered with `CustomField`, it has no field dictionary
+    Custom,
+}
+
+impl ParseType {
+    /// the number of parse types, `ParseResult` has a field dictionary for each of them
+    pub const COUNT: usize = ParseType::AlternativeTitle as usize + 1;
 }
 
 impl fmt::Display for ParseType {
@@ -28,6 +46,13 @@ impl fmt::Display for ParseType {
             ParseType::Rights => write!(f, "Rights"),
             ParseType::DateIssued => write!(f, "DateIssued"),
             ParseType::Downloads => write!(f, "Downloads"),
+            ParseType::Description => write!(f, "Description"),
+            ParseType::TableOfContents => write!(f, "TableOfContents"),
+            ParseType::Summary => write!(f, "Summary"),
+            ParseType::Type => write!(f, "Type"),
+            ParseType::License => write!(f, "License"),
+            ParseType::AlternativeTitle => write!(f, "AlternativeTitle"),
+            ParseType::Custom => write!(f, "Custom"),
         }
     }
 }
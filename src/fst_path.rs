@@ -0,0 +1,395 @@
+use crate::error::Error;
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// the number of bits of the step masks
+const MAX_STEPS: usize = 64;
+
+/// A path to the nodes holding a value, like `dcterms:subject/rdf:Description/rdf:value`:
+///
+/// - `a/b` matches the `b` nodes right under an `a` node, `a//b` the `b` nodes anywhere under it
+///   (the matcher of older versions read `a/b` like `a//b`)
+/// - `*` matches any node (ex: `dcterms:type/*/rdf:value`) and `ns:*` any node of a namespace (ex: `marcrel:*`)
+/// - `a[@attr]` matches the `a` nodes with the attribute and `a[@attr=value]` the ones where it has
+///   this value, the value may be quoted with `'` or `"` (ex: `dcterms:issued[@rdf:datatype='http://www.w3.org/2001/XMLSchema#date']`)
+/// - `a[b]`, `a[b/@attr]` and `a[b/@attr=value]` match the `a` nodes with such a `b` child
+///   (ex: `rdf:Description[dcam:memberOf/@rdf:resource='http://purl.org/dc/terms/LCC']/rdf:value`)
+/// - `a/b|c/d` matches the nodes of either path
+/// - the first node of a path may be anywhere in the rdf
+///
+/// The child of a predicate may come after the values under its parent, a value found before it is
+/// `defer`red and comes back from `take_confirmed` once the child is there.
+#[derive(Debug, Clone)]
+pub(crate) struct FSTPath<T = String> {
+    /// the steps of all the alternatives, one after the other
+    steps: Vec<Step>,
+    /// the steps preceded by `//`, one bit per step
+    descendant_steps: u64,
+    /// the first and the last step of every alternative, one bit per step
+    first_steps: u64,
+    last_steps: u64,
+    /// the steps with child predicates, one bit per step
+    child_steps: u64,
+    /// the state of every open node
+    stack: Vec<Frame>,
+    /// the values found while a child predicate was not met yet
+    deferred: Vec<Deferred<T>>,
+    /// the deferred values whose predicates have been met since
+    confirmed: Vec<T>,
+}
+
+#[derive(Debug, Clone)]
+enum NodeName {
+    /// `*`
+    Any,
+    /// `ns:*`, with the `ns:` prefix
+    Namespace(String),
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    name: NodeName,
+    /// the attributes the node must have, with their value when one is given
+    predicates: Vec<(String, Option<String>)>,
+    /// the children the node must have, their bits in `Frame::satisfied`
+    children: Vec<Step>,
+    children_mask: u64,
+}
+
+/// the state of an open node, one bit per step:
+/// the steps it matches, the `//` steps that may match any node under it, and the same two
+/// for the steps whose child predicates (and the ones of the steps before them) are met
+#[derive(Debug, Clone, Copy, Default)]
+struct Frame {
+    matched: u64,
+    waiting: u64,
+    confirmed: u64,
+    waiting_confirmed: u64,
+    /// the child predicates met by the children of the node so far, one bit per predicate
+    satisfied: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Deferred<T> {
+    /// the stack when the value was found, the nodes from `open` on have been closed since
+    stack: Vec<Frame>,
+    open: usize,
+    value: T,
+}
+
+impl Step {
+    fn matches(&self, node_name: &str, attributes: &[(&str, &str)]) -> bool {
+        let name_matches = match &self.name {
+            NodeName::Any => true,
+            NodeName::Namespace(prefix) => node_name.starts_with(prefix.as_str()),
+            NodeName::Name(name) => name == node_name,
+        };
+        if !name_matches {
+            return false;
+        }
+        self.predicates.iter().all(|(name, value)| {
+            attributes
+                .iter()
+                .any(|(x, y)| x == name && value.as_ref().map_or(true, |value| value == y))
+        })
+    }
+}
+
+impl NodeName {
+    fn parse(name: String) -> NodeName {
+        match name.strip_suffix('*') {
+            Some("") => NodeName::Any,
+            Some(prefix) if prefix.ends_with(':') => NodeName::Namespace(prefix.to_string()),
+            _ => NodeName::Name(name),
+        }
+    }
+}
+
+/// `@attr` or `@attr=value` up to the end of a predicate, the `@` already read
+fn parse_attribute(
+    chars: &mut Peekable<Chars>,
+    invalid: &dyn Fn(&str) -> Error,
+) -> Result<(String, Option<String>), Error> {
+    let mut attribute = String::new();
+    while let Some(c) = chars.next_if(|c| *c != '=' && *c != ']') {
+        attribute.push(c);
+    }
+    if attribute.is_empty() {
+        return Err(invalid("empty attribute name"));
+    }
+    if chars.next_if_eq(&'=').is_none() {
+        return Ok((attribute, None));
+    }
+    let mut text = String::new();
+    match chars.next_if(|c| *c == '\'' || *c == '"') {
+        Some(quote) => loop {
+            match chars.next() {
+                Some(c) if c == quote => break,
+                Some(c) => text.push(c),
+                None => return Err(invalid("unterminated quote")),
+            }
+        },
+        None => {
+            while let Some(c) = chars.next_if(|c| *c != ']') {
+                text.push(c);
+            }
+        }
+    }
+    Ok((attribute, Some(text)))
+}
+
+impl<T> FSTPath<T> {
+    pub fn parse(path: &str) -> Result<FSTPath<T>, Error> {
+        let invalid = |reason: &str| Error::InvalidPath(format!("{} in `{}`", reason, path));
+        let mut steps = Vec::new();
+        let mut descendant_steps = 0;
+        let mut first_steps = 1;
+        let mut last_steps = 0;
+        let mut child_steps = 0;
+        let mut child_count = 0;
+        let mut chars = path.trim_start_matches('/').chars().peekable();
+        loop {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| *c != '/' && *c != '[' && *c != '|') {
+                name.push(c);
+            }
+            let name = name.trim().to_string();
+            if name.is_empty() {
+                return Err(invalid("empty node name"));
+            }
+            let mut predicates = Vec::new();
+            let mut children = Vec::new();
+            while chars.next_if_eq(&'[').is_some() {
+                if chars.next_if_eq(&'@').is_some() {
+                    predicates.push(parse_attribute(&mut chars, &invalid)?);
+                } else {
+                    let mut child = String::new();
+                    while let Some(c) = chars.next_if(|c| !"/]=[".contains(*c)) {
+                        child.push(c);
+                    }
+                    let child = child.trim().to_string();
+                    if child.is_empty() {
+                        return Err(invalid("empty node name"));
+                    }
+                    let mut child_predicates = Vec::new();
+                    if chars.next_if_eq(&'/').is_some() {
+                        if chars.next() != Some('@') {
+                            return Err(invalid("child predicates end with an attribute"));
+                        }
+                        child_predicates.push(parse_attribute(&mut chars, &invalid)?);
+                    }
+                    children.push(Step {
+                        name: NodeName::parse(child),
+                        predicates: child_predicates,
+                        children: Vec::new(),
+                        children_mask: 0,
+                    });
+                }
+                if chars.next() != Some(']') {
+                    return Err(invalid("unterminated predicate"));
+                }
+            }
+            if child_count + children.len() > MAX_STEPS {
+                return Err(invalid("too many child predicates"));
+            }
+            let mut children_mask = 0;
+            for _ in &children {
+                children_mask |= 1 << child_count;
+                child_count += 1;
+            }
+            if !children.is_empty() {
+                child_steps |= 1 << steps.len().min(MAX_STEPS - 1);
+            }
+            steps.push(Step {
+                name: NodeName::parse(name),
+                predicates,
+                children,
+                children_mask,
+            });
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next() {
+                None => break,
+                Some('/') => {
+                    if chars.next_if_eq(&'/').is_some() {
+                        descendant_steps |= 1 << steps.len().min(MAX_STEPS - 1);
+                    }
+                }
+                Some('|') => {
+                    last_steps |= 1 << (steps.len() - 1).min(MAX_STEPS - 1);
+                    first_steps |= 1 << steps.len().min(MAX_STEPS - 1);
+                    while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+                }
+                Some(c) => return Err(invalid(&format!("unexpected `{}`", c))),
+            }
+        }
+        if steps.len() > MAX_STEPS {
+            return Err(invalid("too many nodes"));
+        }
+        last_steps |= 1 << (steps.len() - 1);
+        Ok(FSTPath {
+            steps,
+            descendant_steps,
+            first_steps,
+            last_steps,
+            child_steps,
+            stack: Vec::with_capacity(16),
+            deferred: Vec::new(),
+            confirmed: Vec::new(),
+        })
+    }
+
+    /// a node starts, `attributes` are all of its attributes
+    pub fn start(&mut self, node_name: &str, attributes: &[(&str, &str)]) {
+        if let Some(depth) = self.stack.len().checked_sub(1) {
+            self.start_child(depth, node_name, attributes);
+        }
+        let parent = self.stack.last().copied().unwrap_or_default();
+        let all_steps = match self.steps.len() {
+            MAX_STEPS => u64::MAX,
+            len => (1 << len) - 1,
+        };
+        // the first steps may match anywhere, a last step does not lead to the next alternative
+        let candidates =
+            (self.first_steps | ((parent.matched & !self.last_steps) << 1) | parent.waiting)
+                & all_steps;
+        let mut matched = 0;
+        for (idx, step) in self.steps.iter().enumerate() {
+            if candidates & (1 << idx) != 0 && step.matches(node_name, attributes) {
+                matched |= 1 << idx;
+            }
+        }
+        let mut frame = Frame {
+            matched,
+            waiting: parent.waiting | (((matched & !self.last_steps) << 1) & self.descendant_steps),
+            ..Default::default()
+        };
+        self.confirm(&parent, &mut frame);
+        self.stack.push(frame);
+    }
+
+    pub fn end(&mut self) {
+        self.stack.pop();
+        if self.deferred.is_empty() {
+            return;
+        }
+        // a deferred value is dropped once no open node waits for a child anymore
+        let depth = self.stack.len();
+        for mut deferred in std::mem::take(&mut self.deferred) {
+            deferred.open = deferred.open.min(depth);
+            if self.stack[..deferred.open]
+                .iter()
+                .any(|x| self.unsatisfied(x) != 0)
+            {
+                self.deferred.push(deferred);
+            }
+        }
+    }
+
+    /// true while we are in a node matched by a whole path
+    pub fn is_found(&self) -> bool {
+        self.stack
+            .last()
+            .map_or(false, |x| x.confirmed & self.last_steps != 0)
+    }
+
+    /// true while we are in a node matched by a whole path but for child predicates not met yet,
+    /// its values are to be `defer`red
+    pub fn is_pending(&self) -> bool {
+        self.stack.last().map_or(false, |x| {
+            x.matched & self.last_steps != 0 && x.confirmed & self.last_steps == 0
+        })
+    }
+
+    pub fn defer(&mut self, value: T) {
+        self.deferred.push(Deferred {
+            stack: self.stack.clone(),
+            open: self.stack.len(),
+            value,
+        });
+    }
+
+    /// the deferred values whose child predicates have been met, in the order they were confirmed
+    pub fn take_confirmed(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.confirmed)
+    }
+
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.deferred.clear();
+        self.confirmed.clear();
+    }
+
+    /// the node starting under the node at `depth` may meet one of its child predicates
+    fn start_child(&mut self, depth: usize, node_name: &str, attributes: &[(&str, &str)]) {
+        let frame = self.stack[depth];
+        let mut satisfied = frame.satisfied;
+        let mut steps = frame.matched & self.child_steps;
+        while steps != 0 {
+            let step = &self.steps[steps.trailing_zeros() as usize];
+            let first_child = step.children_mask.trailing_zeros();
+            for (idx, child) in step.children.iter().enumerate() {
+                if child.matches(node_name, attributes) {
+                    satisfied |= 1 << (first_child as usize + idx);
+                }
+            }
+            steps &= steps - 1;
+        }
+        if satisfied == frame.satisfied {
+            return;
+        }
+        let parent = match depth {
+            0 => Frame::default(),
+            _ => self.stack[depth - 1],
+        };
+        let mut frame = Frame { satisfied, ..frame };
+        self.confirm(&parent, &mut frame);
+        self.stack[depth] = frame;
+        for deferred in std::mem::take(&mut self.deferred) {
+            match deferred.open > depth && self.is_confirmed(&deferred) {
+                true => self.confirmed.push(deferred.value),
+                false => self.deferred.push(deferred),
+            }
+        }
+    }
+
+    /// the matched steps of `frame` whose child predicates are not all met
+    fn unsatisfied(&self, frame: &Frame) -> u64 {
+        let mut steps = frame.matched & self.child_steps;
+        let mut unsatisfied = 0;
+        while steps != 0 {
+            let idx = steps.trailing_zeros() as usize;
+            let mask = self.steps[idx].children_mask;
+            if frame.satisfied & mask != mask {
+                unsatisfied |= 1 << idx;
+            }
+            steps &= steps - 1;
+        }
+        unsatisfied
+    }
+
+    /// sets the confirmed steps of `frame` from the ones of its parent
+    fn confirm(&self, parent: &Frame, frame: &mut Frame) {
+        let candidates = self.first_steps
+            | ((parent.confirmed & !self.last_steps) << 1)
+            | parent.waiting_confirmed;
+        frame.confirmed = frame.matched & candidates & !self.unsatisfied(frame);
+        frame.waiting_confirmed = parent.waiting_confirmed
+            | (((frame.confirmed & !self.last_steps) << 1) & self.descendant_steps);
+    }
+
+    /// true when a deferred value is matched now, with the nodes still open in their current state
+    fn is_confirmed(&self, deferred: &Deferred<T>) -> bool {
+        let mut parent = Frame::default();
+        for (depth, frame) in deferred.stack.iter().enumerate() {
+            let mut frame = match depth < deferred.open {
+                true => self.stack[depth],
+                false => *frame,
+            };
+            self.confirm(&parent, &mut frame);
+            parent = frame;
+        }
+        parent.confirmed & self.last_steps != 0
+    }
+}
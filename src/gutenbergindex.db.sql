@@ -1,12 +1,15 @@
 BEGIN TRANSACTION;
+-- `kind` is title for the main titles (`dcterms:title`) and alternative for the others (`dcterms:alternative`)
 CREATE TABLE `titles` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
 	`name`	TEXT,
-	`bookid` INTEGER
+	`bookid` INTEGER,
+	`kind`	TEXT
 );
 CREATE TABLE `subjects` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
-	`name`	TEXT
+	`name`	TEXT,
+	`vocabulary`	TEXT
 );
 CREATE TABLE `rights` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
@@ -18,7 +21,11 @@ CREATE TABLE `publishers` (
 );
 CREATE TABLE `languages` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
-	`name`	TEXT
+	`name`	TEXT,
+	`code`	TEXT,
+	`macrolanguage`	TEXT,
+	`englishname`	TEXT,
+	`nativename`	TEXT
 );
 
 CREATE TABLE `downloadlinkstype` (
@@ -30,7 +37,13 @@ CREATE TABLE `downloadlinks` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
 	`name`	TEXT,
 	`downloadtypeid`	INTEGER,
-	`bookid` INTEGER
+	`bookid` INTEGER,
+	`extent`	INTEGER,
+	`modified`	TEXT
+);
+CREATE TABLE `downloadlink_formats` (
+	`downloadlinkid`	INTEGER,
+	`downloadtypeid`	INTEGER
 );
 CREATE TABLE `bookshelves` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
@@ -41,7 +54,30 @@ CREATE TABLE "books" (
 	`publisherid` INTEGER,
 	`rightsid` INTEGER,
 	`numdownloads` INTEGER,
-	`gutenbergbookid` INTEGER
+	`gutenbergbookid` INTEGER,
+	`dateissued` TEXT,
+	`type`	TEXT,
+	`license`	TEXT,
+	`title`	TEXT,
+	`subtitle`	TEXT,
+	`contenthash`	TEXT
+);
+-- the descriptions of the books, `kind` is one of description, tableofcontents and summary
+CREATE TABLE `descriptions` (
+	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`bookid`	INTEGER,
+	`kind`	TEXT,
+	`text`	TEXT
+);
+-- the values of the custom fields (see `CustomField`), `field` is the key of the field
+CREATE TABLE `customfields` (
+	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`field`	TEXT,
+	`value`	TEXT
+);
+CREATE TABLE `book_customfields` (
+	`bookid`	INTEGER,
+	`customfieldid`	INTEGER
 );
 CREATE TABLE `book_subjects` (
 	`bookid`	INTEGER,
@@ -51,6 +87,12 @@ CREATE TABLE `book_authors` (
 	`bookid`	INTEGER,
 	`authorid`	INTEGER
 );
+CREATE TABLE `book_contributors` (
+	`bookid`	INTEGER,
+	`agentid`	INTEGER,
+	`role`	TEXT,
+	`authorid`	INTEGER
+);
 CREATE TABLE `book_languages` (
 	`bookid`	INTEGER,
 	`languageid`	INTEGER
@@ -61,6 +103,49 @@ CREATE TABLE `book_bookshelves` (
 );
 CREATE TABLE `authors` (
 	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`name`	TEXT,
+	`agentid`	INTEGER,
+	`birthdate`	INTEGER,
+	`deathdate`	INTEGER,
+	`surname`	TEXT,
+	`givennames`	TEXT,
+	`fullername`	TEXT,
+	`displayname`	TEXT,
+	`corporate`	INTEGER
+);
+CREATE TABLE `author_names` (
+	`authorid`	INTEGER,
+	`name`	TEXT
+);
+CREATE TABLE `author_aliases` (
+	`authorid`	INTEGER,
 	`name`	TEXT
 );
+CREATE TABLE `author_webpages` (
+	`authorid`	INTEGER,
+	`url`	TEXT
+);
+CREATE TABLE `snapshots` (
+	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`createdat`	TEXT,
+	`source`	TEXT,
+	`lastissued`	TEXT,
+	`books`	INTEGER,
+	`added`	INTEGER,
+	`updated`	INTEGER,
+	`removed`	INTEGER
+);
+-- `stale` is set on caches migrated from a schema whose data the migrations could not fill in
+CREATE TABLE `cachestate` (
+	`stale`	INTEGER
+);
+INSERT INTO `cachestate`(`stale`) VALUES (0);
+CREATE VIRTUAL TABLE `book_search` USING fts5(
+	`titles`,
+	`authors`,
+	`subjects`,
+	`bookshelves`,
+	`descriptions`,
+	tokenize = 'unicode61 remove_diacritics 2'
+);
 COMMIT;
\ No newline at end of file
@@ -2,22 +2,46 @@ BEGIN TRANSACTION;
 
 CREATE INDEX `books_numdownloads_idx` ON `books` (`numdownloads` ASC);
 CREATE INDEX `gutenbergbookid_idx` ON `books` (`gutenbergbookid` ASC);
+CREATE INDEX `books_dateissued_idx` ON `books` (`dateissued` ASC);
+CREATE INDEX `books_type_idx` ON `books` (`type` ASC);
+CREATE INDEX `descriptions_bookid_idx` ON `descriptions` (`bookid` ASC);
+CREATE INDEX `customfields_field_value_idx` ON `customfields` (`field` ASC, `value` ASC);
+CREATE INDEX `book_customfields_bookid_idx` ON `book_customfields` (`bookid` ASC);
+CREATE INDEX `book_customfields_customfieldid_idx` ON `book_customfields` (`customfieldid` ASC);
 
 CREATE INDEX `authors_name_idx` ON `authors` (`name` ASC);
+CREATE INDEX `authors_agentid_idx` ON `authors` (`agentid` ASC);
+CREATE INDEX `authors_birthdate_idx` ON `authors` (`birthdate` ASC);
+CREATE INDEX `authors_deathdate_idx` ON `authors` (`deathdate` ASC);
+CREATE INDEX `author_aliases_authorid_idx` ON `author_aliases` (`authorid` ASC);
+CREATE INDEX `author_webpages_authorid_idx` ON `author_webpages` (`authorid` ASC);
+CREATE INDEX `author_names_authorid_idx` ON `author_names` (`authorid` ASC);
+CREATE INDEX `author_names_name_idx` ON `author_names` (`name` ASC);
+CREATE INDEX `book_contributors_bookid_idx` ON `book_contributors` (`bookid` ASC);
+CREATE INDEX `book_contributors_agentid_idx` ON `book_contributors` (`agentid` ASC);
+CREATE INDEX `book_contributors_authorid_idx` ON `book_contributors` (`authorid` ASC);
 
 CREATE INDEX `titles_name_idx` ON `titles` (`name` ASC);
+CREATE INDEX `titles_bookid_idx` ON `titles` (`bookid` ASC);
+CREATE INDEX `books_subtitle_idx` ON `books` (`subtitle` ASC);
 
 CREATE INDEX `subjects_name_idx` ON `subjects` (`name` ASC);
+CREATE INDEX `subjects_vocabulary_idx` ON `subjects` (`vocabulary` ASC);
 
 CREATE INDEX `rights_name_idx` ON `rights` (`name` ASC);
 
 CREATE INDEX `publishers_name_idx` ON `publishers` (`name` ASC);
 
 CREATE INDEX `languages_name_idx` ON `languages` (`name` ASC);
+CREATE INDEX `languages_code_idx` ON `languages` (`code` ASC);
+CREATE INDEX `languages_macrolanguage_idx` ON `languages` (`macrolanguage` ASC);
 
 CREATE INDEX `bookshelves_name_idx` ON `bookshelves` (`name` ASC);
 
 CREATE INDEX `downloadlinks_name_idx` ON `downloadlinks` (`name` ASC);
+CREATE INDEX `downloadlinks_bookid_idx` ON `downloadlinks` (`bookid` ASC);
+CREATE INDEX `downloadlink_formats_downloadlinkid_idx` ON `downloadlink_formats` (`downloadlinkid` ASC);
+CREATE INDEX `downloadlink_formats_downloadtypeid_idx` ON `downloadlink_formats` (`downloadtypeid` ASC);
 
 
 COMMIT;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- the ISO 639 codes and names of the languages, filled in by the migration from the bundled language table
+ALTER TABLE `languages` ADD COLUMN `code` TEXT;
+ALTER TABLE `languages` ADD COLUMN `macrolanguage` TEXT;
+ALTER TABLE `languages` ADD COLUMN `englishname` TEXT;
+ALTER TABLE `languages` ADD COLUMN `nativename` TEXT;
+CREATE INDEX `languages_code_idx` ON `languages` (`code` ASC);
+CREATE INDEX `languages_macrolanguage_idx` ON `languages` (`macrolanguage` ASC);
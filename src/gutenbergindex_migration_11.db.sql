@@ -0,0 +1,12 @@
+-- the parts of the author names and the forms author queries match, filled in by the migration
+ALTER TABLE `authors` ADD COLUMN `surname` TEXT;
+ALTER TABLE `authors` ADD COLUMN `givennames` TEXT;
+ALTER TABLE `authors` ADD COLUMN `fullername` TEXT;
+ALTER TABLE `authors` ADD COLUMN `displayname` TEXT;
+ALTER TABLE `authors` ADD COLUMN `corporate` INTEGER;
+CREATE TABLE `author_names` (
+	`authorid`	INTEGER,
+	`name`	TEXT
+);
+CREATE INDEX `author_names_authorid_idx` ON `author_names` (`authorid` ASC);
+CREATE INDEX `author_names_name_idx` ON `author_names` (`name` ASC);
@@ -0,0 +1,2 @@
+-- the content hash of the books, a book without one is rewritten by the next update of the cache
+ALTER TABLE `books` ADD COLUMN `contenthash` TEXT;
@@ -0,0 +1,5 @@
+-- a cache migrated from a schema before version 9 is stale: the migrations could not fill in all of its data
+CREATE TABLE `cachestate` (
+	`stale`	INTEGER
+);
+INSERT INTO `cachestate`(`stale`) VALUES (0);
@@ -0,0 +1,6 @@
+-- contributors are linked to their author row, the ones without an agent id were not stored before
+ALTER TABLE `book_contributors` ADD COLUMN `authorid` INTEGER;
+UPDATE `book_contributors` SET `authorid` = (SELECT `id` FROM `authors` WHERE `authors`.`agentid` = `book_contributors`.`agentid`);
+CREATE INDEX `book_contributors_authorid_idx` ON `book_contributors` (`authorid` ASC);
+-- the next update rewrites every book, bringing back the contributors that were dropped
+UPDATE `books` SET `contenthash` = NULL;
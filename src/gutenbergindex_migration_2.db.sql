@@ -0,0 +1,23 @@
+-- version 1 is the schema of the caches built before the schema was versioned
+ALTER TABLE `books` ADD COLUMN `dateissued` TEXT;
+CREATE INDEX `books_dateissued_idx` ON `books` (`dateissued` ASC);
+
+CREATE TABLE `snapshots` (
+	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`createdat`	TEXT,
+	`source`	TEXT,
+	`lastissued`	TEXT,
+	`books`	INTEGER,
+	`added`	INTEGER,
+	`updated`	INTEGER,
+	`removed`	INTEGER
+);
+
+-- the search table, filled in by the next update of the cache
+CREATE VIRTUAL TABLE `book_search` USING fts5(
+	`titles`,
+	`authors`,
+	`subjects`,
+	`bookshelves`,
+	tokenize = 'unicode61 remove_diacritics 2'
+);
@@ -0,0 +1,17 @@
+-- agent metadata, the values are filled in by the next update of the cache
+ALTER TABLE `authors` ADD COLUMN `agentid` INTEGER;
+ALTER TABLE `authors` ADD COLUMN `birthdate` INTEGER;
+ALTER TABLE `authors` ADD COLUMN `deathdate` INTEGER;
+CREATE TABLE `author_aliases` (
+	`authorid`	INTEGER,
+	`name`	TEXT
+);
+CREATE TABLE `author_webpages` (
+	`authorid`	INTEGER,
+	`url`	TEXT
+);
+CREATE INDEX `authors_agentid_idx` ON `authors` (`agentid` ASC);
+CREATE INDEX `authors_birthdate_idx` ON `authors` (`birthdate` ASC);
+CREATE INDEX `authors_deathdate_idx` ON `authors` (`deathdate` ASC);
+CREATE INDEX `author_aliases_authorid_idx` ON `author_aliases` (`authorid` ASC);
+CREATE INDEX `author_webpages_authorid_idx` ON `author_webpages` (`authorid` ASC);
@@ -0,0 +1,8 @@
+-- contributors (translators, illustrators, editors...), filled in by the next update of the cache
+CREATE TABLE `book_contributors` (
+	`bookid`	INTEGER,
+	`agentid`	INTEGER,
+	`role`	TEXT
+);
+CREATE INDEX `book_contributors_bookid_idx` ON `book_contributors` (`bookid` ASC);
+CREATE INDEX `book_contributors_agentid_idx` ON `book_contributors` (`agentid` ASC);
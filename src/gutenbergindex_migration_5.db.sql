@@ -0,0 +1,15 @@
+-- per file size and modification time, filled in by the next update of the cache
+ALTER TABLE `downloadlinks` ADD COLUMN `extent` INTEGER;
+ALTER TABLE `downloadlinks` ADD COLUMN `modified` TEXT;
+
+-- every format of a file, older caches only know the one in downloadlinks.downloadtypeid
+CREATE TABLE `downloadlink_formats` (
+	`downloadlinkid`	INTEGER,
+	`downloadtypeid`	INTEGER
+);
+INSERT INTO `downloadlink_formats`(downloadlinkid, downloadtypeid)
+SELECT id, downloadtypeid FROM `downloadlinks` WHERE downloadtypeid IS NOT NULL;
+
+CREATE INDEX `downloadlinks_bookid_idx` ON `downloadlinks` (`bookid` ASC);
+CREATE INDEX `downloadlink_formats_downloadlinkid_idx` ON `downloadlink_formats` (`downloadlinkid` ASC);
+CREATE INDEX `downloadlink_formats_downloadtypeid_idx` ON `downloadlink_formats` (`downloadtypeid` ASC);
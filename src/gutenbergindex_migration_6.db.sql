@@ -0,0 +1,3 @@
+-- the vocabulary of the subjects (LCSH headings or LCC classification codes), filled in by the next update of the cache
+ALTER TABLE `subjects` ADD COLUMN `vocabulary` TEXT;
+CREATE INDEX `subjects_vocabulary_idx` ON `subjects` (`vocabulary` ASC);
@@ -0,0 +1,23 @@
+-- the book type, license and descriptions, filled in by the next update of the cache
+ALTER TABLE `books` ADD COLUMN `type` TEXT;
+ALTER TABLE `books` ADD COLUMN `license` TEXT;
+CREATE INDEX `books_type_idx` ON `books` (`type` ASC);
+
+CREATE TABLE `descriptions` (
+	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`bookid`	INTEGER,
+	`kind`	TEXT,
+	`text`	TEXT
+);
+CREATE INDEX `descriptions_bookid_idx` ON `descriptions` (`bookid` ASC);
+
+-- the search table gets a column for the descriptions, it is filled in by the next update of the cache
+DROP TABLE `book_search`;
+CREATE VIRTUAL TABLE `book_search` USING fts5(
+	`titles`,
+	`authors`,
+	`subjects`,
+	`bookshelves`,
+	`descriptions`,
+	tokenize = 'unicode61 remove_diacritics 2'
+);
@@ -0,0 +1,13 @@
+-- the values of the custom fields, filled in by the next update of the cache
+CREATE TABLE `customfields` (
+	`id`	INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+	`field`	TEXT,
+	`value`	TEXT
+);
+CREATE TABLE `book_customfields` (
+	`bookid`	INTEGER,
+	`customfieldid`	INTEGER
+);
+CREATE INDEX `customfields_field_value_idx` ON `customfields` (`field` ASC, `value` ASC);
+CREATE INDEX `book_customfields_bookid_idx` ON `book_customfields` (`bookid` ASC);
+CREATE INDEX `book_customfields_customfieldid_idx` ON `book_customfields` (`customfieldid` ASC);
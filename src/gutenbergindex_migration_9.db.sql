@@ -0,0 +1,6 @@
+-- the kind of the titles, and the main title and subtitle of the books, filled in by the next update of the cache
+ALTER TABLE `titles` ADD COLUMN `kind` TEXT;
+CREATE INDEX `titles_bookid_idx` ON `titles` (`bookid` ASC);
+ALTER TABLE `books` ADD COLUMN `title` TEXT;
+ALTER TABLE `books` ADD COLUMN `subtitle` TEXT;
+CREATE INDEX `books_subtitle_idx` ON `books` (`subtitle` ASC);
@@ -0,0 +1,11 @@
+DELETE FROM `authors` WHERE `id` NOT IN (SELECT `authorid` FROM `book_authors`)
+	AND `id` NOT IN (SELECT `authorid` FROM `book_contributors` WHERE `authorid` IS NOT NULL);
+DELETE FROM `author_aliases` WHERE `authorid` NOT IN (SELECT `id` FROM `authors`);
+DELETE FROM `author_webpages` WHERE `authorid` NOT IN (SELECT `id` FROM `authors`);
+DELETE FROM `author_names` WHERE `authorid` NOT IN (SELECT `id` FROM `authors`);
+DELETE FROM `subjects` WHERE `id` NOT IN (SELECT `subjectid` FROM `book_subjects`);
+DELETE FROM `languages` WHERE `id` NOT IN (SELECT `languageid` FROM `book_languages`);
+DELETE FROM `bookshelves` WHERE `id` NOT IN (SELECT `bookshelfid` FROM `book_bookshelves`);
+DELETE FROM `publishers` WHERE `id` NOT IN (SELECT `publisherid` FROM `books`);
+DELETE FROM `rights` WHERE `id` NOT IN (SELECT `rightsid` FROM `books`);
+DELETE FROM `customfields` WHERE `id` NOT IN (SELECT `customfieldid` FROM `book_customfields`);
@@ -0,0 +1,13 @@
+INSERT INTO `book_search`(`rowid`, `titles`, `authors`, `subjects`, `bookshelves`, `descriptions`)
+SELECT books.id,
+	(SELECT group_concat(titles.name, ' ; ') FROM titles WHERE titles.bookid = books.id),
+	(SELECT group_concat(authors.name, ' ; ') FROM authors, book_authors
+		WHERE authors.id = book_authors.authorid AND book_authors.bookid = books.id),
+	(SELECT group_concat(subjects.name, ' ; ') FROM subjects, book_subjects
+		WHERE subjects.id = book_subjects.subjectid AND book_subjects.bookid = books.id),
+	(SELECT group_concat(bookshelves.name, ' ; ') FROM bookshelves, book_bookshelves
+		WHERE bookshelves.id = book_bookshelves.bookshelfid AND book_bookshelves.bookid = books.id),
+	(SELECT group_concat(descriptions.text, ' ; ') FROM descriptions WHERE descriptions.bookid = books.id)
+FROM books WHERE books.id NOT IN (SELECT `rowid` FROM `book_search`);
+
+INSERT INTO `book_search`(`book_search`) VALUES('optimize');
@@ -0,0 +1,269 @@
+use serde::Serialize;
+
+/// A language of ISO 639, with its codes and names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Language {
+    /// the ISO 639-3 code (ex: `eng`), for the collective languages of the catalog (`myn`, `nah`, `nai`) the ISO 639-5 one
+    pub code: &'static str,
+    /// the ISO 639-1 two letter code (ex: `en`)
+    pub part1: Option<&'static str>,
+    /// the ISO 639-2/B bibliographic code when it differs from `code` (ex: `fre` for `fra`)
+    pub part2b: Option<&'static str>,
+    pub english_name: &'static str,
+    /// the name of the language in the language itself
+    pub native_name: &'static str,
+    /// the ISO 639-3 code of the macrolanguage it belongs to (ex: `zho` for `yue`)
+    pub macrolanguage: Option<&'static str>,
+}
+
+impl Language {
+    /// the BCP 47 language subtag: the two letter code when there is one, else the ISO 639-3 code
+    pub fn tag(&self) -> &'static str {
+        self.part1.unwrap_or(self.code)
+    }
+
+    /// the code of the macrolanguage it belongs to, or its own code (ex: `zho` for `zho`, `cmn` and `yue`)
+    pub fn group(&self) -> &'static str {
+        self.macrolanguage.unwrap_or(self.code)
+    }
+}
+
+const fn language(
+    code: &'static str,
+    part1: &'static str,
+    part2b: &'static str,
+    english_name: &'static str,
+    native_name: &'static str,
+    macrolanguage: &'static str,
+) -> Language {
+    Language {
+        code,
+        part1: optional(part1),
+        part2b: optional(part2b),
+        english_name,
+        native_name,
+        macrolanguage: optional(macrolanguage),
+    }
+}
+
+const fn optional(value: &'static str) -> Option<&'static str> {
+    match value.is_empty() {
+        true => None,
+        false => Some(value),
+    }
+}
+
+/// the languages of the gutenberg catalog and the most common other ones, by ISO 639-3 code
+pub static LANGUAGES: [Language; 121] = [
+    language("afr", "af", "", "Afrikaans", "Afrikaans", ""),
+    language("ale", "", "", "Aleut", "Unangam Tunuu", ""),
+    language("ang", "", "", "Old English", "Englisc", ""),
+    language("ara", "ar", "", "Arabic", "العربية", ""),
+    language("arb", "", "", "Standard Arabic", "العربية الفصحى", "ara"),
+    language("arp", "", "", "Arapaho", "Hinónoʼeitíít", ""),
+    language("aze", "az", "", "Azerbaijani", "azərbaycan dili", ""),
+    language("bel", "be", "", "Belarusian", "беларуская", ""),
+    language("ben", "bn", "", "Bengali", "বাংলা", ""),
+    language("bgs", "", "", "Tagabawa", "Tagabawa", ""),
+    language("bod", "bo", "tib", "Tibetan", "བོད་སྐད་", ""),
+    language("bos", "bs", "", "Bosnian", "bosanski", "hbs"),
+    language("bre", "br", "", "Breton", "brezhoneg", ""),
+    language("brx", "", "", "Bodo", "बड़ो", ""),
+    language("bul", "bg", "", "Bulgarian", "български", ""),
+    language("cat", "ca", "", "Catalan", "català", ""),
+    language("ceb", "", "", "Cebuano", "Sinugbuanong Binisayâ", ""),
+    language("ces", "cs", "cze", "Czech", "čeština", ""),
+    language("chr", "", "", "Cherokee", "ᏣᎳᎩ", ""),
+    language("cmn", "", "", "Mandarin Chinese", "官话", "zho"),
+    language("cor", "kw", "", "Cornish", "Kernewek", ""),
+    language("csb", "", "", "Kashubian", "kaszëbsczi", ""),
+    language("cym", "cy", "wel", "Welsh", "Cymraeg", ""),
+    language("dan", "da", "", "Danish", "dansk", ""),
+    language("deu", "de", "ger", "German", "Deutsch", ""),
+    language("dum", "", "", "Middle Dutch", "Diets", ""),
+    language("ekk", "", "", "Standard Estonian", "eesti", "est"),
+    language("ell", "el", "gre", "Modern Greek", "Ελληνικά", ""),
+    language("eng", "en", "", "English", "English", ""),
+    language("enm", "", "", "Middle English", "Englisch", ""),
+    language("epo", "eo", "", "Esperanto", "Esperanto", ""),
+    language("est", "et", "", "Estonian", "eesti", ""),
+    language("eus", "eu", "baq", "Basque", "euskara", ""),
+    language("fao", "fo", "", "Faroese", "føroyskt", ""),
+    language("fas", "fa", "per", "Persian", "فارسی", ""),
+    language("fin", "fi", "", "Finnish", "suomi", ""),
+    language("fra", "fr", "fre", "French", "français", ""),
+    language("frm", "", "", "Middle French", "françois", ""),
+    language("fro", "", "", "Old French", "franceis", ""),
+    language("fry", "fy", "", "Western Frisian", "Frysk", ""),
+    language("fur", "", "", "Friulian", "furlan", ""),
+    language("gla", "gd", "", "Scottish Gaelic", "Gàidhlig", ""),
+    language("gle", "ga", "", "Irish", "Gaeilge", ""),
+    language("glg", "gl", "", "Galician", "galego", ""),
+    language("got", "", "", "Gothic", "𐌲𐌿𐍄𐌹𐍃𐌺", ""),
+    language("grc", "", "", "Ancient Greek", "Ἑλληνική", ""),
+    language("guj", "gu", "", "Gujarati", "ગુજરાતી", ""),
+    language("hak", "", "", "Hakka Chinese", "客家話", "zho"),
+    language("haw", "", "", "Hawaiian", "ʻŌlelo Hawaiʻi", ""),
+    language("hbs", "sh", "", "Serbo-Croatian", "srpskohrvatski", ""),
+    language("heb", "he", "", "Hebrew", "עברית", ""),
+    language("hin", "hi", "", "Hindi", "हिन्दी", ""),
+    language("hrv", "hr", "", "Croatian", "hrvatski", "hbs"),
+    language("hun", "hu", "", "Hungarian", "magyar", ""),
+    language("hye", "hy", "arm", "Armenian", "հայերեն", ""),
+    language("iku", "iu", "", "Inuktitut", "ᐃᓄᒃᑎᑐᑦ", ""),
+    language("ilo", "", "", "Iloko", "Ilokano", ""),
+    language("ina", "ia", "", "Interlingua", "interlingua", ""),
+    language("ind", "id", "", "Indonesian", "Bahasa Indonesia", "msa"),
+    language("isl", "is", "ice", "Icelandic", "íslenska", ""),
+    language("ita", "it", "", "Italian", "italiano", ""),
+    language("jpn", "ja", "", "Japanese", "日本語", ""),
+    language("kan", "kn", "", "Kannada", "ಕನ್ನಡ", ""),
+    language("kat", "ka", "geo", "Georgian", "ქართული", ""),
+    language("kha", "", "", "Khasi", "Khasi", ""),
+    language("kld", "", "", "Gamilaraay", "Gamilaraay", ""),
+    language("kor", "ko", "", "Korean", "한국어", ""),
+    language("lat", "la", "", "Latin", "Latina", ""),
+    language("lav", "lv", "", "Latvian", "latviešu", ""),
+    language("lit", "lt", "", "Lithuanian", "lietuvių", ""),
+    language("ltz", "lb", "", "Luxembourgish", "Lëtzebuergesch", ""),
+    language("lvs", "", "", "Standard Latvian", "latviešu", "lav"),
+    language("mal", "ml", "", "Malayalam", "മലയാളം", ""),
+    language("mar", "mr", "", "Marathi", "मराठी", ""),
+    language("mkd", "mk", "mac", "Macedonian", "македонски", ""),
+    language("mlt", "mt", "", "Maltese", "Malti", ""),
+    language("mri", "mi", "mao", "Maori", "te reo Māori", ""),
+    language("msa", "ms", "may", "Malay", "Bahasa Melayu", ""),
+    language("myn", "", "", "Mayan languages", "Mayan languages", ""),
+    language("nah", "", "", "Nahuatl languages", "Nāhuatl", ""),
+    language(
+        "nai",
+        "",
+        "",
+        "North American Indian languages",
+        "North American Indian languages",
+        "",
+    ),
+    language("nan", "", "", "Min Nan Chinese", "閩南語", "zho"),
+    language("nap", "", "", "Neapolitan", "napulitano", ""),
+    language("nav", "nv", "", "Navajo", "Diné bizaad", ""),
+    language("nld", "nl", "dut", "Dutch", "Nederlands", ""),
+    language("nno", "nn", "", "Norwegian Nynorsk", "norsk nynorsk", "nor"),
+    language("nob", "nb", "", "Norwegian Bokmål", "norsk bokmål", "nor"),
+    language("nor", "no", "", "Norwegian", "norsk", ""),
+    language("oci", "oc", "", "Occitan", "occitan", ""),
+    language("oji", "oj", "", "Ojibwa", "ᐊᓂᔑᓈᐯᒧᐎᓐ", ""),
+    language("pan", "pa", "", "Punjabi", "ਪੰਜਾਬੀ", ""),
+    language("pes", "", "", "Iranian Persian", "فارسی", "fas"),
+    language("pol", "pl", "", "Polish", "polski", ""),
+    language("por", "pt", "", "Portuguese", "português", ""),
+    language("rmq", "", "", "Caló", "caló", ""),
+    language("ron", "ro", "rum", "Romanian", "română", ""),
+    language("rus", "ru", "", "Russian", "русский", ""),
+    language("san", "sa", "", "Sanskrit", "संस्कृतम्", ""),
+    language("sco", "", "", "Scots", "Scots", ""),
+    language("slk", "sk", "slo", "Slovak", "slovenčina", ""),
+    language("slv", "sl", "", "Slovenian", "slovenščina", ""),
+    language("spa", "es", "", "Spanish", "español", ""),
+    language("sqi", "sq", "alb", "Albanian", "shqip", ""),
+    language("srp", "sr", "", "Serbian", "српски", "hbs"),
+    language("swa", "sw", "", "Swahili", "Kiswahili", ""),
+    language("swe", "sv", "", "Swedish", "svenska", ""),
+    language(
+        "swh",
+        "",
+        "",
+        "Swahili (individual language)",
+        "Kiswahili",
+        "swa",
+    ),
+    language("tam", "ta", "", "Tamil", "தமிழ்", ""),
+    language("tel", "te", "", "Telugu", "తెలుగు", ""),
+    language("tgl", "tl", "", "Tagalog", "Tagalog", ""),
+    language("tha", "th", "", "Thai", "ไทย", ""),
+    language("tur", "tr", "", "Turkish", "Türkçe", ""),
+    language("ukr", "uk", "", "Ukrainian", "українська", ""),
+    language("urd", "ur", "", "Urdu", "اردو", ""),
+    language("vie", "vi", "", "Vietnamese", "Tiếng Việt", ""),
+    language("wuu", "", "", "Wu Chinese", "吴语", "zho"),
+    language("ydd", "", "", "Eastern Yiddish", "ייִדיש", "yid"),
+    language("yid", "yi", "", "Yiddish", "ייִדיש", ""),
+    language("yue", "", "", "Yue Chinese", "粵語", "zho"),
+    language("zho", "zh", "chi", "Chinese", "中文", ""),
+    language("zsm", "", "", "Standard Malay", "Bahasa Melayu", "msa"),
+];
+
+/// the primary subtag of a language tag (`en` for `en-US`)
+fn primary_subtag(value: &str) -> String {
+    value
+        .trim()
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Finds a language by any of its codes, a language tag (ex: `en-GB`) or its english or native name, ignoring case
+pub fn find_language(value: &str) -> Option<&'static Language> {
+    let code = primary_subtag(value);
+    let by_code = LANGUAGES.iter().find(|x| {
+        x.code == code || x.part1 == Some(code.as_str()) || x.part2b == Some(code.as_str())
+    });
+    if by_code.is_some() {
+        return by_code;
+    }
+    // a name shared by a macrolanguage and its members (ex: "eesti") is the macrolanguage
+    let name = value.trim().to_lowercase();
+    LANGUAGES
+        .iter()
+        .filter(|x| x.english_name.to_lowercase() == name || x.native_name.to_lowercase() == name)
+        .min_by_key(|x| x.macrolanguage.is_some())
+}
+
+/// Normalizes a language tag to BCP 47: the shortest ISO 639 code of the language, the script in title case
+/// and the region in upper case (ex: `eng_us` is `en-US`, `fre` is `fr`, `zho-hant-tw` is `zh-Hant-TW`).
+/// Unknown languages are kept, in lower case.
+pub fn normalize_language_tag(value: &str) -> String {
+    let value = value.trim();
+    let mut subtags = value.split(['-', '_']).filter(|x| !x.is_empty());
+    let primary = match subtags.next() {
+        Some(primary) => primary,
+        None => return String::new(),
+    };
+    let mut tag = match (find_language(primary), find_language(value)) {
+        (Some(language), _) => language.tag().to_string(),
+        // a name with a dash in it (ex: "Serbo-Croatian")
+        (None, Some(language)) => return language.tag().to_string(),
+        (None, None) => primary.to_ascii_lowercase(),
+    };
+    for subtag in subtags {
+        tag.push('-');
+        match subtag.len() {
+            4 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                tag.push_str(&subtag[..1].to_ascii_uppercase());
+                tag.push_str(&subtag[1..].to_ascii_lowercase());
+            }
+            2 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                tag.push_str(&subtag.to_ascii_uppercase())
+            }
+            _ => tag.push_str(&subtag.to_ascii_lowercase()),
+        }
+    }
+    tag
+}
+
+/// Returns the ISO 639-3 code of a language given by code, tag or name (ex: `en`, `en-US`, `eng` and `English` are `eng`),
+/// unknown languages keep their primary subtag
+pub fn language_code(value: &str) -> String {
+    match find_language(value) {
+        Some(language) => language.code.to_string(),
+        None => primary_subtag(value),
+    }
+}
+
+/// Like `language_code` but the members of a macrolanguage are replaced by it (ex: `yue` and `zh-TW` are `zho`)
+pub fn macrolanguage_code(value: &str) -> String {
+    match find_language(value) {
+        Some(language) => language.group().to_string(),
+        None => primary_subtag(value),
+    }
+}
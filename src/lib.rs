@@ -1,17 +1,24 @@
 #![doc = include_str!("../README.md")]
 use crate::error::Error;
-use sqlite_cache::SQLiteCache;
+use rdf_parser::ParseReport;
+use sqlite_cache::{SQLiteCache, UpdateReport};
 
 use settings::GutenbergCacheSettings;
 use utils::{decompress_tar_bz, download_file};
 
-mod book;
+mod fst_parser_agent_node;
+mod fst_parser_custom_node;
 mod fst_parser_file_node;
 mod fst_parser_node;
-mod fst_parser_or_node;
+mod fst_parser_subject_node;
+mod fst_path;
 mod utils;
 
+pub mod book;
+pub mod book_query;
+pub mod book_record;
 pub mod error;
+pub mod language;
 pub mod rdf_parser;
 pub mod settings;
 pub mod sqlite_cache;
@@ -20,11 +27,13 @@ pub mod text_get;
 pub mod fst_parser;
 pub mod fst_parser_type;
 
+/// Builds the cache if it is not there yet (or `force_regenerate` is set) and returns it with the report of
+/// the parse, which lists the rdfs skipped with `lenient_parsing` (empty when the existing cache is used)
 pub async fn setup_sqlite(
     settings: &GutenbergCacheSettings,
     force_regenerate: bool,
     show_progress_bar: bool,
-) -> Result<SQLiteCache, Error> {
+) -> Result<(SQLiteCache, ParseReport), Error> {
     let archive_exists = std::path::Path::new(&settings.cache_rdf_archive_name).exists();
     if !archive_exists || force_regenerate {
         if archive_exists {
@@ -38,21 +47,108 @@ pub async fn setup_sqlite(
         .await?;
     }
 
-    let cache_folder_exists = std::path::Path::new(&settings.cache_rdf_unpack_directory).exists();
+    if settings.unpack_rdf_archive {
+        let cache_folder_exists =
+            std::path::Path::new(&settings.cache_rdf_unpack_directory).exists();
 
-    if !cache_folder_exists || force_regenerate {
-        if cache_folder_exists {
-            std::fs::remove_file(&settings.cache_rdf_unpack_directory)?;
+        if !cache_folder_exists || force_regenerate {
+            if cache_folder_exists {
+                std::fs::remove_dir_all(&settings.cache_rdf_unpack_directory)?;
+            }
+            decompress_tar_bz(&settings.cache_rdf_archive_name, show_progress_bar)?;
+        }
+    }
+
+    match SQLiteCache::get_cache(settings) {
+        // a cache migrated from an old schema misses data that only a rebuild brings back
+        Ok(cache) if cache.is_stale()? => {
+            drop(cache);
+            let (parse_result, parse_report) = parse_rdfs(settings, show_progress_bar)?;
+            let cache =
+                SQLiteCache::create_cache(&parse_result, settings, true, show_progress_bar)?;
+            Ok((cache, parse_report))
+        }
+        Ok(cache) => Ok((cache, ParseReport::default())),
+        Err(Error::InvalidCacheSchema(e)) => Err(Error::InvalidCacheSchema(e)),
+        Err(_e) => {
+            let (parse_result, parse_report) = parse_rdfs(settings, show_progress_bar)?;
+            let cache =
+                SQLiteCache::create_cache(&parse_result, settings, false, show_progress_bar)?;
+            Ok((cache, parse_report))
+        }
+    }
+}
+
+/// Downloads the current rdf archive and applies it to the existing cache (see `SQLiteCache::update_cache`),
+/// only new or changed books are written and books that are no longer in the catalog are removed.
+/// If there is no cache yet it is created. The report of the parse lists the rdfs skipped with `lenient_parsing`.
+pub async fn update_sqlite(
+    settings: &GutenbergCacheSettings,
+    show_progress_bar: bool,
+) -> Result<(SQLiteCache, UpdateReport, ParseReport), Error> {
+    if std::path::Path::new(&settings.cache_rdf_archive_name).exists() {
+        std::fs::remove_file(&settings.cache_rdf_archive_name)?;
+    }
+    download_file(
+        &settings.cache_rdf_download_link,
+        &settings.cache_rdf_archive_name,
+        show_progress_bar,
+    )
+    .await?;
+
+    if settings.unpack_rdf_archive {
+        // unpack into a clean folder so books removed from the catalog do not linger
+        if std::path::Path::new(&settings.cache_rdf_unpack_directory).exists() {
+            std::fs::remove_dir_all(&settings.cache_rdf_unpack_directory)?;
         }
         decompress_tar_bz(&settings.cache_rdf_archive_name, show_progress_bar)?;
     }
 
+    let (parse_result, parse_report) = parse_rdfs(settings, show_progress_bar)?;
     match SQLiteCache::get_cache(settings) {
-        Ok(cache) => Ok(cache),
+        Err(Error::InvalidCacheSchema(e)) => Err(Error::InvalidCacheSchema(e)),
+        Ok(mut cache) => {
+            let report = cache.update_cache(
+                &parse_result,
+                true,
+                Some(&settings.cache_rdf_download_link),
+                show_progress_bar,
+            )?;
+            Ok((cache, report, parse_report))
+        }
         Err(_e) => {
-            let parse_result =
-                rdf_parser::parse_rdfs_from_folder(&settings.cache_rdf_unpack_directory, true)?;
-            SQLiteCache::create_cache(&parse_result, settings, false, show_progress_bar)
+            let mut cache =
+                SQLiteCache::create_cache(&parse_result, settings, false, show_progress_bar)?;
+            let report = UpdateReport {
+                added: cache.find(&book_query::BookQuery::new())?,
+                ..Default::default()
+            };
+            Ok((cache, report, parse_report))
         }
     }
 }
+
+/// parses the rdfs with the parse settings (`lenient_parsing`, `parser_threads`, `custom_fields`),
+/// from the unpack directory when `unpack_rdf_archive` is set or else straight from the archive
+fn parse_rdfs(
+    settings: &GutenbergCacheSettings,
+    show_progress_bar: bool,
+) -> Result<(fst_parser::ParseResult, ParseReport), Error> {
+    let options = rdf_parser::ParseOptions {
+        lenient: settings.lenient_parsing,
+        threads: settings.parser_threads,
+        custom_fields: settings.custom_fields.clone(),
+    };
+    match settings.unpack_rdf_archive {
+        true => rdf_parser::parse_rdfs_from_folder_with(
+            &settings.cache_rdf_unpack_directory,
+            &options,
+            show_progress_bar,
+        ),
+        false => rdf_parser::parse_rdfs_from_archive(
+            &settings.cache_rdf_archive_name,
+            &options,
+            show_progress_bar,
+        ),
+    }
+}
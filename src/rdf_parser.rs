@@ -1,29 +1,54 @@
+use bzip2::read::BzDecoder;
 use indexmap::IndexMap;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::borrow::Borrow;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::io::BufReader;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::str;
+use tar::{Archive, Entry};
 use walkdir::WalkDir;
 
-use quick_xml::events::Event;
-use quick_xml::reader::Reader;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::{LocalName, QName, ResolveResult};
+use quick_xml::reader::{NsReader, Reader};
 
 use crate::book::Book;
+use crate::book_record::BookRecord;
 use crate::error::Error;
+use crate::fst_parser::Agent;
+use crate::fst_parser::CustomField;
 use crate::fst_parser::DictionaryItemContent;
 use crate::fst_parser::FSTParser;
 use crate::fst_parser::ParseItemResult;
 use crate::fst_parser::ParseResult;
+use crate::fst_parser_agent_node::FSTParserAgentNode;
 use crate::fst_parser_file_node::FSTParserFileNode;
 use crate::fst_parser_node::FSTParserNode;
-use crate::fst_parser_or_node::FSTParserOrNode;
+use crate::fst_parser_subject_node::FSTParserSubjectNode;
 use crate::fst_parser_type::ParseType;
 
+/// the namespaces of the gutenberg rdfs, with the prefix the parsers know their nodes by
+static NAMESPACES: [(&str, &str); 7] = [
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+    ("pgterms", "http://www.gutenberg.org/2009/pgterms/"),
+    ("dcterms", "http://purl.org/dc/terms/"),
+    ("dcam", "http://purl.org/dc/dcam/"),
+    ("marcrel", "http://id.loc.gov/vocabulary/relators/"),
+    ("cc", "http://web.resource.org/cc/"),
+];
+
 pub trait XmlReader {
     fn trim(&mut self, val: bool) -> &mut Self;
     fn read<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<Event<'b>>;
     fn pos(&self) -> usize;
+    /// the namespace of a node (or attribute) name of the last event,
+    /// readers that do not track namespaces leave the names as they are written
+    fn resolve<'n>(&self, name: QName<'n>, _attribute: bool) -> (ResolveResult<'_>, LocalName<'n>) {
+        (ResolveResult::Unbound, name.into())
+    }
 }
 
 impl XmlReader for Reader<BufReader<std::fs::File>> {
@@ -50,66 +75,123 @@ impl XmlReader for Reader<&[u8]> {
     }
 }
 
+impl XmlReader for NsReader<BufReader<std::fs::File>> {
+    fn trim(&mut self, val: bool) -> &mut Self {
+        self.trim_text(val)
+    }
+    fn read<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<Event<'b>> {
+        self.read_event_into(buf)
+    }
+    fn pos(&self) -> usize {
+        self.buffer_position()
+    }
+    fn resolve<'n>(&self, name: QName<'n>, attribute: bool) -> (ResolveResult<'_>, LocalName<'n>) {
+        NsReader::resolve(self, name, attribute)
+    }
+}
+
+impl XmlReader for NsReader<&[u8]> {
+    fn trim(&mut self, val: bool) -> &mut Self {
+        self.trim_text(val)
+    }
+    fn read<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<Event<'b>> {
+        self.read_event_into(buf)
+    }
+    fn pos(&self) -> usize {
+        self.buffer_position()
+    }
+    fn resolve<'n>(&self, name: QName<'n>, attribute: bool) -> (ResolveResult<'_>, LocalName<'n>) {
+        NsReader::resolve(self, name, attribute)
+    }
+}
+
+/// the name of a node (or attribute) with the usual prefix of its namespace, whatever prefix the rdf uses for it,
+/// names outside of the known namespaces are kept as they are written
+fn qualified_name<'n, R: XmlReader>(
+    reader: &R,
+    name: QName<'n>,
+    attribute: bool,
+) -> Result<Cow<'n, str>, Error> {
+    let written = str::from_utf8(name.into_inner())?;
+    let (namespace, local_name) = match reader.resolve(name, attribute) {
+        (ResolveResult::Bound(namespace), local_name) => (namespace, local_name),
+        _ => return Ok(Cow::Borrowed(written)),
+    };
+    let prefix = match NAMESPACES
+        .iter()
+        .find(|(_, uri)| uri.as_bytes() == namespace.into_inner())
+    {
+        Some((prefix, _)) => *prefix,
+        None => return Ok(Cow::Borrowed(written)),
+    };
+    if name.prefix().map(|x| x.into_inner()) == Some(prefix.as_bytes()) {
+        return Ok(Cow::Borrowed(written));
+    }
+    let local_name = str::from_utf8(local_name.into_inner())?;
+    Ok(Cow::Owned(format!("{}:{}", prefix, local_name)))
+}
+
+/// the gutenberg id of an ebook from its `rdf:about`, relative (`ebooks/1`) or absolute (`http://www.gutenberg.org/ebooks/1`)
+fn parse_ebook_id(about: &str) -> Result<usize, Error> {
+    let path = match about.split_once("://") {
+        Some((_, url)) => url.split_once('/').map_or("", |x| x.1),
+        None => about,
+    };
+    path.trim_start_matches('/')
+        .strip_prefix("ebooks/")
+        .and_then(|x| x.parse::<usize>().ok())
+        .ok_or_else(|| Error::InvalidRdf(format!("cannot parse ebook id {}", about)))
+}
+
 pub fn parse_rdf_from_reader<R: XmlReader>(
     reader: &mut R,
     field_parsers: &mut Vec<Box<dyn FSTParser>>,
     book_id: usize,
     out: &mut ParseResult,
 ) -> Result<usize, Error> {
-    let mut gutenberg_book_id: usize = 0;
+    let mut gutenberg_book_id = None;
+    let mut has_root = false;
     let mut buf = Vec::with_capacity(1024);
     loop {
         reader.trim(true);
 
         match reader.read(&mut buf) {
             Ok(Event::Start(e)) => {
-                let current_node_name = str::from_utf8(e.name().0)?;
-                if current_node_name.eq("rdf::RDF") {
-                    continue;
+                let current_node_name = qualified_name(reader, e.name(), false)?;
+                if !has_root {
+                    if current_node_name != "rdf:RDF" {
+                        return Err(Error::InvalidRdf(format!(
+                            "the root node is {} instead of rdf:RDF",
+                            current_node_name
+                        )));
+                    }
+                    has_root = true;
                 }
 
-                if current_node_name.eq("pgterms:ebook") {
-                    for attr in e.attributes() {
-                        let attr_val = attr?;
-                        if attr_val.key.0.eq(b"rdf:about") {
-                            let str_book_id = str::from_utf8(attr_val.value.borrow())?;
-                            let splits = str_book_id.split("/").collect::<Vec<&str>>();
-                            assert!(splits.len() == 2);
-                            match splits[1].parse::<usize>() {
-                                Ok(book_id) => {
-                                    gutenberg_book_id = book_id;
-                                }
-                                Err(e) => {
-                                    return Err(Error::InvalidRdf(
-                                        format!(
-                                            "parseIntError:{} , cannot parse bookid for {}",
-                                            e.to_string(),
-                                            book_id
-                                        )
-                                        .to_string(),
-                                    ));
-                                }
-                            }
-                        }
+                let attributes = node_attributes(reader, &e)?;
+                if current_node_name == "pgterms:ebook" {
+                    if let Some((_, about)) = attributes.iter().find(|x| x.0 == "rdf:about") {
+                        gutenberg_book_id = Some(parse_ebook_id(about)?);
                     }
-                    continue;
                 }
 
+                start_node(&current_node_name, &attributes, field_parsers, book_id, out)?;
+            }
+
+            // a self closing node, like <pgterms:webpage rdf:resource="..."/>
+            Ok(Event::Empty(e)) => {
+                let current_node_name = qualified_name(reader, e.name(), false)?;
+                let attributes = node_attributes(reader, &e)?;
+                start_node(&current_node_name, &attributes, field_parsers, book_id, out)?;
                 for check in field_parsers.iter_mut() {
-                    check.start_node(current_node_name);
-                    for attr in e.attributes() {
-                        let a = attr?;
-                        let value = str::from_utf8(a.value.borrow())?;
-                        let key = str::from_utf8(a.key.0.borrow())?;
-                        check.attribute(&key, &value, out, book_id as i32)?;
-                    }
+                    check.end_node(&current_node_name);
                 }
             }
 
             Ok(Event::End(ref e)) => {
-                let current_node_name = str::from_utf8(e.name().0)?;
+                let current_node_name = qualified_name(reader, e.name(), false)?;
                 for check in field_parsers.iter_mut() {
-                    check.end_node(current_node_name);
+                    check.end_node(&current_node_name);
                 }
             }
 
@@ -120,54 +202,131 @@ pub fn parse_rdf_from_reader<R: XmlReader>(
             }
 
             Ok(Event::Eof) => break,
-            Err(e) => panic!("Error at position {}: {:?}", reader.pos(), e),
+            Err(e) => return Err(e.into()),
             _ => (),
         }
     }
-    return Ok(gutenberg_book_id);
+    if !has_root {
+        return Err(Error::InvalidRdf("no rdf:RDF node".to_string()));
+    }
+    gutenberg_book_id
+        .ok_or_else(|| Error::InvalidRdf("no pgterms:ebook node with an ebook id".to_string()))
 }
 
-fn setup_fst() -> (ParseResult, Vec<Box<dyn FSTParser>>) {
+/// the namespace of the MARC relator nodes (`marcrel:trl`, `marcrel:ill`, ...) holding the contributors
+static RELATOR_PREFIX: &str = "marcrel:";
+
+/// the qualified name and the value of an attribute
+type Attribute<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+/// the attributes of a node
+fn node_attributes<'a, R: XmlReader>(
+    reader: &R,
+    e: &'a BytesStart,
+) -> Result<Vec<Attribute<'a>>, Error> {
+    let mut attributes = Vec::new();
+    for attribute in e.attributes() {
+        let attribute = attribute?;
+        let name = qualified_name(reader, attribute.key, true)?;
+        let value = match attribute.value {
+            Cow::Borrowed(x) => Cow::Borrowed(str::from_utf8(x)?),
+            Cow::Owned(x) => Cow::Owned(String::from_utf8(x).map_err(|e| e.utf8_error())?),
+        };
+        attributes.push((name, value));
+    }
+    Ok(attributes)
+}
+
+fn start_node(
+    node_name: &str,
+    attributes: &[Attribute],
+    field_parsers: &mut [Box<dyn FSTParser>],
+    book_id: usize,
+    out: &mut ParseResult,
+) -> Result<(), Error> {
+    let attributes = attributes
+        .iter()
+        .map(|(name, value)| (name.as_ref(), value.as_ref()))
+        .collect::<Vec<_>>();
+    for check in field_parsers.iter_mut() {
+        check.start_element(node_name, &attributes, out, book_id as i32)?;
+    }
+    Ok(())
+}
+
+fn setup_fst(custom_fields: &[CustomField]) -> (ParseResult, Vec<Box<dyn FSTParser>>) {
     let mut parse_result: ParseResult = ParseResult {
         books: Vec::with_capacity(1024),
         field_dictionaries: Vec::with_capacity(1024),
         file_types_dictionary: IndexMap::<String, DictionaryItemContent>::with_capacity(1024),
         files_dictionary: IndexMap::<String, DictionaryItemContent>::with_capacity(1024),
+        agents_dictionary: IndexMap::new(),
+        subject_vocabularies: HashMap::new(),
+        custom_dictionaries: IndexMap::new(),
     };
     static TITLE_PATH: &str = "dcterms:title";
-    static TITLE_PATH_ALTERNATIVVE: &str = "dcterms:alternative";
+    static TITLE_PATH_ALTERNATIVE: &str = "dcterms:alternative";
     static SUBJECT_PATH: &str = "dcterms:subject/rdf:Description/rdf:value";
+    static LCSH_PATH: &str = "dcterms:subject/rdf:Description[dcam:memberOf/@rdf:resource='http://purl.org/dc/terms/LCSH']/rdf:value";
+    static LCC_PATH: &str = "dcterms:subject/rdf:Description[dcam:memberOf/@rdf:resource='http://purl.org/dc/terms/LCC']/rdf:value";
     static LANGUAGE_PATH: &str = "dcterms:language/rdf:Description/rdf:value";
-    static AUTHOR_PATH: &str = "dcterms:creator/pgterms:agent/pgterms:name";
-    static AUTHOR_PATH_ALTERNATIVE: &str = "dcterms:creator/pgterms:agent/pgterms:agent";
+    static AUTHOR_PATH: &str =
+        "dcterms:creator/pgterms:agent/pgterms:name|dcterms:creator/pgterms:agent/pgterms:agent";
     static BOOKSHELF_PATH: &str = "pgterms:bookshelf/rdf:Description/rdf:value";
-    static FILENODE_PATH: &str =
-        "dcterms:hasFormat/pgterms:file/dcterms:format/rdf:Description/rdf:value";
+    static FILENODE_PATH: &str = "dcterms:hasFormat/pgterms:file";
     static FILENODE_ATTRIBUTE: &str = "rdf:about";
     static PUBLISHER_PATH: &str = "dcterms:publisher";
     static RIGHTS_PATH: &str = "dcterms:rights";
     static DOWNLOADS_PATH: &str = "pgterms:downloads";
     static ISSUED_PATH: &str = "dcterms:issued";
-
-    let field_parsers = vec![
-        FSTParserOrNode::build(vec![TITLE_PATH, TITLE_PATH_ALTERNATIVVE], ParseType::Title),
-        FSTParserNode::build(SUBJECT_PATH, ParseType::Subject),
-        FSTParserNode::build(LANGUAGE_PATH, ParseType::Language),
-        FSTParserOrNode::build(
-            vec![AUTHOR_PATH, AUTHOR_PATH_ALTERNATIVE],
-            ParseType::Author,
+    static CREATOR_PATH: &str = "dcterms:creator";
+    // every node of the MARC relator namespace
+    static RELATOR_PATH: &str = "marcrel:*";
+    // these are only read right under the ebook, `dcterms:description` also describes the agent webpages
+    static DESCRIPTION_PATH: &str = "pgterms:ebook/dcterms:description";
+    static TABLE_OF_CONTENTS_PATH: &str = "pgterms:ebook/dcterms:tableOfContents";
+    static SUMMARY_PATH: &str = "pgterms:ebook/pgterms:marc520";
+    static TYPE_PATH: &str = "pgterms:ebook/dcterms:type/rdf:Description/rdf:value";
+    static LICENSE_PATH: &str = "pgterms:ebook/dcterms:license";
+    static LICENSE_ATTRIBUTE: &str = "rdf:resource";
+
+    let mut field_parsers = vec![
+        FSTParserNode::build(TITLE_PATH, ParseType::Title),
+        FSTParserSubjectNode::build(
+            SUBJECT_PATH,
+            &[("LCSH", LCSH_PATH), ("LCC", LCC_PATH)],
+            ParseType::Subject,
         ),
+        FSTParserNode::build(LANGUAGE_PATH, ParseType::Language),
+        FSTParserNode::build(AUTHOR_PATH, ParseType::Author),
         FSTParserNode::build(BOOKSHELF_PATH, ParseType::Bookshelf),
         FSTParserFileNode::build(FILENODE_PATH, FILENODE_ATTRIBUTE, ParseType::Files),
         FSTParserNode::build(PUBLISHER_PATH, ParseType::Publisher),
         FSTParserNode::build(RIGHTS_PATH, ParseType::Rights),
         FSTParserNode::build(ISSUED_PATH, ParseType::DateIssued),
         FSTParserNode::build(DOWNLOADS_PATH, ParseType::Downloads),
+        FSTParserNode::build(DESCRIPTION_PATH, ParseType::Description),
+        FSTParserNode::build(TABLE_OF_CONTENTS_PATH, ParseType::TableOfContents),
+        FSTParserNode::build(SUMMARY_PATH, ParseType::Summary),
+        FSTParserNode::build(TYPE_PATH, ParseType::Type),
+        FSTParserNode::build_attribute(LICENSE_PATH, LICENSE_ATTRIBUTE, ParseType::License),
+        FSTParserNode::build(TITLE_PATH_ALTERNATIVE, ParseType::AlternativeTitle),
+        // the parsers above are indexed by their ParseType, the ones below are not
+        FSTParserAgentNode::build(CREATOR_PATH, ParseType::Author),
+        FSTParserAgentNode::build(RELATOR_PATH, ParseType::Author),
     ];
 
-    for _ in &field_parsers {
+    for _ in 0..ParseType::COUNT {
         parse_result.field_dictionaries.push(IndexMap::new());
     }
+    // the custom dictionaries are in registration order, whatever the order their values are found in
+    for custom_field in custom_fields {
+        field_parsers.push(custom_field.build());
+        parse_result
+            .custom_dictionaries
+            .entry(custom_field.key.clone())
+            .or_default();
+    }
 
     (parse_result, field_parsers)
 }
@@ -186,167 +345,657 @@ fn get_files_from_directory(folder_path: &String) -> Result<Vec<String>, Error>
     Ok(paths)
 }
 
+/// What a lenient parse did: how many rdfs were parsed and which ones were skipped
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    pub parsed: usize,
+    pub skipped: Vec<SkippedRdf>,
+}
+
+/// An rdf left out of a lenient parse
+#[derive(Debug, Clone)]
+pub struct SkippedRdf {
+    /// the file path, or `content #<index>` when parsing content
+    pub file: String,
+    /// byte position in the file where the error was found
+    pub position: usize,
+    pub reason: String,
+}
+
+/// How the rdfs are parsed
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// skip the invalid rdfs and list them in the `ParseReport` instead of failing
+    pub lenient: bool,
+    /// number of parsing threads, 0 uses every core. The result is the same for any number of threads
+    pub threads: usize,
+    /// extra fields to parse, see `CustomField`
+    pub custom_fields: Vec<CustomField>,
+}
+
+impl ParseOptions {
+    fn thread_count(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |x| x.get()),
+            threads => threads,
+        }
+    }
+}
+
+/// Parses every rdf in the folder, the first invalid rdf stops the parse with `Error::InvalidRdfFile`
 pub fn parse_rdfs_from_folder(
     folder: &String,
     display_progress_bar: bool,
 ) -> Result<ParseResult, Error> {
+    Ok(parse_rdfs_from_folder_with(folder, &ParseOptions::default(), display_progress_bar)?.0)
+}
+
+/// Like `parse_rdfs_from_folder` but the invalid rdfs are skipped and listed in the report
+pub fn parse_rdfs_from_folder_lenient(
+    folder: &String,
+    display_progress_bar: bool,
+) -> Result<(ParseResult, ParseReport), Error> {
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    parse_rdfs_from_folder_with(folder, &options, display_progress_bar)
+}
+
+pub fn parse_rdfs_from_folder_with(
+    folder: &String,
+    options: &ParseOptions,
+    display_progress_bar: bool,
+) -> Result<(ParseResult, ParseReport), Error> {
     let paths = get_files_from_directory(folder)?;
-    parse_rdfs(&paths, false, display_progress_bar)
+    let rdfs = paths.iter().map(|x| Rdf::File(x)).collect::<Vec<Rdf>>();
+    let pb = progress_bar(Some(rdfs.len()), display_progress_bar)?;
+    let result = parse_rdfs(&rdfs, options, pb.as_ref());
+    if let Some(p) = pb {
+        p.finish();
+    }
+    result
 }
 
 pub fn parse_rdfs_from_content(
-    rdfs_content: &Vec<String>,
+    rdfs_content: &[String],
     display_progress_bar: bool,
 ) -> Result<ParseResult, Error> {
-    parse_rdfs(&rdfs_content, true, display_progress_bar)
+    let options = ParseOptions::default();
+    Ok(parse_rdfs_from_content_with(rdfs_content, &options, display_progress_bar)?.0)
 }
 
-fn parse_rdfs(
-    param: &Vec<String>,
-    is_content: bool,
-    show_progress_bar: bool,
-) -> Result<ParseResult, Error> {
-    let (mut parse_result, mut field_parsers) = setup_fst();
-
-    let mut pb: Option<ProgressBar> = None;
-    if show_progress_bar {
-        let pb_new = ProgressBar::new(param.len() as u64);
-        pb_new.set_style(
-            ProgressStyle::with_template(
-                "{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.white/blue}] ({eta})",
-            )?
-            .progress_chars("█  "),
-        );
-        pb_new.set_message(format!("Parsing rdf"));
-        pb = Some(pb_new);
-    }
-    let mut idx = 0;
-    for file_path in param {
-        idx = idx + 1;
-
-        if let Some(p) = &mut pb {
-            p.set_position(idx as u64);
+pub fn parse_rdfs_from_content_lenient(
+    rdfs_content: &[String],
+    display_progress_bar: bool,
+) -> Result<(ParseResult, ParseReport), Error> {
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    parse_rdfs_from_content_with(rdfs_content, &options, display_progress_bar)
+}
+
+pub fn parse_rdfs_from_content_with(
+    rdfs_content: &[String],
+    options: &ParseOptions,
+    display_progress_bar: bool,
+) -> Result<(ParseResult, ParseReport), Error> {
+    let rdfs = rdfs_content
+        .iter()
+        .enumerate()
+        .map(|(idx, content)| Rdf::Content {
+            name: format!("content #{}", idx),
+            content,
+        })
+        .collect::<Vec<Rdf>>();
+    let pb = progress_bar(Some(rdfs.len()), display_progress_bar)?;
+    let result = parse_rdfs(&rdfs, options, pb.as_ref());
+    if let Some(p) = pb {
+        p.finish();
+    }
+    result
+}
+
+/// number of archive entries held in memory and parsed together
+const ARCHIVE_BATCH_SIZE: usize = 4096;
+
+/// Parses the rdfs straight from the gutenberg `.tar.bz2` archive, nothing is written to disk.
+/// The entries are read in batches that are parsed like `parse_rdfs_from_content_with`,
+/// errors and skipped rdfs are reported with the path of the entry in the archive.
+pub fn parse_rdfs_from_archive(
+    archive_path: &str,
+    options: &ParseOptions,
+    display_progress_bar: bool,
+) -> Result<(ParseResult, ParseReport), Error> {
+    parse_rdfs_from_tar_bz(File::open(archive_path)?, options, display_progress_bar)
+}
+
+/// Like `parse_rdfs_from_archive` for any reader of a `.tar.bz2` archive
+pub fn parse_rdfs_from_tar_bz<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+    display_progress_bar: bool,
+) -> Result<(ParseResult, ParseReport), Error> {
+    let pb = progress_bar(None, display_progress_bar)?;
+    let mut archive = Archive::new(BzDecoder::new(reader));
+    let mut parse_result: Option<ParseResult> = None;
+    let mut report = ParseReport::default();
+    let mut batch: Vec<(String, String)> = Vec::with_capacity(ARCHIVE_BATCH_SIZE);
+    let mut entries = archive.entries()?;
+    loop {
+        let entry = entries.next();
+        let done = entry.is_none();
+        if let Some(entry) = entry {
+            match read_rdf_entry(entry)? {
+                Some((name, Ok(content))) => batch.push((name, content)),
+                Some((name, Err(e))) => skip_or_fail(&mut report, options.lenient, name, 0, e)?,
+                None => continue,
+            }
+            if batch.len() < ARCHIVE_BATCH_SIZE {
+                continue;
+            }
         }
+        if !batch.is_empty() {
+            let rdfs = batch
+                .iter()
+                .map(|(name, content)| Rdf::Content {
+                    name: name.clone(),
+                    content,
+                })
+                .collect::<Vec<Rdf>>();
+            let (partial, partial_report) = parse_rdfs(&rdfs, options, pb.as_ref())?;
+            match &mut parse_result {
+                Some(result) => result.append(partial),
+                None => parse_result = Some(partial),
+            }
+            report.parsed += partial_report.parsed;
+            report.skipped.extend(partial_report.skipped);
+            batch.clear();
+        }
+        if done {
+            break;
+        }
+    }
+    if let Some(p) = pb {
+        p.finish();
+    }
+    Ok((
+        parse_result.unwrap_or_else(|| setup_fst(&options.custom_fields).0),
+        report,
+    ))
+}
 
-        let gutenberg_book_id;
-        let mut reader;
-        let data;
-        if is_content {
-            reader = Reader::from_str(file_path);
-        } else {
-            data = fs::read_to_string(file_path)?;
-            reader = Reader::from_str(data.as_str());
+/// Parses the rdfs of a folder one at a time and hands every book to `on_book`, resolved to a `BookRecord`
+/// with the custom fields of the options. Only the book being parsed is kept in memory and `threads` is ignored.
+/// In lenient mode the rdfs that cannot be parsed are skipped and reported, otherwise the first one fails the parse.
+/// An error returned by `on_book` stops the parse.
+pub fn stream_books_from_folder<F>(
+    folder: &String,
+    options: &ParseOptions,
+    on_book: F,
+) -> Result<ParseReport, Error>
+where
+    F: FnMut(BookRecord) -> Result<(), Error>,
+{
+    let mut stream = BookStream::new(options, on_book);
+    for path in get_files_from_directory(folder)? {
+        stream.parse(&Rdf::File(&path))?;
+    }
+    Ok(stream.report)
+}
+
+/// Like `stream_books_from_folder` for rdfs given by their content
+pub fn stream_books_from_content<F>(
+    rdfs_content: &[String],
+    options: &ParseOptions,
+    on_book: F,
+) -> Result<ParseReport, Error>
+where
+    F: FnMut(BookRecord) -> Result<(), Error>,
+{
+    let mut stream = BookStream::new(options, on_book);
+    for (idx, content) in rdfs_content.iter().enumerate() {
+        stream.parse(&Rdf::Content {
+            name: format!("content #{}", idx),
+            content,
+        })?;
+    }
+    Ok(stream.report)
+}
+
+/// Like `stream_books_from_folder` for the rdfs of a `.tar.bz2` archive, read without unpacking it
+pub fn stream_books_from_tar_bz<R, F>(
+    reader: R,
+    options: &ParseOptions,
+    on_book: F,
+) -> Result<ParseReport, Error>
+where
+    R: Read,
+    F: FnMut(BookRecord) -> Result<(), Error>,
+{
+    let mut stream = BookStream::new(options, on_book);
+    let mut archive = Archive::new(BzDecoder::new(reader));
+    for entry in archive.entries()? {
+        match read_rdf_entry(entry)? {
+            Some((name, Ok(content))) => stream.parse(&Rdf::Content {
+                name,
+                content: &content,
+            })?,
+            Some((name, Err(e))) => skip_or_fail(&mut stream.report, options.lenient, name, 0, e)?,
+            None => {}
         }
+    }
+    Ok(stream.report)
+}
 
-        gutenberg_book_id =
-            parse_rdf_from_reader(&mut reader, &mut field_parsers, idx, &mut parse_result)?;
+/// Parses rdfs one by one, the dictionaries are emptied after every book
+struct BookStream<F> {
+    parse_result: ParseResult,
+    field_parsers: Vec<Box<dyn FSTParser>>,
+    lenient: bool,
+    report: ParseReport,
+    on_book: F,
+}
 
-        let publisher_id = match field_parsers[ParseType::Publisher as usize].get_result() {
-            Ok(item) => item.item_links[0] as i32 + 1,
-            Err(_) => -1,
-        };
+impl<F> BookStream<F>
+where
+    F: FnMut(BookRecord) -> Result<(), Error>,
+{
+    fn new(options: &ParseOptions, on_book: F) -> BookStream<F> {
+        let (parse_result, field_parsers) = setup_fst(&options.custom_fields);
+        BookStream {
+            parse_result,
+            field_parsers,
+            lenient: options.lenient,
+            report: ParseReport::default(),
+            on_book,
+        }
+    }
 
-        let title_id = match field_parsers[ParseType::Title as usize].get_result() {
-            Ok(item) => item.item_links[0] as i32,
-            Err(_) => -1,
+    fn parse(&mut self, rdf: &Rdf) -> Result<(), Error> {
+        // the parse result is empty before every book, so the book is always the first one
+        let mark = self.parse_result.mark();
+        let parsed = read_book(rdf, &mut self.field_parsers, 1, &mut self.parse_result);
+        for parser in &mut self.field_parsers {
+            parser.reset();
+        }
+        let record = match parsed {
+            Ok((mut book, agents)) => {
+                add_book_agents(&mut self.parse_result, &mut book, agents, 1);
+                Ok(BookRecord::from_parsed(&self.parse_result, &book))
+            }
+            Err(e) => Err(e),
         };
+        self.parse_result.rollback(mark, 1);
+        match record {
+            Ok(record) => {
+                self.report.parsed += 1;
+                (self.on_book)(record)
+            }
+            Err((position, e)) => skip_or_fail(
+                &mut self.report,
+                self.lenient,
+                rdf.name().to_string(),
+                position,
+                e,
+            ),
+        }
+    }
+}
 
-        let rights_id = match field_parsers[ParseType::Rights as usize].get_result() {
-            Ok(item) => item.item_links[0] as i32 + 1,
-            Err(_) => -1,
-        };
+/// the path of an rdf in the archive and its content
+type ArchiveRdf = (String, Result<String, Error>);
 
-        let date_id = match field_parsers[ParseType::DateIssued as usize].get_result() {
-            Ok(item) => item.item_links[0] as i32,
-            Err(_) => -1,
-        };
+/// reads an archive entry, `None` when it is not an rdf file
+fn read_rdf_entry<R: Read>(entry: std::io::Result<Entry<R>>) -> Result<Option<ArchiveRdf>, Error> {
+    let mut entry = entry?;
+    let name = entry.path()?.display().to_string();
+    if !entry.header().entry_type().is_file() || !name.ends_with(".rdf") {
+        return Ok(None);
+    }
+    let mut content = String::new();
+    let content = entry
+        .read_to_string(&mut content)
+        .map(|_| content)
+        .map_err(Error::from);
+    Ok(Some((name, content)))
+}
 
-        let down_id = match field_parsers[ParseType::Downloads as usize].get_result() {
-            Ok(item) => item.item_links[0] as i32,
-            Err(_) => -1,
-        };
+/// records an rdf that could not be parsed in lenient mode, otherwise returns its error
+fn skip_or_fail(
+    report: &mut ParseReport,
+    lenient: bool,
+    file: String,
+    position: usize,
+    e: Error,
+) -> Result<(), Error> {
+    if !lenient {
+        return Err(Error::InvalidRdfFile {
+            file,
+            position,
+            reason: e.to_string(),
+        });
+    }
+    report.skipped.push(SkippedRdf {
+        file,
+        position,
+        reason: e.to_string(),
+    });
+    Ok(())
+}
 
-        let language_ids = field_parsers[ParseType::Language as usize]
-            .get_result()
-            .unwrap_or(&ParseItemResult {
-                item_links: Vec::new(),
-            })
-            .item_links
-            .clone();
-
-        let subject_ids = field_parsers[ParseType::Subject as usize]
-            .get_result()
-            .unwrap_or(&ParseItemResult {
-                item_links: Vec::new(),
-            })
-            .item_links
-            .clone();
-
-        let author_ids = field_parsers[ParseType::Author as usize]
-            .get_result()
-            .unwrap_or(&ParseItemResult {
-                item_links: Vec::new(),
-            })
-            .item_links
-            .clone();
-
-        let bookshelf_ids = field_parsers[ParseType::Bookshelf as usize]
-            .get_result()
-            .unwrap_or(&ParseItemResult {
-                item_links: Vec::new(),
-            })
-            .item_links
-            .clone();
-        let mut date_issued = "".to_string();
-        if let Some(dict_value) = parse_result.field_dictionaries[ParseType::DateIssued as usize]
-            .get_index(date_id as usize)
-        {
-            date_issued = dict_value.0.to_string();
+/// An rdf to parse, a file or its content
+enum Rdf<'a> {
+    File(&'a str),
+    Content { name: String, content: &'a str },
+}
+
+impl Rdf<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Rdf::File(path) => path,
+            Rdf::Content { name, .. } => name,
         }
+    }
+}
 
-        let mut num_downloads = 0;
-        if let Some(dict_value) = parse_result.field_dictionaries[ParseType::Downloads as usize]
-            .get_index(down_id as usize)
-        {
-            match dict_value.0.parse::<i32>() {
-                Ok(val) => {
-                    num_downloads = val;
-                }
-                Err(e) => {
-                    return Err(Error::InvalidRdf(
-                        format!(
-                            "bad num downloads parse for book {}, {}, {}",
-                            gutenberg_book_id,
-                            e.to_string(),
-                            dict_value.0
-                        )
-                        .to_string(),
-                    ));
-                }
-            }
+fn progress_bar(len: Option<usize>, show_progress_bar: bool) -> Result<Option<ProgressBar>, Error> {
+    if !show_progress_bar {
+        return Ok(None);
+    }
+    let pb = match len {
+        Some(len) => {
+            let pb = ProgressBar::new(len as u64);
+            pb.set_style(
+                ProgressStyle::with_template(
+                    "{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.white/blue}] ({eta})",
+                )?
+                .progress_chars("█  "),
+            );
+            pb
+        }
+        // the number of entries of an archive is not known before reading it
+        None => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(ProgressStyle::with_template(
+                "{msg}\n{spinner:.green} [{elapsed_precise}] {pos} rdfs ({per_sec})",
+            )?);
+            pb
         }
+    };
+    pb.set_message("Parsing rdf".to_string());
+    Ok(Some(pb))
+}
 
-        parse_result.books.push(Book {
-            publisher_id,
-            title_id,
-            rights_id,
-            gutenberg_book_id,
-            date_issued,
-            num_downloads,
-            language_ids,
-            subject_ids,
-            author_ids,
-            bookshelf_ids,
-            files: field_parsers[ParseType::Files as usize].get_files()?,
-        });
+/// splits the rdfs in one contiguous chunk per thread, the partial results are appended in chunk order
+/// so the dictionary ids are the ones of a sequential parse
+fn parse_rdfs(
+    rdfs: &[Rdf],
+    options: &ParseOptions,
+    pb: Option<&ProgressBar>,
+) -> Result<(ParseResult, ParseReport), Error> {
+    let threads = options.thread_count().min(rdfs.len()).max(1);
+    let chunk_size = (rdfs.len() + threads - 1) / threads;
+    let partials = match threads {
+        1 => vec![parse_chunk(rdfs, options, pb)],
+        _ => std::thread::scope(|scope| {
+            let handles = rdfs
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || parse_chunk(chunk, options, pb)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|x| match x.join() {
+                    Ok(partial) => partial,
+                    Err(_) => Err(Error::InvalidRdf("a parsing thread panicked".to_string())),
+                })
+                .collect::<Vec<_>>()
+        }),
+    };
+
+    let mut parse_result: Option<ParseResult> = None;
+    let mut report = ParseReport::default();
+    for partial in partials {
+        let (partial_result, partial_report) = partial?;
+        match &mut parse_result {
+            Some(result) => result.append(partial_result),
+            None => parse_result = Some(partial_result),
+        }
+        report.parsed += partial_report.parsed;
+        report.skipped.extend(partial_report.skipped);
+    }
+    Ok((
+        parse_result.unwrap_or_else(|| setup_fst(&options.custom_fields).0),
+        report,
+    ))
+}
+
+/// parses the rdfs with a parser set of its own
+fn parse_chunk(
+    rdfs: &[Rdf],
+    options: &ParseOptions,
+    pb: Option<&ProgressBar>,
+) -> Result<(ParseResult, ParseReport), Error> {
+    let (mut parse_result, mut field_parsers) = setup_fst(&options.custom_fields);
+    let mut report = ParseReport::default();
+    for rdf in rdfs {
+        if let Some(p) = pb {
+            p.inc(1);
+        }
+
+        // book links point at the position of the book in `ParseResult::books` (1 based)
+        let book_id = parse_result.books.len() + 1;
+        let mark = parse_result.mark();
+        let parsed = read_book(rdf, &mut field_parsers, book_id, &mut parse_result);
         for parser in &mut field_parsers {
             parser.reset();
         }
+        match parsed {
+            Ok((mut book, agents)) => {
+                add_book_agents(&mut parse_result, &mut book, agents, book_id);
+                parse_result.books.push(book);
+                report.parsed += 1;
+            }
+            Err((position, e)) => {
+                parse_result.rollback(mark, book_id);
+                skip_or_fail(
+                    &mut report,
+                    options.lenient,
+                    rdf.name().to_string(),
+                    position,
+                    e,
+                )?;
+            }
+        }
     }
+    Ok((parse_result, report))
+}
 
-    if let Some(p) = pb {
-        p.finish();
+/// parses one rdf, errors come with the byte position they were found at
+/// a parsed book and the agents it describes with the node they were found under
+type ParsedBook = (Book, Vec<(String, Agent)>);
+
+fn read_book(
+    rdf: &Rdf,
+    field_parsers: &mut Vec<Box<dyn FSTParser>>,
+    book_id: usize,
+    parse_result: &mut ParseResult,
+) -> Result<ParsedBook, (usize, Error)> {
+    let data;
+    let mut reader = match rdf {
+        Rdf::Content { content, .. } => NsReader::from_str(content),
+        Rdf::File(path) => {
+            data = fs::read_to_string(path).map_err(|e| (0, e.into()))?;
+            NsReader::from_str(data.as_str())
+        }
+    };
+    parse_book(&mut reader, field_parsers, book_id, parse_result).map_err(|e| (reader.pos(), e))
+}
+
+/// parses a book, its agents are left out of the parse result until `add_book_agents`
+fn parse_book<R: XmlReader>(
+    reader: &mut R,
+    field_parsers: &mut Vec<Box<dyn FSTParser>>,
+    book_id: usize,
+    parse_result: &mut ParseResult,
+) -> Result<ParsedBook, Error> {
+    let gutenberg_book_id = parse_rdf_from_reader(reader, field_parsers, book_id, parse_result)?;
+    let files = field_parsers[ParseType::Files as usize].get_files()?;
+
+    let publisher_id = match field_parsers[ParseType::Publisher as usize].get_result() {
+        Ok(item) => item.item_links.first().map_or(-1, |x| *x as i32 + 1),
+        Err(_) => -1,
+    };
+
+    let title_id = match field_parsers[ParseType::Title as usize].get_result() {
+        Ok(item) => item.item_links.first().map_or(-1, |x| *x as i32),
+        Err(_) => -1,
+    };
+
+    let title_ids = field_parsers[ParseType::Title as usize].get_all_results();
+    let alternative_title_ids =
+        field_parsers[ParseType::AlternativeTitle as usize].get_all_results();
+
+    let rights_id = match field_parsers[ParseType::Rights as usize].get_result() {
+        Ok(item) => item.item_links.first().map_or(-1, |x| *x as i32 + 1),
+        Err(_) => -1,
+    };
+
+    let date_id = match field_parsers[ParseType::DateIssued as usize].get_result() {
+        Ok(item) => item.item_links.first().map_or(-1, |x| *x as i32),
+        Err(_) => -1,
+    };
+
+    let down_id = match field_parsers[ParseType::Downloads as usize].get_result() {
+        Ok(item) => item.item_links.first().map_or(-1, |x| *x as i32),
+        Err(_) => -1,
+    };
+
+    let language_ids = field_parsers[ParseType::Language as usize]
+        .get_result()
+        .unwrap_or(&ParseItemResult {
+            item_links: Vec::new(),
+        })
+        .item_links
+        .clone();
+
+    let subject_ids = field_parsers[ParseType::Subject as usize]
+        .get_result()
+        .unwrap_or(&ParseItemResult {
+            item_links: Vec::new(),
+        })
+        .item_links
+        .clone();
+
+    let author_ids = field_parsers[ParseType::Author as usize]
+        .get_result()
+        .unwrap_or(&ParseItemResult {
+            item_links: Vec::new(),
+        })
+        .item_links
+        .clone();
+
+    let bookshelf_ids = field_parsers[ParseType::Bookshelf as usize]
+        .get_result()
+        .unwrap_or(&ParseItemResult {
+            item_links: Vec::new(),
+        })
+        .item_links
+        .clone();
+    let first_value = |parse_type: ParseType| -> Option<String> {
+        let item = field_parsers[parse_type as usize].get_result().ok()?;
+        parse_result.field_dictionaries[parse_type as usize]
+            .get_index(*item.item_links.first()?)
+            .map(|x| x.0.to_string())
+    };
+    let book_type = first_value(ParseType::Type);
+    let license = first_value(ParseType::License);
+
+    let mut date_issued = "".to_string();
+    if let Some(dict_value) =
+        parse_result.field_dictionaries[ParseType::DateIssued as usize].get_index(date_id as usize)
+    {
+        date_issued = dict_value.0.to_string();
     }
 
-    Ok(parse_result)
+    let mut num_downloads = 0;
+    if let Some(dict_value) =
+        parse_result.field_dictionaries[ParseType::Downloads as usize].get_index(down_id as usize)
+    {
+        match dict_value.0.parse::<i32>() {
+            Ok(val) => {
+                num_downloads = val;
+            }
+            Err(e) => {
+                return Err(Error::InvalidRdf(
+                    format!(
+                        "bad num downloads parse for book {}, {}, {}",
+                        gutenberg_book_id, e, dict_value.0
+                    )
+                    .to_string(),
+                ));
+            }
+        }
+    }
+
+    let agents = field_parsers
+        .iter()
+        .flat_map(|x| x.get_agents())
+        .collect::<Vec<(String, Agent)>>();
+
+    let mut custom_fields: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for parser in field_parsers.iter() {
+        if let Some(key) = parser.get_custom_key() {
+            let values = parser.get_all_results();
+            if !values.is_empty() {
+                custom_fields
+                    .entry(key.to_string())
+                    .or_default()
+                    .extend(values);
+            }
+        }
+    }
+
+    let book = Book {
+        publisher_id,
+        title_id,
+        title_ids,
+        alternative_title_ids,
+        rights_id,
+        gutenberg_book_id,
+        date_issued,
+        num_downloads,
+        language_ids,
+        subject_ids,
+        author_ids,
+        agent_ids: Vec::new(),
+        contributors: Vec::new(),
+        bookshelf_ids,
+        description_ids: field_parsers[ParseType::Description as usize].get_all_results(),
+        table_of_contents_ids: field_parsers[ParseType::TableOfContents as usize].get_all_results(),
+        summary_ids: field_parsers[ParseType::Summary as usize].get_all_results(),
+        book_type,
+        license,
+        custom_fields,
+        files,
+    };
+    Ok((book, agents))
+}
+
+/// merges the agents of an accepted book in the parse result and links them to the book,
+/// so a rejected book never changes what the other books said about an agent
+fn add_book_agents(
+    parse_result: &mut ParseResult,
+    book: &mut Book,
+    agents: Vec<(String, Agent)>,
+    book_id: usize,
+) {
+    for (node, agent) in agents {
+        let agent_id = parse_result.add_agent(agent, book_id as i32);
+        match node.strip_prefix(RELATOR_PREFIX) {
+            Some(role) => book.contributors.push((agent_id, role.to_string())),
+            None => book.agent_ids.push(agent_id),
+        }
+    }
 }
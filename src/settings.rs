@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::fst_parser::CustomField;
 use serde_json::Value;
 use std::path::Path;
 
@@ -16,12 +17,24 @@ pub struct GutenbergCacheSettings {
     pub text_files_cache_folder: String,
     /// this will make the cache in memory (it will not save it on disk), it is used in tests
     pub db_in_memory: bool,
+    /// skip the rdfs that cannot be parsed instead of failing the whole cache build
+    pub lenient_parsing: bool,
+    /// number of threads used to parse the rdfs, 0 uses every core
+    pub parser_threads: usize,
+    /// unpack the rdf tar archive to `cache_rdf_unpack_directory` before parsing, otherwise the rdfs are read straight from the archive
+    pub unpack_rdf_archive: bool,
+    /// extra fields to parse and store in the cache, see `CustomField`
+    pub custom_fields: Vec<CustomField>,
 }
 
 impl Default for GutenbergCacheSettings {
     fn default() -> GutenbergCacheSettings {
         GutenbergCacheSettings {
             db_in_memory: false,
+            lenient_parsing: false,
+            parser_threads: 0,
+            unpack_rdf_archive: false,
+            custom_fields: Vec::new(),
             text_files_cache_folder: "text_cache".to_string(),
             cache_rdf_download_link: "https://www.gutenberg.org/cache/epub/feeds/rdf-files.tar.bz2"
                 .to_string(),
@@ -78,6 +91,48 @@ impl GutenbergCacheSettings {
                 ));
             }
         }
+        if let Some(field) = json.get("LenientParsing") {
+            if let Some(v) = field.as_bool() {
+                settings.lenient_parsing = v;
+            } else {
+                return Err(Error::InvalidSettingsField("LenientParsing".to_string()));
+            }
+        }
+        if let Some(field) = json.get("ParserThreads") {
+            if let Some(v) = field.as_u64() {
+                settings.parser_threads = v as usize;
+            } else {
+                return Err(Error::InvalidSettingsField("ParserThreads".to_string()));
+            }
+        }
+        if let Some(field) = json.get("UnpackRDFArchive") {
+            if let Some(v) = field.as_bool() {
+                settings.unpack_rdf_archive = v;
+            } else {
+                return Err(Error::InvalidSettingsField("UnpackRDFArchive".to_string()));
+            }
+        }
+        // [{"Key": "loc", "Path": "pgterms:ebook/pgterms:marc010"}, {"Key": ..., "Path": ..., "Attribute": ...}]
+        if let Some(field) = json.get("CustomFields") {
+            let invalid = || Error::InvalidSettingsField("CustomFields".to_string());
+            for item in field.as_array().ok_or_else(invalid)? {
+                let key = item
+                    .get("Key")
+                    .and_then(|x| x.as_str())
+                    .ok_or_else(invalid)?;
+                let path = item
+                    .get("Path")
+                    .and_then(|x| x.as_str())
+                    .ok_or_else(invalid)?;
+                let custom_field = match item.get("Attribute") {
+                    Some(attribute) => {
+                        CustomField::attribute(key, path, attribute.as_str().ok_or_else(invalid)?)
+                    }
+                    None => CustomField::path(key, path),
+                };
+                settings.custom_fields.push(custom_field?);
+            }
+        }
         Ok(settings)
     }
 }
@@ -1,22 +1,109 @@
+use crate::book::Book;
+use crate::book_query::{BookQuery, CursorKey, QueryCursor, QueryField, QueryPage};
+use crate::book_record::{
+    normalize_date, parse_author_name, split_title, AuthorRecord, BookRecord, ContributorRecord,
+    FileRecord,
+};
 use crate::error::Error;
-use crate::fst_parser::DictionaryItemContent;
 use crate::fst_parser::ParseResult;
+use crate::fst_parser::{Agent, DictionaryItemContent};
 use crate::fst_parser_type::ParseType;
+use crate::language::{find_language, language_code, macrolanguage_code};
 use crate::settings::GutenbergCacheSettings;
 use indexmap::IndexMap;
 use indicatif::{ProgressBar, ProgressStyle};
 use num_traits::FromPrimitive;
-use rusqlite::Connection;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, Connection};
+use serde::Serialize;
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 
+/// The version of the cache database schema built by this version of the crate
+pub const SCHEMA_VERSION: i32 = 14;
+
+/// `MIGRATIONS[n]` upgrades a cache from schema version `n + 1` to `n + 2`
+const MIGRATIONS: [&str; (SCHEMA_VERSION - 1) as usize] = [
+    include_str!("gutenbergindex_migration_2.db.sql"),
+    include_str!("gutenbergindex_migration_3.db.sql"),
+    include_str!("gutenbergindex_migration_4.db.sql"),
+    include_str!("gutenbergindex_migration_5.db.sql"),
+    include_str!("gutenbergindex_migration_6.db.sql"),
+    include_str!("gutenbergindex_migration_7.db.sql"),
+    include_str!("gutenbergindex_migration_8.db.sql"),
+    include_str!("gutenbergindex_migration_9.db.sql"),
+    include_str!("gutenbergindex_migration_10.db.sql"),
+    include_str!("gutenbergindex_migration_11.db.sql"),
+    include_str!("gutenbergindex_migration_12.db.sql"),
+    include_str!("gutenbergindex_migration_13.db.sql"),
+    include_str!("gutenbergindex_migration_14.db.sql"),
+];
+
+/// the migrations up to this schema version leave data that only the catalog can fill in,
+/// a cache migrated from an older version is stale until it is rebuilt or updated
+const STALE_BEFORE_VERSION: i32 = 9;
+
 pub struct SQLiteCache {
     pub connection: Box<Connection>,
 }
-struct HelperQuery<'a> {
-    tables: Vec<&'a str>,
-    query_struct: Vec<&'a str>,
+
+/// A full text search hit
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub gutenberg_id: i32,
+    /// bm25 rank, lower is better
+    pub rank: f64,
+    /// the best matching fragment of the titles, authors, subjects, bookshelves or descriptions with the matches in [brackets]
+    pub snippet: String,
+}
+
+/// The number of books having a value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// Counts over the whole catalog (or a filtered subset of it)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogStats {
+    pub books: i64,
+    pub authors: i64,
+    pub subjects: i64,
+    pub languages: i64,
+    pub bookshelves: i64,
+    pub files: i64,
+    pub total_downloads: i64,
+    pub first_issued: Option<String>,
+    pub last_issued: Option<String>,
+}
+
+/// What an incremental update changed, by gutenberg id
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UpdateReport {
+    pub added: Vec<i32>,
+    pub updated: Vec<i32>,
+    pub removed: Vec<i32>,
+    pub unchanged: usize,
+}
+
+/// A catalog version applied to the cache, either by building it or by an update
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogSnapshot {
+    pub id: i64,
+    /// UTC time of the update, `YYYY-MM-DD HH:MM:SS`
+    pub created_at: String,
+    /// where the catalog came from (ex: the rdf archive link), if known
+    pub source: Option<String>,
+    /// the newest issue date in the applied catalog, a simple way to tell how recent it is
+    pub last_issued: Option<String>,
+    /// number of books in the cache after the update
+    pub books: i64,
+    pub added: i64,
+    pub updated: i64,
+    pub removed: i64,
 }
 
 impl SQLiteCache {
@@ -33,151 +120,605 @@ impl SQLiteCache {
         Ok(results)
     }
 
-    pub fn query(&mut self, json: &Value) -> Result<Vec<i32>, Error> {
-        let mut helpers = Vec::new();
-
-        if let Some(field) = json.get("language") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["languages", "book_languages"],
-                    query_struct: vec![
-                        "languages.id = book_languages.languageid AND books.id = book_languages.bookid",
-                        "languages.name",
-                        field_value,
-                    ],
-                });
-            } else {
-                return Err(Error::InvalidQuery("language must be a string".to_string()));
-            }
-        }
-        if let Some(field) = json.get("author") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["authors", "book_authors"],
-                    query_struct: vec![
-                        "authors.id = book_authors.authorid and books.id = book_authors.bookid",
-                        "authors.name",
-                        field_value,
-                    ],
+    /// Returns everything the cache knows about a book, or None if the gutenberg id is not in the cache
+    pub fn get_book(&mut self, gutenberg_id: i32) -> Result<Option<BookRecord>, Error> {
+        let book = self.connection.query_row(
+            "SELECT books.id, books.numdownloads, books.dateissued, publishers.name, rights.name, books.type, books.license,
+            books.title, books.subtitle FROM books LEFT JOIN publishers ON publishers.id = books.publisherid
+            LEFT JOIN rights ON rights.id = books.rightsid
+            WHERE books.gutenbergbookid = ?",
+            (gutenberg_id,),
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<i32>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                    row.get::<_, Option<String>>(8)?,
+                ))
+            },
+        );
+        let (
+            book_id,
+            num_downloads,
+            date_issued,
+            publisher,
+            rights,
+            book_type,
+            license,
+            title,
+            subtitle,
+        ) = match book {
+            Ok(book) => book,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut downloads = Vec::new();
+        {
+            let mut stmt = self.connection.prepare_cached(
+                "SELECT downloadlinks.id, downloadlinks.name, downloadlinkstype.name, downloadlinks.extent, downloadlinks.modified
+                FROM downloadlinks LEFT JOIN downloadlinkstype ON downloadlinkstype.id = downloadlinks.downloadtypeid
+                WHERE downloadlinks.bookid = ? ORDER BY downloadlinks.id",
+            )?;
+            let mut format_stmt = self.connection.prepare_cached(
+                "SELECT downloadlinkstype.name FROM downloadlink_formats, downloadlinkstype
+                WHERE downloadlinkstype.id = downloadlink_formats.downloadtypeid AND downloadlink_formats.downloadlinkid = ?
+                ORDER BY downloadlink_formats.rowid",
+            )?;
+            let mut rows = stmt.query((book_id,))?;
+            while let Some(row) = rows.next()? {
+                let link_id: i64 = row.get(0)?;
+                let link: String = row.get(1)?;
+                let file_type: Option<String> = row.get(2)?;
+                files
+                    .entry(file_type.unwrap_or_default())
+                    .or_default()
+                    .push(link.clone());
+                let formats = format_stmt
+                    .query_map((link_id,), |x| x.get(0))?
+                    .collect::<Result<Vec<String>, _>>()?;
+                downloads.push(FileRecord {
+                    url: link,
+                    formats,
+                    extent: row.get(3)?,
+                    modified: row.get(4)?,
                 });
-            } else {
-                return Err(Error::InvalidQuery("author must be a string".to_string()));
             }
         }
-        if let Some(field) = json.get("title") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["titles"],
-                    query_struct: vec!["titles.bookid = books.id", "titles.name", field_value],
-                });
-            } else {
-                return Err(Error::InvalidQuery("title must a string".to_string()));
-            }
-        }
-        if let Some(field) = json.get("subject") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["subjects", "book_subjects"],
-                    query_struct: vec![
-                        "subjects.id = book_subjects.bookid and books.id = book_subjects.subjectid ",
-                        "subjects.name",
-                        field_value,
-                    ],
-                });
-            } else {
-                return Err(Error::InvalidQuery("subject must a string".to_string()));
-            }
-        }
-        if let Some(field) = json.get("publisher") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["publishers"],
-                    query_struct: vec![
-                        "publishers.id = books.publisherid",
-                        "publishers.name",
-                        field_value,
-                    ],
-                });
-            } else {
-                return Err(Error::InvalidQuery("publisher must a string".to_string()));
-            }
-        }
-        if let Some(field) = json.get("bookshelve") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["bookshelves, book_bookshelves"],
-                    query_struct: vec![
-                        "bookshelves.id = book_bookshelves.bookshelfid AND books.id = book_bookshelves.bookid",
-                        "bookshelves.name",
-                        field_value,
-                    ],
-                });
-            } else {
-                return Err(Error::InvalidQuery("bookshelve must a string".to_string()));
+
+        let mut custom_fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        {
+            let mut stmt = self.connection.prepare_cached(
+                "SELECT customfields.field, customfields.value FROM customfields, book_customfields
+                WHERE customfields.id = book_customfields.customfieldid AND book_customfields.bookid = ?
+                ORDER BY book_customfields.rowid",
+            )?;
+            let mut rows = stmt.query((book_id,))?;
+            while let Some(row) = rows.next()? {
+                custom_fields
+                    .entry(row.get(0)?)
+                    .or_default()
+                    .push(row.get(1)?);
             }
         }
-        if let Some(field) = json.get("rights") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery {
-                    tables: vec!["rights"],
-                    query_struct: vec!["rights.id = books.rightsid", "rights.name", field_value],
-                });
-            } else {
-                return Err(Error::InvalidQuery("rights must be a string".to_string()));
+
+        Ok(Some(BookRecord {
+            gutenberg_id,
+            titles: self.get_book_strings(
+                "SELECT titles.name FROM titles WHERE titles.bookid = ? ORDER BY titles.id",
+                book_id,
+            )?,
+            title,
+            subtitle,
+            alternative_titles: self.get_book_strings(
+                "SELECT titles.name FROM titles WHERE titles.bookid = ? AND titles.kind = 'alternative' ORDER BY titles.id",
+                book_id,
+            )?,
+            authors: self.get_book_strings(
+                "SELECT authors.name FROM authors, book_authors
+                WHERE authors.id = book_authors.authorid AND book_authors.bookid = ? ORDER BY book_authors.rowid",
+                book_id,
+            )?,
+            languages: self.get_book_strings(
+                "SELECT languages.name FROM languages, book_languages
+                WHERE languages.id = book_languages.languageid AND book_languages.bookid = ? ORDER BY book_languages.rowid",
+                book_id,
+            )?,
+            subjects: self.get_book_strings(
+                "SELECT subjects.name FROM subjects, book_subjects
+                WHERE subjects.id = book_subjects.subjectid AND book_subjects.bookid = ?
+                AND subjects.vocabulary IS NOT 'LCC' ORDER BY book_subjects.rowid",
+                book_id,
+            )?,
+            lcc: self.get_book_strings(
+                "SELECT subjects.name FROM subjects, book_subjects
+                WHERE subjects.id = book_subjects.subjectid AND book_subjects.bookid = ?
+                AND subjects.vocabulary = 'LCC' ORDER BY book_subjects.rowid",
+                book_id,
+            )?,
+            bookshelves: self.get_book_strings(
+                "SELECT bookshelves.name FROM bookshelves, book_bookshelves
+                WHERE bookshelves.id = book_bookshelves.bookshelfid AND book_bookshelves.bookid = ? ORDER BY book_bookshelves.rowid",
+                book_id,
+            )?,
+            contributors: self.get_book_contributor_names(book_id)?,
+            descriptions: self.get_book_strings(
+                "SELECT text FROM descriptions WHERE bookid = ? AND kind = 'description' ORDER BY id",
+                book_id,
+            )?,
+            table_of_contents: self.get_book_strings(
+                "SELECT text FROM descriptions WHERE bookid = ? AND kind = 'tableofcontents' ORDER BY id",
+                book_id,
+            )?,
+            summaries: self.get_book_strings(
+                "SELECT text FROM descriptions WHERE bookid = ? AND kind = 'summary' ORDER BY id",
+                book_id,
+            )?,
+            book_type,
+            license,
+            custom_fields,
+            rights,
+            publisher,
+            date_issued,
+            num_downloads: num_downloads.unwrap_or(0),
+            files,
+            downloads,
+        }))
+    }
+
+    /// Returns the smallest file of a book with a format starting with `format_prefix` (ex: `text/plain`), zipped files are skipped
+    pub fn smallest_file(
+        &mut self,
+        gutenberg_id: i32,
+        format_prefix: &str,
+    ) -> Result<Option<FileRecord>, Error> {
+        let book = match self.get_book(gutenberg_id)? {
+            Some(book) => book,
+            None => return Ok(None),
+        };
+        Ok(book
+            .downloads
+            .into_iter()
+            .filter(|x| !x.is_zipped() && x.formats.iter().any(|f| f.starts_with(format_prefix)))
+            .min_by_key(|x| x.extent.unwrap_or(i64::MAX)))
+    }
+
+    /// Returns the links of the files modified after `since` (ex: `2022-01-01` or `2022-01-01T10:00:00`)
+    pub fn files_modified_since(&mut self, since: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT name FROM downloadlinks WHERE modified > ? ORDER BY modified, id",
+        )?;
+        let links = stmt
+            .query_map((since,), |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(links)
+    }
+
+    /// Returns the records of the given gutenberg ids in the same order, ids that are not in the cache are skipped
+    pub fn get_books(&mut self, gutenberg_ids: &[i32]) -> Result<Vec<BookRecord>, Error> {
+        let mut results = Vec::with_capacity(gutenberg_ids.len());
+        for id in gutenberg_ids {
+            if let Some(book) = self.get_book(*id)? {
+                results.push(book);
             }
         }
-        if let Some(field) = json.get("downloadlinkstype") {
-            if let Some(field_value) = field.as_str() {
-                helpers.push(HelperQuery{tables: vec!["downloadlinks", "downloadlinkstype"],
-                            query_struct: vec!["downloadlinks.downloadtypeid =  downloadlinkstype.id and downloadlinks.bookid = books.id",
-                            "downloadlinkstype.name",
-                            field_value]});
-            } else {
-                return Err(Error::InvalidQuery(
-                    "downloadlinkstype must a string".to_string(),
-                ));
+        Ok(results)
+    }
+
+    /// Returns an author by gutenberg agent id
+    pub fn get_author(&mut self, agent_id: i64) -> Result<Option<AuthorRecord>, Error> {
+        let row_id = {
+            let mut stmt = self
+                .connection
+                .prepare_cached("SELECT id FROM authors WHERE agentid = ? LIMIT 1")?;
+            let mut rows = stmt.query((agent_id,))?;
+            match rows.next()? {
+                Some(row) => row.get::<_, i64>(0)?,
+                None => return Ok(None),
             }
-        }
+        };
+        Ok(Some(self.get_author_row(row_id)?))
+    }
 
-        let mut query = "SELECT DISTINCT books.gutenbergbookid FROM books".to_string();
-        for q in &helpers {
-            query = format!("{},{}", query, q.tables.join(","))
+    /// Returns the authors of a book, in the order of the rdf
+    pub fn get_book_authors(&mut self, gutenberg_id: i32) -> Result<Vec<AuthorRecord>, Error> {
+        let row_ids = {
+            let mut stmt = self.connection.prepare_cached(
+                "SELECT book_authors.authorid FROM book_authors, books
+                WHERE book_authors.bookid = books.id AND books.gutenbergbookid = ? ORDER BY book_authors.rowid",
+            )?;
+            let mut rows = stmt.query((gutenberg_id,))?;
+            let mut row_ids: Vec<i64> = Vec::new();
+            while let Some(row) = rows.next()? {
+                row_ids.push(row.get(0)?);
+            }
+            row_ids
+        };
+        let mut results = Vec::with_capacity(row_ids.len());
+        for row_id in row_ids {
+            results.push(self.get_author_row(row_id)?);
         }
+        Ok(results)
+    }
 
-        query = format!("{} WHERE ", query);
-        let mut idx = 0;
-        for q in &helpers {
-            query = format!(
-                "{} {} and {} in ({}) ",
-                query, q.query_struct[0], q.query_struct[1], q.query_struct[2]
-            );
-            if idx != helpers.len() - 1 {
-                query = format!("{} and ", query);
+    /// Returns the contributors of a book (translators, illustrators, editors...) in the order of the rdf
+    pub fn get_book_contributors(
+        &mut self,
+        gutenberg_id: i32,
+    ) -> Result<Vec<ContributorRecord>, Error> {
+        let rows = {
+            let mut stmt = self.connection.prepare_cached(
+                "SELECT book_contributors.role, book_contributors.authorid
+                FROM book_contributors, books
+                WHERE book_contributors.bookid = books.id AND books.gutenbergbookid = ? ORDER BY book_contributors.rowid",
+            )?;
+            let mut rows = stmt.query((gutenberg_id,))?;
+            let mut results: Vec<(String, Option<i64>)> = Vec::new();
+            while let Some(row) = rows.next()? {
+                results.push((row.get(0)?, row.get(1)?));
+            }
+            results
+        };
+        let mut results = Vec::with_capacity(rows.len());
+        for (role, row_id) in rows {
+            if let Some(row_id) = row_id {
+                results.push(ContributorRecord {
+                    role,
+                    author: self.get_author_row(row_id)?,
+                });
             }
-            idx = idx + 1;
         }
-        let mut stmt = self.connection.prepare(&query)?;
-        let mut rows = stmt.query(())?;
+        Ok(results)
+    }
+
+    fn get_book_contributor_names(
+        &mut self,
+        book_id: i64,
+    ) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT book_contributors.role, authors.name FROM book_contributors, authors
+            WHERE authors.id = book_contributors.authorid AND book_contributors.bookid = ? ORDER BY book_contributors.rowid",
+        )?;
+        let mut rows = stmt.query((book_id,))?;
+        let mut results: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        while let Some(row) = rows.next()? {
+            results.entry(row.get(0)?).or_default().push(row.get(1)?);
+        }
+        Ok(results)
+    }
+
+    fn get_author_row(&mut self, row_id: i64) -> Result<AuthorRecord, Error> {
+        let mut author = self.connection.query_row(
+            "SELECT agentid, name, birthdate, deathdate, surname, givennames, fullername, displayname, corporate
+            FROM authors WHERE id = ?",
+            (row_id,),
+            |row| {
+                Ok(AuthorRecord {
+                    agent_id: row.get(0)?,
+                    name: row.get(1)?,
+                    birth_year: row.get(2)?,
+                    death_year: row.get(3)?,
+                    surname: row.get(4)?,
+                    given_names: row.get(5)?,
+                    fuller_name: row.get(6)?,
+                    display_name: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+                    corporate: row.get::<_, Option<bool>>(8)?.unwrap_or_default(),
+                    ..Default::default()
+                })
+            },
+        )?;
+        author.aliases = self.get_book_strings(
+            "SELECT name FROM author_aliases WHERE authorid = ? ORDER BY rowid",
+            row_id,
+        )?;
+        author.webpages = self.get_book_strings(
+            "SELECT url FROM author_webpages WHERE authorid = ? ORDER BY rowid",
+            row_id,
+        )?;
+        Ok(author)
+    }
+
+    fn get_book_strings(&mut self, query: &str, book_id: i64) -> Result<Vec<String>, Error> {
+        let mut stmt = self.connection.prepare_cached(query)?;
+        let mut rows = stmt.query((book_id,))?;
         let mut results = Vec::new();
         while let Some(row) = rows.next()? {
             results.push(row.get(0)?);
         }
+        Ok(results)
+    }
+
+    /// Counts the books per value of `field` (ex: books per language), most common values first.
+    /// When `filter` is set only the books matching its filters are counted (its ordering and paging are ignored).
+    pub fn facet_counts(
+        &mut self,
+        field: QueryField,
+        filter: Option<&BookQuery>,
+        limit: Option<usize>,
+    ) -> Result<Vec<FacetCount>, Error> {
+        let parts = field.sql_parts();
+        let mut params = Vec::new();
+        let book_filter = SQLiteCache::book_filter_sql(parts.book_id, filter, &mut params);
+        let query = format!(
+            "SELECT {column}, COUNT(DISTINCT {book_id}) AS bookcount FROM {tables} WHERE {filter}
+            GROUP BY {column} ORDER BY bookcount DESC, {column} ASC LIMIT ?",
+            column = parts.column,
+            book_id = parts.book_id,
+            tables = parts.tables,
+            filter = parts.where_sql(&book_filter),
+        );
+        params.push(SqlValue::Integer(limit.map_or(-1, |x| x as i64)));
+        self.get_facet_counts(&query, params)
+    }
+
+    /// Counts the books issued per year, in year order
+    pub fn issued_year_counts(
+        &mut self,
+        filter: Option<&BookQuery>,
+    ) -> Result<Vec<FacetCount>, Error> {
+        let mut params = Vec::new();
+        let book_filter = SQLiteCache::book_filter_sql("books.id", filter, &mut params);
+        let query = format!(
+            "SELECT substr(books.dateissued, 1, 4) AS year, COUNT(*) FROM books
+            WHERE books.dateissued IS NOT NULL AND {} GROUP BY year ORDER BY year",
+            book_filter
+        );
+        self.get_facet_counts(&query, params)
+    }
+
+    /// Returns the size of the catalog, or of the books matching the filters of `filter`
+    pub fn catalog_stats(&mut self, filter: Option<&BookQuery>) -> Result<CatalogStats, Error> {
+        let mut params = Vec::new();
+        let book_filter = SQLiteCache::book_filter_sql("books.id", filter, &mut params);
+        let (books, total_downloads, first_issued, last_issued) = self.connection.query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(books.numdownloads), 0), MIN(books.dateissued), MAX(books.dateissued)
+                FROM books WHERE {}",
+                book_filter
+            ),
+            params_from_iter(params.iter()),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+        let count_distinct = |field: QueryField| -> Result<i64, Error> {
+            let parts = field.sql_parts();
+            let mut params = Vec::new();
+            let book_filter = SQLiteCache::book_filter_sql(parts.book_id, filter, &mut params);
+            let query = format!(
+                "SELECT COUNT(DISTINCT {}) FROM {} WHERE {}",
+                parts.column,
+                parts.tables,
+                parts.where_sql(&book_filter)
+            );
+            Ok(self
+                .connection
+                .query_row(&query, params_from_iter(params.iter()), |row| row.get(0))?)
+        };
+        let authors = count_distinct(QueryField::Author)?;
+        let subjects = count_distinct(QueryField::Subject)?;
+        let languages = count_distinct(QueryField::Language)?;
+        let bookshelves = count_distinct(QueryField::Bookshelf)?;
+
+        let mut params = Vec::new();
+        let book_filter = SQLiteCache::book_filter_sql("downloadlinks.bookid", filter, &mut params);
+        let files = self.connection.query_row(
+            &format!("SELECT COUNT(*) FROM downloadlinks WHERE {}", book_filter),
+            params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+
+        Ok(CatalogStats {
+            books,
+            authors,
+            subjects,
+            languages,
+            bookshelves,
+            files,
+            total_downloads,
+            first_issued,
+            last_issued,
+        })
+    }
+
+    /// the condition restricting `book_id_column` to the books matching the filter
+    fn book_filter_sql(
+        book_id_column: &str,
+        filter: Option<&BookQuery>,
+        params: &mut Vec<SqlValue>,
+    ) -> String {
+        match filter.and_then(|x| x.filter_sql(params)) {
+            Some(filter) => format!(
+                "{} IN (SELECT books.id FROM books WHERE {})",
+                book_id_column, filter
+            ),
+            None => "1".to_string(),
+        }
+    }
+
+    fn get_facet_counts(
+        &mut self,
+        query: &str,
+        params: Vec<SqlValue>,
+    ) -> Result<Vec<FacetCount>, Error> {
+        let mut stmt = self.connection.prepare(query)?;
+        let mut rows = stmt.query(params_from_iter(params.iter()))?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            results.push(FacetCount {
+                value: row.get(0)?,
+                count: row.get(1)?,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Full text search over titles, authors, subjects, bookshelves and descriptions.
+    /// Every word in `text` must match (in any field), results are ranked best first.
+    pub fn search(&mut self, text: &str, limit: usize) -> Result<Vec<SearchResult>, Error> {
+        let words = text
+            .split_whitespace()
+            .map(|x| format!("\"{}\"", x.replace('"', "\"\"")))
+            .collect::<Vec<String>>();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.search_match(&words.join(" "), limit)
+    }
 
+    /// Full text search with a raw fts5 match expression (ex: `titles:divina OR authors:dante*`)
+    pub fn search_match(
+        &mut self,
+        match_expression: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let mut stmt = self.connection.prepare(
+            "SELECT books.gutenbergbookid, book_search.rank, snippet(book_search, -1, '[', ']', '...', 12)
+            FROM book_search, books WHERE books.id = book_search.rowid AND book_search MATCH ?
+            ORDER BY book_search.rank LIMIT ?",
+        )?;
+        let mut rows = stmt.query((match_expression, limit as i64))?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            results.push(SearchResult {
+                gutenberg_id: row.get(0)?,
+                rank: row.get(1)?,
+                snippet: row.get(2)?,
+            });
+        }
         Ok(results)
     }
+
+    /// Runs a json query, this is a thin adapter over `BookQuery::from_json` and `find`
+    pub fn query(&mut self, json: &Value) -> Result<Vec<i32>, Error> {
+        let query = BookQuery::from_json(json)?;
+        self.find(&query)
+    }
+
+    /// Returns the gutenberg ids of all books matching the query
+    pub fn find(&mut self, query: &BookQuery) -> Result<Vec<i32>, Error> {
+        Ok(self.find_page(query)?.ids)
+    }
+
+    /// Returns one page of the books matching the query, the cursor in the result can be used to get the next page
+    pub fn find_page(&mut self, query: &BookQuery) -> Result<QueryPage, Error> {
+        let (sql, params) = query.to_sql();
+        let mut stmt = self.connection.prepare(&sql)?;
+        let has_key = stmt.column_count() > 1;
+        let mut rows = stmt.query(params_from_iter(params.iter()))?;
+        let mut ids = Vec::new();
+        let mut last: Option<(i32, SqlValue)> = None;
+        while let Some(row) = rows.next()? {
+            let id: i32 = row.get(0)?;
+            ids.push(id);
+            if has_key {
+                last = Some((id, row.get(1)?));
+            }
+        }
+
+        let mut next_cursor = None;
+        if let (Some(limit), Some((id, key))) = (query.limit, last) {
+            if ids.len() == limit {
+                let key = match key {
+                    SqlValue::Integer(v) => CursorKey::Integer(v),
+                    SqlValue::Text(v) => CursorKey::Text(v),
+                    _ => return Err(Error::InvalidQuery("unsupported sort key type".to_string())),
+                };
+                next_cursor = Some(QueryCursor {
+                    key,
+                    gutenberg_id: id as i64,
+                });
+            }
+        }
+        Ok(QueryPage { ids, next_cursor })
+    }
 }
 
 impl SQLiteCache {
+    /// Opens the cache file, a cache built by an older version of the crate is migrated to the current schema
     pub fn get_cache(settings: &GutenbergCacheSettings) -> Result<SQLiteCache, Error> {
         if Path::new(&settings.cache_filename).exists() {
-            let connection = Box::new(Connection::open(&settings.cache_filename)?);
-            return Ok(SQLiteCache { connection });
+            return SQLiteCache::open(&settings.cache_filename);
         }
         Err(Error::InvalidIO(
             format!("No cache file {}", settings.cache_filename).to_string(),
         ))
     }
 
+    fn open(filename: &str) -> Result<SQLiteCache, Error> {
+        let connection = Box::new(Connection::open(filename)?);
+        let mut cache = SQLiteCache { connection };
+        let version = cache.schema_version()?;
+        if version > SCHEMA_VERSION {
+            return Err(Error::InvalidCacheSchema(format!(
+                "{} has schema version {} but this version of gutenberg-rs supports up to version {}, rebuild the cache or update the crate",
+                filename, version, SCHEMA_VERSION
+            )));
+        }
+        if version < SCHEMA_VERSION {
+            cache.migrate().map_err(|e| {
+                Error::InvalidCacheSchema(format!(
+                    "could not migrate {} from schema version {} to {}: {}",
+                    filename, version, SCHEMA_VERSION, e
+                ))
+            })?;
+        }
+        Ok(cache)
+    }
+
+    /// Returns the schema version of the cache database.
+    /// Caches built before the schema was versioned have version 1.
+    pub fn schema_version(&self) -> Result<i32, Error> {
+        let version: i32 = self
+            .connection
+            .query_row("PRAGMA user_version", (), |row| row.get(0))?;
+        if version > 0 {
+            return Ok(version);
+        }
+        let has_books: bool = self.connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'books')",
+            (),
+            |row| row.get(0),
+        )?;
+        match has_books {
+            true => Ok(1),
+            false => Err(Error::InvalidCacheSchema(
+                "the database is not a gutenberg cache".to_string(),
+            )),
+        }
+    }
+
+    /// Upgrades the cache to the current schema version, returns the version it had before
+    pub fn migrate(&mut self) -> Result<i32, Error> {
+        let start = self.schema_version()?;
+        for version in start..SCHEMA_VERSION {
+            let transaction = self.connection.transaction()?;
+            transaction.execute_batch(MIGRATIONS[version as usize - 1])?;
+            transaction.pragma_update(None, "user_version", version + 1)?;
+            transaction.commit()?;
+        }
+        if start < STALE_BEFORE_VERSION {
+            self.connection
+                .execute("UPDATE cachestate SET stale = 1", ())?;
+        }
+        SQLiteCache::write_language_codes(&self.connection)?;
+        SQLiteCache::write_author_names(&self.connection)?;
+        Ok(start)
+    }
+
+    /// True if the cache was migrated from a schema whose data the migrations could not fill in (titles, issue
+    /// dates, file sizes...). `setup_sqlite` rebuilds a stale cache, an update with the whole catalog refreshes it.
+    pub fn is_stale(&self) -> Result<bool, Error> {
+        Ok(self
+            .connection
+            .query_row("SELECT stale FROM cachestate", (), |row| row.get(0))?)
+    }
+
     pub fn create_cache(
         parse_results: &ParseResult,
         settings: &GutenbergCacheSettings,
@@ -188,11 +729,10 @@ impl SQLiteCache {
             if force_recreate {
                 fs::remove_file(&settings.cache_filename)?;
             } else {
-                let connection = Box::new(Connection::open(&settings.cache_filename)?);
-                return Ok(SQLiteCache { connection });
+                return SQLiteCache::open(&settings.cache_filename);
             }
         }
-        let mut connection = match settings.db_in_memory {
+        let connection = match settings.db_in_memory {
             false => Box::new(Connection::open(&settings.cache_filename)?),
             true => Box::new(Connection::open(":memory:")?),
         };
@@ -200,8 +740,6 @@ impl SQLiteCache {
         connection.execute_batch(create_query)?;
         connection.execute_batch("PRAGMA journal_mode = OFF;PRAGMA synchronous = 0;PRAGMA cache_size = 1000000;PRAGMA locking_mode = EXCLUSIVE;PRAGMA temp_store = MEMORY;")?;
 
-        let mut book_id = 0;
-
         let mut pb_fields: Option<ProgressBar> = None;
         if show_progress_bar {
             let pb = ProgressBar::new(parse_results.field_dictionaries.len() as u64);
@@ -215,44 +753,31 @@ impl SQLiteCache {
         }
 
         for (idx, result) in parse_results.field_dictionaries.iter().enumerate() {
-            book_id = book_id + 1;
             if let Some(pb) = &mut pb_fields {
                 pb.set_position((idx + 1) as u64);
             }
 
             match FromPrimitive::from_usize(idx) {
-                Some(ParseType::Title) => {
-                    SQLiteCache::insert_many_field_id(
-                        &mut connection,
-                        "titles",
-                        "name",
-                        "bookid",
-                        result,
-                        book_id,
-                    )?;
-                }
                 Some(ParseType::Subject) => {
-                    SQLiteCache::insert_many_fields(&mut connection, "subjects", "name", result)?;
+                    SQLiteCache::insert_many_fields(&connection, "subjects", "name", result)?;
                 }
                 Some(ParseType::Language) => {
-                    SQLiteCache::insert_many_fields(&mut connection, "languages", "name", result)?;
+                    SQLiteCache::insert_many_fields(&connection, "languages", "name", result)?;
+                    SQLiteCache::write_language_codes(&connection)?;
                 }
                 Some(ParseType::Author) => {
-                    SQLiteCache::insert_many_fields(&mut connection, "authors", "name", result)?;
+                    for agent in parse_results.agents_dictionary.values() {
+                        SQLiteCache::write_agent(&connection, agent, None)?;
+                    }
                 }
                 Some(ParseType::Bookshelf) => {
-                    SQLiteCache::insert_many_fields(
-                        &mut connection,
-                        "bookshelves",
-                        "name",
-                        result,
-                    )?;
+                    SQLiteCache::insert_many_fields(&connection, "bookshelves", "name", result)?;
                 }
                 Some(ParseType::Publisher) => {
-                    SQLiteCache::insert_many_fields(&mut connection, "publishers", "name", result)?;
+                    SQLiteCache::insert_many_fields(&connection, "publishers", "name", result)?;
                 }
                 Some(ParseType::Rights) => {
-                    SQLiteCache::insert_many_fields(&mut connection, "rights", "name", result)?;
+                    SQLiteCache::insert_many_fields(&connection, "rights", "name", result)?;
                 }
                 _ => {}
             }
@@ -261,11 +786,16 @@ impl SQLiteCache {
             pb.finish();
         }
         SQLiteCache::insert_many_fields(
-            &mut connection,
+            &connection,
             "downloadlinkstype",
             "name",
             &parse_results.file_types_dictionary,
         )?;
+        SQLiteCache::insert_custom_fields(&connection, parse_results)?;
+        // on a fresh database the row ids follow the dictionary order
+        let row_ids = RowIds::sequential(parse_results);
+        SQLiteCache::write_subject_vocabularies(&connection, parse_results, &row_ids.subjects)?;
+
         let mut pb_all: Option<ProgressBar> = None;
         if show_progress_bar {
             let pb = ProgressBar::new(parse_results.books.len() as u64);
@@ -276,7 +806,7 @@ impl SQLiteCache {
                 .progress_chars("█  "),
             );
 
-            pb.set_message(format!("Building sqlite db"));
+            pb.set_message("Building sqlite db".to_string());
             pb_all = Some(pb);
         }
 
@@ -284,93 +814,391 @@ impl SQLiteCache {
             if let Some(pb) = &mut pb_all {
                 pb.set_position(idx as u64);
             }
-            let pairs_book_authors = book
-                .author_ids
-                .iter()
-                .map(|x| (*x + 1, idx + 1))
-                .collect::<Vec<(usize, usize)>>();
-            let pairs_book_subjects = book
-                .subject_ids
-                .iter()
-                .map(|x| (*x + 1, idx + 1))
-                .collect::<Vec<(usize, usize)>>();
-            let pairs_book_languages = book
-                .language_ids
-                .iter()
-                .map(|x| (*x + 1, idx + 1))
-                .collect::<Vec<(usize, usize)>>();
-            let pairs_book_bookshelves = book
-                .bookshelf_ids
+            let hash = BookRecord::from_parsed(parse_results, book).content_hash();
+            SQLiteCache::insert_book(&connection, parse_results, &row_ids, book, None, &hash)?;
+        }
+        let create_query = include_str!("gutenbergindex_indices.db.sql");
+        connection.execute_batch(create_query)?;
+        let search_query = include_str!("gutenbergindex_search.db.sql");
+        connection.execute_batch(search_query)?;
+        // only a complete cache gets a version
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        let added = parse_results
+            .books
+            .iter()
+            .map(|x| x.gutenberg_book_id as i32)
+            .collect::<Vec<i32>>();
+        SQLiteCache::insert_snapshot(
+            &connection,
+            parse_results,
+            None,
+            &UpdateReport {
+                added,
+                ..Default::default()
+            },
+        )?;
+
+        if let Some(pb) = pb_all {
+            pb.finish();
+        }
+
+        Ok(SQLiteCache { connection })
+    }
+
+    /// Applies a parse result to an existing cache: new books are added, books whose data changed are rewritten and
+    /// books that did not change are left alone. Books are compared by the content hash stored with them (see
+    /// `BookRecord::content_hash`), so the cached books are not read back. With `remove_missing` the parse result is treated as the whole catalog
+    /// and cached books that are not in it are removed. The update is recorded as a new catalog snapshot.
+    pub fn update_cache(
+        &mut self,
+        parse_results: &ParseResult,
+        remove_missing: bool,
+        source: Option<&str>,
+        show_progress_bar: bool,
+    ) -> Result<UpdateReport, Error> {
+        let mut report = UpdateReport::default();
+        // every book of a stale cache is rewritten
+        let stale = self.is_stale()?;
+        let mut changed: Vec<(&Book, Option<i64>, String)> = Vec::new();
+        for book in &parse_results.books {
+            let gutenberg_id = book.gutenberg_book_id as i32;
+            let hash = BookRecord::from_parsed(parse_results, book).content_hash();
+            let mut stmt = self
+                .connection
+                .prepare_cached("SELECT id, contenthash FROM books WHERE gutenbergbookid = ?")?;
+            let mut rows = stmt.query((gutenberg_id,))?;
+            match rows.next()? {
+                None => {
+                    report.added.push(gutenberg_id);
+                    changed.push((book, None, hash));
+                }
+                Some(row) => {
+                    let cached_hash: Option<String> = row.get(1)?;
+                    if !stale && cached_hash.as_ref() == Some(&hash) {
+                        report.unchanged += 1;
+                    } else {
+                        report.updated.push(gutenberg_id);
+                        changed.push((book, Some(row.get(0)?), hash));
+                    }
+                }
+            }
+        }
+        if remove_missing {
+            let parsed = parse_results
+                .books
                 .iter()
-                .map(|x| (*x + 1, idx + 1))
-                .collect::<Vec<(usize, usize)>>();
-
-            SQLiteCache::insert_links(
-                &mut connection,
-                pairs_book_authors,
-                "book_authors",
-                "authorid",
-                "bookid",
-            )?;
-            SQLiteCache::insert_links(
-                &mut connection,
-                pairs_book_subjects,
-                "book_subjects",
-                "subjectid",
-                "bookid",
-            )?;
-            SQLiteCache::insert_links(
-                &mut connection,
-                pairs_book_languages,
-                "book_languages",
-                "languageid",
-                "bookid",
-            )?;
-            SQLiteCache::insert_links(
-                &mut connection,
-                pairs_book_bookshelves,
-                "book_bookshelves",
-                "bookshelfid",
-                "bookid",
-            )?;
+                .map(|x| x.gutenberg_book_id as i32)
+                .collect::<HashSet<i32>>();
+            let mut stmt = self
+                .connection
+                .prepare("SELECT gutenbergbookid FROM books ORDER BY gutenbergbookid")?;
+            let mut rows = stmt.query(())?;
+            while let Some(row) = rows.next()? {
+                let id: i32 = row.get(0)?;
+                if !parsed.contains(&id) {
+                    report.removed.push(id);
+                }
+            }
+        }
 
-            let query = format!(
-                "INSERT OR IGNORE INTO downloadlinks(name, downloadtypeid, bookid) VALUES (?,?,?)"
+        let mut pb: Option<ProgressBar> = None;
+        if show_progress_bar {
+            let p = ProgressBar::new(changed.len() as u64);
+            p.set_style(
+                ProgressStyle::with_template(
+                    "{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.white/blue}] ({eta})",
+                )?
+                .progress_chars("█  "),
             );
+            p.set_message("Updating sqlite db".to_string());
+            pb = Some(p);
+        }
 
-            let mut smt = connection.prepare(query.as_str())?;
-            for item in book.files.iter() {
-                let mut file_link = "";
-                if let Some(file_link_item) = parse_results
-                    .files_dictionary
-                    .get_index(item.file_link_id as usize)
-                {
-                    file_link = file_link_item.0;
-                }
-                smt.execute([
-                    file_link,
-                    item.file_type_id.to_string().as_str(),
-                    (idx + 1).to_string().as_str(),
-                ])?;
+        let transaction = self.connection.transaction()?;
+        let row_ids = RowIds::resolve(&transaction, parse_results)?;
+        SQLiteCache::write_language_codes(&transaction)?;
+        SQLiteCache::write_subject_vocabularies(&transaction, parse_results, &row_ids.subjects)?;
+        for (idx, (book, row_id, hash)) in changed.iter().enumerate() {
+            if let Some(p) = &mut pb {
+                p.set_position(idx as u64);
+            }
+            if let Some(row_id) = row_id {
+                SQLiteCache::delete_book_rows(&transaction, *row_id, false)?;
             }
+            SQLiteCache::insert_book(&transaction, parse_results, &row_ids, book, *row_id, hash)?;
+        }
+        for id in &report.removed {
+            if let Some(row_id) = SQLiteCache::get_book_row_id(&transaction, *id)? {
+                SQLiteCache::delete_book_rows(&transaction, row_id, true)?;
+            }
+        }
+        if !report.removed.is_empty() || !report.updated.is_empty() {
+            transaction.execute_batch(include_str!("gutenbergindex_prune.db.sql"))?;
+        }
+        transaction.execute_batch(include_str!("gutenbergindex_search.db.sql"))?;
+        if stale && remove_missing {
+            transaction.execute("UPDATE cachestate SET stale = 0", ())?;
+        }
+        SQLiteCache::insert_snapshot(&transaction, parse_results, source, &report)?;
+        transaction.commit()?;
 
-            connection.execute("INSERT OR IGNORE INTO books(publisherid,rightsid,numdownloads,gutenbergbookid) VALUES (?,?,?,?)"
-            , (book.publisher_id, book.rights_id,
-            book.num_downloads,book.gutenberg_book_id))?;
+        if let Some(p) = pb {
+            p.finish();
         }
-        let create_query = include_str!("gutenbergindex_indices.db.sql");
-        connection.execute_batch(create_query)?;
+        Ok(report)
+    }
 
-        if let Some(pb) = pb_all {
-            pb.finish();
+    /// Removes books (and everything linked only to them) from the cache, returns the number of removed books
+    pub fn remove_books(&mut self, gutenberg_ids: &[i32]) -> Result<usize, Error> {
+        let transaction = self.connection.transaction()?;
+        let mut removed = 0;
+        for id in gutenberg_ids {
+            if let Some(row_id) = SQLiteCache::get_book_row_id(&transaction, *id)? {
+                SQLiteCache::delete_book_rows(&transaction, row_id, true)?;
+                removed += 1;
+            }
         }
+        transaction.execute_batch(include_str!("gutenbergindex_prune.db.sql"))?;
+        transaction.commit()?;
+        Ok(removed)
+    }
 
-        Ok(SQLiteCache { connection })
+    /// Returns the catalog snapshots applied to this cache, oldest first
+    pub fn snapshots(&mut self) -> Result<Vec<CatalogSnapshot>, Error> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, createdat, source, lastissued, books, added, updated, removed FROM snapshots ORDER BY id",
+        )?;
+        let mut rows = stmt.query(())?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            results.push(CatalogSnapshot {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                source: row.get(2)?,
+                last_issued: row.get(3)?,
+                books: row.get(4)?,
+                added: row.get(5)?,
+                updated: row.get(6)?,
+                removed: row.get(7)?,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Returns the catalog snapshot the cache currently reflects
+    pub fn latest_snapshot(&mut self) -> Result<Option<CatalogSnapshot>, Error> {
+        Ok(self.snapshots()?.pop())
+    }
+
+    fn get_book_row_id(connection: &Connection, gutenberg_id: i32) -> Result<Option<i64>, Error> {
+        let mut stmt =
+            connection.prepare_cached("SELECT id FROM books WHERE gutenbergbookid = ?")?;
+        let mut rows = stmt.query((gutenberg_id,))?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_snapshot(
+        connection: &Connection,
+        parse_results: &ParseResult,
+        source: Option<&str>,
+        report: &UpdateReport,
+    ) -> Result<(), Error> {
+        let last_issued = parse_results
+            .books
+            .iter()
+            .filter_map(|x| normalize_date(&x.date_issued))
+            .max();
+        let books: i64 =
+            connection.query_row("SELECT COUNT(*) FROM books", (), |row| row.get(0))?;
+        connection.execute(
+            "INSERT INTO snapshots(createdat, source, lastissued, books, added, updated, removed)
+            VALUES (datetime('now'),?,?,?,?,?,?)",
+            (
+                source,
+                last_issued,
+                books,
+                report.added.len(),
+                report.updated.len(),
+                report.removed.len(),
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// deletes the rows that belong to a book, the books row itself is only deleted with `remove`
+    fn delete_book_rows(connection: &Connection, book_id: i64, remove: bool) -> Result<(), Error> {
+        connection
+            .prepare_cached(
+                "DELETE FROM downloadlink_formats WHERE downloadlinkid IN (SELECT id FROM downloadlinks WHERE bookid = ?)",
+            )?
+            .execute((book_id,))?;
+        for table in [
+            "titles",
+            "descriptions",
+            "downloadlinks",
+            "book_authors",
+            "book_subjects",
+            "book_languages",
+            "book_bookshelves",
+            "book_contributors",
+            "book_customfields",
+        ] {
+            connection
+                .prepare_cached(&format!("DELETE FROM {} WHERE bookid = ?", table))?
+                .execute((book_id,))?;
+        }
+        connection
+            .prepare_cached("DELETE FROM book_search WHERE rowid = ?")?
+            .execute((book_id,))?;
+        if remove {
+            connection
+                .prepare_cached("DELETE FROM books WHERE id = ?")?
+                .execute((book_id,))?;
+        }
+        Ok(())
+    }
+
+    /// stores the vocabulary (LCSH or LCC) of the parsed subjects on their rows
+    fn write_subject_vocabularies(
+        connection: &Connection,
+        parse_results: &ParseResult,
+        subject_rows: &[i64],
+    ) -> Result<(), Error> {
+        let mut smt =
+            connection.prepare_cached("UPDATE subjects SET vocabulary = ? WHERE id = ?")?;
+        for (idx, vocabulary) in &parse_results.subject_vocabularies {
+            if let Some(row_id) = subject_rows.get(*idx) {
+                smt.execute((vocabulary, row_id))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// fills in the ISO 639 codes and names of the languages that do not have them yet
+    fn write_language_codes(connection: &Connection) -> Result<(), Error> {
+        let mut select = connection.prepare("SELECT id, name FROM languages WHERE code IS NULL")?;
+        let languages = select
+            .query_map((), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut smt = connection.prepare(
+            "UPDATE languages SET code = ?, macrolanguage = ?, englishname = ?, nativename = ? WHERE id = ?",
+        )?;
+        for (row_id, name) in languages {
+            let language = find_language(&name);
+            smt.execute((
+                language_code(&name),
+                macrolanguage_code(&name),
+                language.map(|x| x.english_name),
+                language.map(|x| x.native_name),
+                row_id,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// inserts an author row with its aliases and webpages, or rewrites the existing row `row_id`
+    fn write_agent(
+        connection: &Connection,
+        agent: &Agent,
+        row_id: Option<i64>,
+    ) -> Result<i64, Error> {
+        let values = (
+            &agent.name,
+            agent.agent_id,
+            agent.birth_year,
+            agent.death_year,
+        );
+        let author_id = match row_id {
+            Some(row_id) => {
+                connection
+                    .prepare_cached(
+                        "UPDATE authors SET name = ?, agentid = ?, birthdate = ?, deathdate = ? WHERE id = ?",
+                    )?
+                    .execute((values.0, values.1, values.2, values.3, row_id))?;
+                connection
+                    .prepare_cached("DELETE FROM author_aliases WHERE authorid = ?")?
+                    .execute((row_id,))?;
+                connection
+                    .prepare_cached("DELETE FROM author_webpages WHERE authorid = ?")?
+                    .execute((row_id,))?;
+                row_id
+            }
+            None => {
+                connection
+                    .prepare_cached(
+                        "INSERT INTO authors(name, agentid, birthdate, deathdate) VALUES (?,?,?,?)",
+                    )?
+                    .execute(values)?;
+                connection.last_insert_rowid()
+            }
+        };
+        SQLiteCache::write_author_name(connection, author_id, &agent.name)?;
+        let mut smt =
+            connection.prepare_cached("INSERT INTO author_aliases(authorid, name) VALUES (?,?)")?;
+        for alias in &agent.aliases {
+            smt.execute((author_id, alias))?;
+        }
+        let mut smt =
+            connection.prepare_cached("INSERT INTO author_webpages(authorid, url) VALUES (?,?)")?;
+        for webpage in &agent.webpages {
+            smt.execute((author_id, webpage))?;
+        }
+        Ok(author_id)
+    }
+
+    /// writes the parts of an author name and the forms author queries match it by
+    fn write_author_name(connection: &Connection, author_id: i64, name: &str) -> Result<(), Error> {
+        let parsed = parse_author_name(name);
+        connection
+            .prepare_cached(
+                "UPDATE authors SET surname = ?, givennames = ?, fullername = ?, displayname = ?, corporate = ? WHERE id = ?",
+            )?
+            .execute((
+                &parsed.surname,
+                &parsed.given_names,
+                &parsed.fuller_name,
+                &parsed.display_name,
+                parsed.corporate,
+                author_id,
+            ))?;
+        connection
+            .prepare_cached("DELETE FROM author_names WHERE authorid = ?")?
+            .execute((author_id,))?;
+        let mut forms = vec![name.to_string(), parsed.display_name];
+        forms.extend(parsed.fuller_name);
+        forms.dedup();
+        let mut smt =
+            connection.prepare_cached("INSERT INTO author_names(authorid, name) VALUES (?,?)")?;
+        for form in forms.iter().filter(|x| !x.is_empty()) {
+            smt.execute((author_id, form))?;
+        }
+        Ok(())
+    }
+
+    /// fills in the name parts of the authors stored before they were parsed
+    fn write_author_names(connection: &Connection) -> Result<(), Error> {
+        let mut select =
+            connection.prepare("SELECT id, name FROM authors WHERE displayname IS NULL")?;
+        let authors = select
+            .query_map((), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (row_id, name) in authors {
+            SQLiteCache::write_author_name(connection, row_id, &name)?;
+        }
+        Ok(())
     }
 
     fn insert_links(
-        connection: &mut Connection,
-        links: Vec<(usize, usize)>,
+        connection: &Connection,
+        links: Vec<(i64, i64)>,
         table_name: &str,
         link1_name: &str,
         link2_name: &str,
@@ -385,14 +1213,14 @@ impl SQLiteCache {
             link2_name
         );
 
-        let mut smt = connection.prepare(query.as_str())?;
+        let mut smt = connection.prepare_cached(query.as_str())?;
         for item in links.iter() {
-            smt.execute([item.0.to_string(), item.1.to_string()])?;
+            smt.execute([item.0, item.1])?;
         }
         Ok(())
     }
     fn insert_many_fields(
-        connection: &mut Connection,
+        connection: &Connection,
         table: &str,
         field: &str,
         field_dictionary: &IndexMap<String, DictionaryItemContent>,
@@ -410,29 +1238,415 @@ impl SQLiteCache {
         Ok(())
     }
 
-    fn insert_many_field_id(
-        connection: &mut Connection,
-        table: &str,
-        field1: &str,
-        field2: &str,
-        field_dictionary: &IndexMap<String, DictionaryItemContent>,
-        _book_id: usize,
+    /// inserts the values of every custom field, in dictionary order
+    fn insert_custom_fields(
+        connection: &Connection,
+        parse_results: &ParseResult,
     ) -> Result<(), Error> {
-        if field_dictionary.is_empty() {
-            return Ok(());
+        let mut smt = connection.prepare("INSERT INTO customfields(field, value) VALUES (?,?)")?;
+        for (key, dictionary) in &parse_results.custom_dictionaries {
+            for (value, _) in dictionary.iter() {
+                smt.execute((key, value))?;
+            }
         }
+        Ok(())
+    }
 
-        let query = format!(
-            "INSERT OR IGNORE INTO {}({}, {}) VALUES (?,?)",
-            table, field1, field2
-        );
+    /// inserts the book row with its titles, download links and field links, returns the row id of the book.
+    /// When `row_id` is set the existing books row is updated instead.
+    fn insert_book(
+        connection: &Connection,
+        parse_results: &ParseResult,
+        row_ids: &RowIds,
+        book: &Book,
+        row_id: Option<i64>,
+        content_hash: &str,
+    ) -> Result<i64, Error> {
+        let publisher_id = RowIds::get(&row_ids.publishers, book.publisher_id);
+        let rights_id = RowIds::get(&row_ids.rights, book.rights_id);
+        let date_issued = normalize_date(&book.date_issued);
+        let titles = &parse_results.field_dictionaries[ParseType::Title as usize];
+        let (title, subtitle) = match book.title_ids.first().and_then(|x| titles.get_index(*x)) {
+            Some((title, _)) => {
+                let (title, subtitle) = split_title(title);
+                (Some(title), subtitle)
+            }
+            None => (None, None),
+        };
+        let book_id = match row_id {
+            Some(row_id) => {
+                connection
+                    .prepare_cached(
+                        "UPDATE books SET publisherid = ?, rightsid = ?, numdownloads = ?, dateissued = ?, type = ?, license = ?, title = ?, subtitle = ?, contenthash = ? WHERE id = ?",
+                    )?
+                    .execute((
+                        publisher_id,
+                        rights_id,
+                        book.num_downloads,
+                        date_issued,
+                        &book.book_type,
+                        &book.license,
+                        &title,
+                        &subtitle,
+                        content_hash,
+                        row_id,
+                    ))?;
+                row_id
+            }
+            None => {
+                connection
+                    .prepare_cached(
+                        "INSERT INTO books(publisherid,rightsid,numdownloads,gutenbergbookid,dateissued,type,license,title,subtitle,contenthash) VALUES (?,?,?,?,?,?,?,?,?,?)",
+                    )?
+                    .execute((
+                        publisher_id,
+                        rights_id,
+                        book.num_downloads,
+                        book.gutenberg_book_id,
+                        date_issued,
+                        &book.book_type,
+                        &book.license,
+                        &title,
+                        &subtitle,
+                        content_hash,
+                    ))?;
+                connection.last_insert_rowid()
+            }
+        };
 
-        let mut smt = connection.prepare(query.as_str())?;
-        for item in field_dictionary.iter() {
-            for book_id in &item.1.book_links {
-                smt.execute([item.0.as_str(), book_id.to_string().as_str()])?;
+        let mut smt =
+            connection.prepare_cached("INSERT INTO titles(name, bookid, kind) VALUES (?,?,?)")?;
+        for (kind, parse_type, ids) in [
+            ("title", ParseType::Title, &book.title_ids),
+            (
+                "alternative",
+                ParseType::AlternativeTitle,
+                &book.alternative_title_ids,
+            ),
+        ] {
+            let dictionary = &parse_results.field_dictionaries[parse_type as usize];
+            for id in ids {
+                if let Some(title) = dictionary.get_index(*id) {
+                    smt.execute((title.0, book_id, kind))?;
+                }
             }
         }
-        Ok(())
+
+        let mut smt = connection
+            .prepare_cached("INSERT INTO descriptions(bookid, kind, text) VALUES (?,?,?)")?;
+        for (kind, parse_type, ids) in [
+            ("description", ParseType::Description, &book.description_ids),
+            (
+                "tableofcontents",
+                ParseType::TableOfContents,
+                &book.table_of_contents_ids,
+            ),
+            ("summary", ParseType::Summary, &book.summary_ids),
+        ] {
+            let dictionary = &parse_results.field_dictionaries[parse_type as usize];
+            for id in ids {
+                if let Some(text) = dictionary.get_index(*id) {
+                    smt.execute((book_id, kind, text.0))?;
+                }
+            }
+        }
+
+        let mut smt = connection.prepare_cached(
+            "INSERT OR IGNORE INTO downloadlinks(name, downloadtypeid, bookid, extent, modified) VALUES (?,?,?,?,?)",
+        )?;
+        let mut format_smt = connection.prepare_cached(
+            "INSERT INTO downloadlink_formats(downloadlinkid, downloadtypeid) VALUES (?,?)",
+        )?;
+        for item in book.files.iter() {
+            let mut file_link = "";
+            if let Some(file_link_item) = parse_results
+                .files_dictionary
+                .get_index(item.file_link_id as usize)
+            {
+                file_link = file_link_item.0;
+            }
+            let file_type_id = RowIds::get(&row_ids.file_types, item.file_type_id);
+            smt.execute((
+                file_link,
+                file_type_id,
+                book_id,
+                item.extent,
+                &item.modified,
+            ))?;
+            let link_id = connection.last_insert_rowid();
+            for format_id in &item.file_type_ids {
+                format_smt.execute((link_id, RowIds::get(&row_ids.file_types, *format_id)))?;
+            }
+        }
+
+        let links = |ids: &Vec<usize>, rows: &Vec<i64>| -> Vec<(i64, i64)> {
+            ids.iter().map(|x| (rows[*x], book_id)).collect()
+        };
+        SQLiteCache::insert_links(
+            connection,
+            links(&book.agent_ids, &row_ids.authors),
+            "book_authors",
+            "authorid",
+            "bookid",
+        )?;
+        let mut smt = connection.prepare_cached(
+            "INSERT INTO book_contributors(bookid, agentid, authorid, role) VALUES (?,?,?,?)",
+        )?;
+        for (agent, role) in &book.contributors {
+            let agent_id = parse_results
+                .agents_dictionary
+                .get_index(*agent)
+                .and_then(|x| x.1.agent_id);
+            smt.execute((book_id, agent_id, row_ids.authors[*agent], role))?;
+        }
+        SQLiteCache::insert_links(
+            connection,
+            links(&book.subject_ids, &row_ids.subjects),
+            "book_subjects",
+            "subjectid",
+            "bookid",
+        )?;
+        SQLiteCache::insert_links(
+            connection,
+            links(&book.language_ids, &row_ids.languages),
+            "book_languages",
+            "languageid",
+            "bookid",
+        )?;
+        SQLiteCache::insert_links(
+            connection,
+            links(&book.bookshelf_ids, &row_ids.bookshelves),
+            "book_bookshelves",
+            "bookshelfid",
+            "bookid",
+        )?;
+        for (key, ids) in &book.custom_fields {
+            if let Some(rows) = row_ids.custom_fields.get(key) {
+                SQLiteCache::insert_links(
+                    connection,
+                    links(ids, rows),
+                    "book_customfields",
+                    "customfieldid",
+                    "bookid",
+                )?;
+            }
+        }
+        Ok(book_id)
+    }
+}
+
+/// maps the dictionary indices of a parse result to the row ids of the lookup tables
+struct RowIds {
+    authors: Vec<i64>,
+    subjects: Vec<i64>,
+    languages: Vec<i64>,
+    bookshelves: Vec<i64>,
+    publishers: Vec<i64>,
+    rights: Vec<i64>,
+    file_types: Vec<i64>,
+    /// by custom field key
+    custom_fields: HashMap<String, Vec<i64>>,
+}
+
+impl RowIds {
+    /// the row ids of a freshly created cache, where the lookup tables are filled in dictionary order
+    fn sequential(parse_results: &ParseResult) -> RowIds {
+        let rows = |dictionary: &IndexMap<String, DictionaryItemContent>| -> Vec<i64> {
+            (1..=dictionary.len() as i64).collect()
+        };
+        let fields = &parse_results.field_dictionaries;
+        // every custom field shares the customfields table, their values follow one another
+        let mut custom_fields = HashMap::new();
+        let mut next_row = 1;
+        for (key, dictionary) in &parse_results.custom_dictionaries {
+            let end = next_row + dictionary.len() as i64;
+            custom_fields.insert(key.clone(), (next_row..end).collect());
+            next_row = end;
+        }
+        RowIds {
+            authors: (1..=parse_results.agents_dictionary.len() as i64).collect(),
+            subjects: rows(&fields[ParseType::Subject as usize]),
+            languages: rows(&fields[ParseType::Language as usize]),
+            bookshelves: rows(&fields[ParseType::Bookshelf as usize]),
+            publishers: rows(&fields[ParseType::Publisher as usize]),
+            rights: rows(&fields[ParseType::Rights as usize]),
+            file_types: rows(&parse_results.file_types_dictionary),
+            custom_fields,
+        }
+    }
+
+    /// looks up the row id of every dictionary value in an existing cache, inserting the missing values
+    fn resolve(connection: &Connection, parse_results: &ParseResult) -> Result<RowIds, Error> {
+        let fields = &parse_results.field_dictionaries;
+        Ok(RowIds {
+            authors: RowIds::resolve_agents(connection, &parse_results.agents_dictionary)?,
+            subjects: RowIds::resolve_table(
+                connection,
+                "subjects",
+                &fields[ParseType::Subject as usize],
+            )?,
+            languages: RowIds::resolve_table(
+                connection,
+                "languages",
+                &fields[ParseType::Language as usize],
+            )?,
+            bookshelves: RowIds::resolve_table(
+                connection,
+                "bookshelves",
+                &fields[ParseType::Bookshelf as usize],
+            )?,
+            publishers: RowIds::resolve_table(
+                connection,
+                "publishers",
+                &fields[ParseType::Publisher as usize],
+            )?,
+            rights: RowIds::resolve_table(
+                connection,
+                "rights",
+                &fields[ParseType::Rights as usize],
+            )?,
+            file_types: RowIds::resolve_table(
+                connection,
+                "downloadlinkstype",
+                &parse_results.file_types_dictionary,
+            )?,
+            custom_fields: RowIds::resolve_custom_fields(connection, parse_results)?,
+        })
+    }
+
+    fn resolve_custom_fields(
+        connection: &Connection,
+        parse_results: &ParseResult,
+    ) -> Result<HashMap<String, Vec<i64>>, Error> {
+        let mut select = connection
+            .prepare("SELECT id FROM customfields WHERE field = ? AND value = ? LIMIT 1")?;
+        let mut insert =
+            connection.prepare("INSERT INTO customfields(field, value) VALUES (?,?)")?;
+        let mut results = HashMap::new();
+        for (key, dictionary) in &parse_results.custom_dictionaries {
+            let mut rows = Vec::with_capacity(dictionary.len());
+            for (value, _) in dictionary.iter() {
+                let mut found = select.query((key, value))?;
+                let row_id = match found.next()? {
+                    Some(row) => row.get(0)?,
+                    None => {
+                        insert.execute((key, value))?;
+                        connection.last_insert_rowid()
+                    }
+                };
+                rows.push(row_id);
+            }
+            results.insert(key.clone(), rows);
+        }
+        Ok(results)
+    }
+
+    /// agents are matched by agent id, or by name for authors stored before agent ids were,
+    /// only the new agents and the ones whose metadata changed are written
+    fn resolve_agents(
+        connection: &Connection,
+        agents: &IndexMap<String, Agent>,
+    ) -> Result<Vec<i64>, Error> {
+        let mut by_id = connection.prepare("SELECT id FROM authors WHERE agentid = ? LIMIT 1")?;
+        let mut by_name = connection
+            .prepare("SELECT id FROM authors WHERE agentid IS NULL AND name = ? LIMIT 1")?;
+        let mut results = Vec::with_capacity(agents.len());
+        for agent in agents.values() {
+            let mut rows = match agent.agent_id {
+                Some(id) => by_id.query((id,))?,
+                None => by_name.query((&agent.name,))?,
+            };
+            let mut row_id: Option<i64> = match rows.next()? {
+                Some(row) => Some(row.get(0)?),
+                None => None,
+            };
+            drop(rows);
+            if row_id.is_none() && agent.agent_id.is_some() {
+                let mut rows = by_name.query((&agent.name,))?;
+                if let Some(row) = rows.next()? {
+                    row_id = Some(row.get(0)?);
+                }
+            }
+            let row_id = match row_id {
+                Some(row_id) if !RowIds::agent_changed(connection, row_id, agent)? => row_id,
+                row_id => SQLiteCache::write_agent(connection, agent, row_id)?,
+            };
+            results.push(row_id);
+        }
+        Ok(results)
+    }
+
+    /// true if the author row differs from the parsed agent, or misses the parts of its name
+    fn agent_changed(connection: &Connection, row_id: i64, agent: &Agent) -> Result<bool, Error> {
+        let stored = connection
+            .prepare_cached(
+                "SELECT name, agentid, birthdate, deathdate, displayname IS NULL FROM authors WHERE id = ?",
+            )?
+            .query_row((row_id,), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, Option<i32>>(2)?,
+                    row.get::<_, Option<i32>>(3)?,
+                    row.get::<_, bool>(4)?,
+                ))
+            })?;
+        if stored
+            != (
+                agent.name.clone(),
+                agent.agent_id,
+                agent.birth_year,
+                agent.death_year,
+                false,
+            )
+        {
+            return Ok(true);
+        }
+        let values = |sql: &str| -> Result<Vec<String>, Error> {
+            let mut stmt = connection.prepare_cached(sql)?;
+            let mut rows = stmt.query((row_id,))?;
+            let mut results = Vec::new();
+            while let Some(row) = rows.next()? {
+                results.push(row.get(0)?);
+            }
+            Ok(results)
+        };
+        Ok(
+            values("SELECT name FROM author_aliases WHERE authorid = ? ORDER BY rowid")?
+                != agent.aliases
+                || values("SELECT url FROM author_webpages WHERE authorid = ? ORDER BY rowid")?
+                    != agent.webpages,
+        )
+    }
+
+    fn resolve_table(
+        connection: &Connection,
+        table: &str,
+        dictionary: &IndexMap<String, DictionaryItemContent>,
+    ) -> Result<Vec<i64>, Error> {
+        let mut select =
+            connection.prepare(&format!("SELECT id FROM {} WHERE name = ? LIMIT 1", table))?;
+        let mut insert = connection.prepare(&format!("INSERT INTO {}(name) VALUES (?)", table))?;
+        let mut results = Vec::with_capacity(dictionary.len());
+        for (name, _) in dictionary.iter() {
+            let mut rows = select.query((name,))?;
+            let row_id = match rows.next()? {
+                Some(row) => row.get(0)?,
+                None => {
+                    insert.execute((name,))?;
+                    connection.last_insert_rowid()
+                }
+            };
+            results.push(row_id);
+        }
+        Ok(results)
+    }
+
+    /// converts a `dictionary index + 1` id stored in a parsed book, ids below 1 mean no value
+    fn get(rows: &[i64], id: i32) -> i64 {
+        match id {
+            id if id > 0 => rows.get(id as usize - 1).copied().unwrap_or(-1),
+            _ => -1,
+        }
     }
 }
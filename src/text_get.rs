@@ -129,13 +129,13 @@ pub async fn get_text_from_link(
             return match fs::read_to_string(file_cache_location) {
                 Ok(data) => Ok(data),
                 Err(e) => Err(Error::InvalidCacheLocation(
-                    format!("could not read cache: {} (error:{})", path, e.to_string()).to_string(),
+                    format!("could not read cache: {} (error:{})", path, e).to_string(),
                 )),
             };
         }
     }
     Err(Error::InvalidCacheLocation(
-        format!("Invalid url {}", the_url.to_string()).to_string(),
+        format!("Invalid url {}", the_url).to_string(),
     ))
 }
 
@@ -145,7 +145,7 @@ fn line_starts_with_any(line: &str, tokens: &[&str]) -> bool {
             return true;
         }
     }
-    return false;
+    false
 }
 
 /// This is a helper function that you can call on a downloaded book to strip it from the gutenberg headers
@@ -153,7 +153,7 @@ pub fn strip_headers(text: String) -> String {
     #[cfg(windows)]
     const LINE_ENDING: &'static str = "\r\n";
     #[cfg(not(windows))]
-    const LINE_ENDING: &'static str = "\n";
+    const LINE_ENDING: &str = "\n";
 
     let lines = text.split(LINE_ENDING);
     let mut out: Vec<&str> = Vec::new();
@@ -197,9 +197,9 @@ pub fn strip_headers(text: String) -> String {
         if !ignore_section {
             let stripline = line.trim_end_matches(LINE_ENDING);
             out.push(stripline);
-            i = i + 1;
+            i += 1;
         }
     }
 
-    return out.join(LINE_ENDING);
+    out.join(LINE_ENDING)
 }
@@ -47,12 +47,12 @@ pub async fn download_file(url: &str, path: &str, show_progress_bar: bool) -> Re
         pb = Some(p);
     }
     while let Some(item) = stream.next().await {
-        let chunk = item.or(Err(Error::InvalidRequest(format!(
-            "Error while downloading file"
-        ))))?;
-        file.write(&chunk).or(Err(Error::InvalidRequest(format!(
-            "Error while writing to file"
-        ))))?;
+        let chunk = item.or(Err(Error::InvalidRequest(
+            "Error while downloading file".to_string(),
+        )))?;
+        file.write_all(&chunk).or(Err(Error::InvalidRequest(
+            "Error while writing to file".to_string(),
+        )))?;
         let new = min(downloaded + (chunk.len() as u64), total_size);
         downloaded = new;
         if let Some(p) = &mut pb {
@@ -64,7 +64,7 @@ pub async fn download_file(url: &str, path: &str, show_progress_bar: bool) -> Re
         p.finish();
     }
 
-    return Ok(());
+    Ok(())
 }
 
 pub fn decompress_tar_bz(path: &str, show_progress_bar: bool) -> Result<(), Error> {
@@ -87,7 +87,7 @@ pub fn decompress_bz(path: &str, show_progress_bar: bool) -> Result<(u64, String
     }
     let mut decoder = BzDecoder::new(bz_file);
     let big_data_size = 1024 * 1024;
-    let mut total_archive_size = 0 as u64;
+    let mut total_archive_size = 0_u64;
     let mut output_file = File::create(new_filename)?;
 
     loop {
@@ -97,7 +97,7 @@ pub fn decompress_bz(path: &str, show_progress_bar: bool) -> Result<(u64, String
         if big_data_size > data_len as usize {
             read_buffer.resize(data_len as usize, 0);
         }
-        output_file.write(&read_buffer)?;
+        output_file.write_all(&read_buffer)?;
         if let Some(p) = &mut pb {
             p.set_position(decoder.total_in());
         }
@@ -122,14 +122,14 @@ pub fn decompress_tar(path: &str, initial_size: u64, show_progress_bar: bool) ->
         let p = ProgressBar::new(initial_size);
         p.set_style(ProgressStyle::with_template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.white/blue}] {bytes}/{total_bytes} ({eta})")
         ?.progress_chars("█  "));
-        p.set_message(format!("Unpacking to folder"));
+        p.set_message("Unpacking to folder".to_string());
         pb = Some(p);
     }
     for entry in archive.entries()? {
         let mut entry_value = entry?;
         entry_value.unpack_in(".")?;
         if let Some(p) = &mut pb {
-            p.set_position(entry_value.raw_header_position() as u64);
+            p.set_position(entry_value.raw_header_position());
         }
     }
     if let Some(p) = &mut pb {
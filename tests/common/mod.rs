@@ -0,0 +1,40 @@
+// every test file uses its own part of these helpers
+#![allow(dead_code)]
+
+use gutenberg_rs::fst_parser::ParseResult;
+use gutenberg_rs::rdf_parser::parse_rdfs_from_content;
+use gutenberg_rs::settings::GutenbergCacheSettings;
+use gutenberg_rs::sqlite_cache::SQLiteCache;
+
+pub static SAMPLE_1: &str = include_str!("../documents/pg1.rdf");
+pub static SAMPLE_2: &str = include_str!("../documents/pg25.rdf");
+pub static SAMPLE_3: &str = include_str!("../documents/pg732.rdf");
+pub static SAMPLE_4: &str = include_str!("../documents/pg1000.rdf");
+pub static SAMPLE_5: &str = include_str!("../documents/pg90907.rdf");
+pub static SAMPLE_6: &str = include_str!("../documents/pg41418.rdf");
+
+/// the sample rdfs, in the order of their `SAMPLE_*` statics
+pub fn fixture_documents() -> Vec<String> {
+    vec![
+        SAMPLE_1.to_string(),
+        SAMPLE_2.to_string(),
+        SAMPLE_3.to_string(),
+        SAMPLE_4.to_string(),
+        SAMPLE_5.to_string(),
+        SAMPLE_6.to_string(),
+    ]
+}
+
+/// an in-memory cache of the parsed rdfs
+pub fn cache_from(parsing_results: &ParseResult) -> SQLiteCache {
+    let settings = GutenbergCacheSettings {
+        db_in_memory: true,
+        ..Default::default()
+    };
+    SQLiteCache::create_cache(parsing_results, &settings, true, false).unwrap()
+}
+
+/// an in-memory cache of the rdfs
+pub fn build_cache(documents: Vec<String>) -> SQLiteCache {
+    cache_from(&parse_rdfs_from_content(&documents, false).unwrap())
+}
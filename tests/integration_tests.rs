@@ -1,19 +1,32 @@
-use gutenberg_rs::fst_parser::ParseResult;
+mod common;
+
+use common::{
+    build_cache, cache_from, fixture_documents, SAMPLE_1, SAMPLE_2, SAMPLE_3, SAMPLE_4, SAMPLE_5,
+    SAMPLE_6,
+};
+use gutenberg_rs::book::GutenbergFileEntry;
+use gutenberg_rs::book_query::{BookQuery, QueryExpr, QueryField, SortDirection, SortField};
+use gutenberg_rs::book_record::{
+    lcc_class_name, parse_author_name, role_name, split_title, AuthorName,
+};
+use gutenberg_rs::error::Error;
+use gutenberg_rs::fst_parser::{CustomField, FSTParser, ParseItemResult, ParseResult};
 use gutenberg_rs::fst_parser_type::ParseType;
-use gutenberg_rs::rdf_parser::parse_rdfs_from_content;
+use gutenberg_rs::language::{
+    find_language, language_code, macrolanguage_code, normalize_language_tag, LANGUAGES,
+};
+use gutenberg_rs::rdf_parser::{
+    parse_rdfs_from_content, parse_rdfs_from_content_lenient, parse_rdfs_from_content_with,
+    parse_rdfs_from_folder, parse_rdfs_from_folder_lenient, parse_rdfs_from_tar_bz,
+    stream_books_from_content, stream_books_from_tar_bz, ParseOptions,
+};
 use gutenberg_rs::settings::GutenbergCacheSettings;
-use gutenberg_rs::sqlite_cache::SQLiteCache;
+use gutenberg_rs::setup_sqlite;
+use gutenberg_rs::sqlite_cache::{FacetCount, SQLiteCache, SCHEMA_VERSION};
 use serde_json::json;
 use serde_json::Value;
 use std::collections::HashSet;
 
-static SAMPLE_1: &str = include_str!("../tests/documents/pg1.rdf");
-static SAMPLE_2: &str = include_str!("../tests/documents/pg25.rdf");
-static SAMPLE_3: &str = include_str!("../tests/documents/pg732.rdf");
-static SAMPLE_4: &str = include_str!("../tests/documents/pg1000.rdf");
-static SAMPLE_5: &str = include_str!("../tests/documents/pg90907.rdf");
-static SAMPLE_6: &str = include_str!("../tests/documents/pg41418.rdf");
-
 pub struct BookTitleAuthor {
     pub title: String,
     pub author: String,
@@ -49,26 +62,15 @@ fn check_title_author_book_id(
             assert_eq!(x.author, author);
         }
         Err(e) => {
-            println!(
-                "sqlite error: {}  for query {}",
-                e.to_string(),
-                query_string
-            );
-            assert!(false);
+            println!("sqlite error: {}  for query {}", e, query_string);
+            panic!();
         }
     }
 }
 #[test]
 fn test_parse_authors_titles() {
-    let documents = vec![
-        SAMPLE_1.to_string(),
-        SAMPLE_2.to_string(),
-        SAMPLE_3.to_string(),
-        SAMPLE_4.to_string(),
-        SAMPLE_5.to_string(),
-        SAMPLE_6.to_string(),
-    ];
-    let check_author_titles = vec![
+    let documents = fixture_documents();
+    let check_author_titles = [
         CheckTitleAuthor {
             author: "Jefferson, Thomas".to_string(),
             title: "The Declaration of Independence of the United States of America".to_string(),
@@ -103,12 +105,14 @@ fn test_parse_authors_titles() {
 
     let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
     assert_eq!(parsing_results.books.len(), documents.len());
-    assert_eq!(parsing_results.field_dictionaries.len(), 10);
+    assert_eq!(parsing_results.field_dictionaries.len(), ParseType::COUNT);
     assert_eq!(parsing_results.files_dictionary.len(), 75);
     assert_eq!(parsing_results.file_types_dictionary.len(), 12);
     // build sqlite
-    let mut settings = GutenbergCacheSettings::default();
-    settings.db_in_memory = true;
+    let settings = GutenbergCacheSettings {
+        db_in_memory: true,
+        ..Default::default()
+    };
 
     let cache = SQLiteCache::create_cache(&parsing_results, &settings, true, false);
 
@@ -124,29 +128,29 @@ fn test_parse_authors_titles() {
     assert_eq!(parsing_results.books[5].author_ids.len(), 1);
     match cache {
         Ok(mut x) => {
-            for i in 0..6 {
+            for (i, check) in check_author_titles.iter().enumerate() {
                 if i == 4 {
                     continue;
                 }
                 match authors.get_index(parsing_results.books[i].author_ids[0]) {
-                    Some(x) => assert_eq!(x.0, &check_author_titles[i].author),
-                    None => assert!(false),
+                    Some(x) => assert_eq!(x.0, &check.author),
+                    None => panic!(),
                 }
                 match titles.get_index(parsing_results.books[i].title_id as usize) {
-                    Some(x) => assert_eq!(x.0, &check_author_titles[i].title),
-                    None => assert!(false),
+                    Some(x) => assert_eq!(x.0, &check.title),
+                    None => panic!(),
                 }
                 check_title_author_book_id(
                     &mut x,
-                    check_author_titles[i].author.as_str(),
-                    check_author_titles[i].title.as_str(),
-                    check_author_titles[i].gutenberg_id,
+                    check.author.as_str(),
+                    check.title.as_str(),
+                    check.gutenberg_id,
                 );
             }
         }
         Err(x) => {
-            println!("error {}", x.to_string());
-            assert!(false);
+            println!("error {}", x);
+            panic!();
         }
     }
 }
@@ -160,23 +164,18 @@ fn compare_query_results(x: &mut SQLiteCache, json: &Value, expected: Vec<i32>)
             assert_eq!(set1, set2);
         }
         Err(x) => {
-            println!("error {}", x.to_string());
-            assert!(false);
+            println!("error {}", x);
+            panic!();
         }
     }
 }
 #[test]
 fn test_query() {
-    let documents = vec![
-        SAMPLE_1.to_string(),
-        SAMPLE_2.to_string(),
-        SAMPLE_3.to_string(),
-        SAMPLE_4.to_string(),
-        SAMPLE_5.to_string(),
-        SAMPLE_6.to_string(),
-    ];
-    let mut settings = GutenbergCacheSettings::default();
-    settings.db_in_memory = true;
+    let documents = fixture_documents();
+    let settings = GutenbergCacheSettings {
+        db_in_memory: true,
+        ..Default::default()
+    };
     let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
     let cache = SQLiteCache::create_cache(&parsing_results, &settings, true, false);
     match cache {
@@ -213,8 +212,2336 @@ fn test_query() {
             );
         }
         Err(x) => {
-            println!("error {}", x.to_string());
-            assert!(false);
+            println!("error {}", x);
+            panic!();
+        }
+    }
+}
+
+fn compare_find_results(x: &mut SQLiteCache, query: &BookQuery, expected: Vec<i32>) {
+    match x.find(query) {
+        Ok(x) => {
+            let set1: HashSet<i32> = x.iter().copied().collect();
+            let set2: HashSet<i32> = expected.into_iter().collect();
+            assert_eq!(set1, set2);
+        }
+        Err(x) => {
+            println!("error {}", x);
+            panic!();
+        }
+    }
+}
+
+#[test]
+fn test_book_query() {
+    let mut x = build_cache(fixture_documents());
+
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().language("en"),
+        vec![41418, 25, 732, 90907, 1],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().language("en").author("Jefferson, Thomas"),
+        vec![1],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().language_any(["en", "it"]),
+        vec![41418, 25, 732, 90907, 1, 1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().bookshelf_any(["IT Poesia", "Fantasy"]),
+        vec![1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new(),
+        vec![41418, 25, 732, 90907, 1, 1000],
+    );
+
+    // values are bound, never spliced into the sql text
+    compare_find_results(&mut x, &BookQuery::new().language("en') OR 1=1 --"), vec![]);
+    compare_query_results(
+        &mut x,
+        &json!({"author": "'Jefferson, Thomas'') OR 1=1 --'"}),
+        vec![],
+    );
+
+    // the json adapter accepts arrays and unquoted single values
+    compare_query_results(
+        &mut x,
+        &json!({"language": ["en", "it"], "author": "Dante Alighieri"}),
+        vec![1000],
+    );
+    compare_query_results(
+        &mut x,
+        &json!({"bookshelve": "'Fantasy',\n 'IT Poesia'"}),
+        vec![1000],
+    );
+    assert!(x.query(&json!({"language": 1})).is_err());
+    assert!(x.query(&json!({"language": "'en"})).is_err());
+}
+
+#[test]
+fn test_query_boolean_and_patterns() {
+    let mut x = build_cache(fixture_documents());
+
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().with_any([
+            QueryExpr::is(QueryField::Language, "it"),
+            QueryExpr::is(QueryField::Author, "Jefferson, Thomas"),
+        ]),
+        vec![1, 1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new()
+            .language("en")
+            .exclude(QueryExpr::is(QueryField::Bookshelf, "Politics")),
+        vec![41418, 25, 732, 90907],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().prefix(QueryField::Author, "Whyte"),
+        vec![41418],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().prefix(QueryField::Author, "whyte"),
+        vec![],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().with(QueryExpr::prefix(QueryField::Author, "whyte").ignore_case()),
+        vec![41418],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().contains(QueryField::Bookshelf, "Factbooks"),
+        vec![25],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new()
+            .with(QueryExpr::contains(QueryField::Bookshelf, "REVOLUTIONARY").ignore_case())
+            .exclude(QueryExpr::contains(QueryField::Title, "Roman")),
+        vec![1],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().with(QueryExpr::is(QueryField::Language, "IT").ignore_case()),
+        vec![1000],
+    );
+    // like wildcards in the value are matched literally
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().with(QueryExpr::contains(QueryField::Title, "%").ignore_case()),
+        vec![],
+    );
+
+    compare_query_results(
+        &mut x,
+        &json!({"or": [{"language": ["it"]}, {"author": {"prefix": "Jefferson"}}]}),
+        vec![1, 1000],
+    );
+    compare_query_results(
+        &mut x,
+        &json!({
+            "language": ["en", "fr"],
+            "not": {"bookshelve": ["Politics"]},
+            "bookshelve": {"contains": "factbooks", "ignore_case": true}
+        }),
+        vec![25],
+    );
+    assert!(x.query(&json!({"author": {"prefix": 1}})).is_err());
+    assert!(x
+        .query(&json!({"author": {"prefix": "a", "contains": "b"}}))
+        .is_err());
+    assert!(x.query(&json!({"unknown": "a"})).is_err());
+}
+
+#[test]
+fn test_query_order_and_pages() {
+    let mut x = build_cache(fixture_documents());
+
+    let by_downloads = BookQuery::new().order_by(SortField::Downloads, SortDirection::Descending);
+    assert_eq!(
+        x.find(&by_downloads).unwrap(),
+        vec![1, 1000, 25, 732, 41418, 90907]
+    );
+    assert_eq!(
+        x.find(&by_downloads.clone().language("en").limit(3))
+            .unwrap(),
+        vec![1, 25, 732]
+    );
+    assert_eq!(
+        x.find(&by_downloads.clone().limit(2).offset(3)).unwrap(),
+        vec![732, 41418]
+    );
+    assert_eq!(
+        x.find(&BookQuery::new().order_by(SortField::GutenbergId, SortDirection::Ascending))
+            .unwrap(),
+        vec![1, 25, 732, 1000, 41418, 90907]
+    );
+    // pages without an explicit order follow the gutenberg id
+    assert_eq!(
+        x.find(&BookQuery::new().limit(2).offset(1)).unwrap(),
+        vec![25, 732]
+    );
+    assert_eq!(
+        x.find(&BookQuery::new().offset(4)).unwrap(),
+        vec![41418, 90907]
+    );
+    let (sql, _) = BookQuery::new().language("en").limit(2).to_sql();
+    assert!(sql.contains("ORDER BY books.gutenbergbookid ASC"));
+    assert_eq!(
+        x.find(&BookQuery::new().order_by(SortField::DateIssued, SortDirection::Descending))
+            .unwrap(),
+        vec![90907, 41418, 732, 1000, 25, 1]
+    );
+    assert_eq!(
+        x.find(&BookQuery::new().order_by(SortField::Title, SortDirection::Ascending))
+            .unwrap(),
+        vec![90907, 41418, 732, 1000, 25, 1]
+    );
+
+    // walk all the books two at a time with the cursor
+    let mut all = Vec::new();
+    let mut query = by_downloads.clone().limit(2);
+    loop {
+        let page = x.find_page(&query).unwrap();
+        all.extend(page.ids);
+        match page.next_cursor {
+            Some(cursor) => query = query.after(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(all, vec![1, 1000, 25, 732, 41418, 90907]);
+
+    let mut all = Vec::new();
+    let mut query = BookQuery::new()
+        .order_by(SortField::Title, SortDirection::Ascending)
+        .limit(4);
+    loop {
+        let page = x.find_page(&query).unwrap();
+        all.extend(page.ids);
+        match page.next_cursor {
+            Some(cursor) => query = query.after(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(all, vec![90907, 41418, 732, 1000, 25, 1]);
+
+    // the alternative titles are not sorted on
+    let mut documents = fixture_documents();
+    documents[3] = SAMPLE_4.replacen(
+        "<dcterms:title>",
+        "<dcterms:alternative>Commedia</dcterms:alternative><dcterms:title>",
+        1,
+    );
+    let mut alternatives = build_cache(documents);
+    assert_eq!(
+        alternatives
+            .find(&BookQuery::new().order_by(SortField::Title, SortDirection::Ascending))
+            .unwrap(),
+        vec![90907, 41418, 732, 1000, 25, 1]
+    );
+
+    assert_eq!(
+        x.query(&json!({"language": ["en"], "order_by": "downloads", "order": "desc", "limit": 2}))
+            .unwrap(),
+        vec![1, 25]
+    );
+    assert!(x.query(&json!({"order_by": "nothing"})).is_err());
+}
+
+#[test]
+fn test_search() {
+    let mut x = build_cache(fixture_documents());
+
+    let results = x.search("divina commedia", 10).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].gutenberg_id, 1000);
+    assert!(results[0].snippet.contains("[Divina]"));
+
+    // matches across fields and ignores case
+    let results = x.search("roman gibbon", 10).unwrap();
+    assert_eq!(
+        results.iter().map(|x| x.gutenberg_id).collect::<Vec<i32>>(),
+        vec![732]
+    );
+
+    // the more matches the better the rank
+    let results = x.search("united", 10).unwrap();
+    assert_eq!(
+        results.iter().map(|x| x.gutenberg_id).collect::<Vec<i32>>(),
+        vec![1, 25]
+    );
+    for pair in results.windows(2) {
+        assert!(pair[0].rank <= pair[1].rank);
+    }
+
+    // punctuation is not taken as fts syntax
+    assert!(x.search("contraband; or, \"a", 10).is_ok());
+    assert!(x.search("", 10).unwrap().is_empty());
+
+    let results = x
+        .search_match("titles:factbook OR authors:dant*", 10)
+        .unwrap();
+    let ids: HashSet<i32> = results.iter().map(|x| x.gutenberg_id).collect();
+    assert_eq!(ids, HashSet::from([25, 1000]));
+}
+
+#[test]
+fn test_get_book() {
+    let mut x = build_cache(fixture_documents());
+
+    let book = x.get_book(1).unwrap().unwrap();
+    assert_eq!(book.gutenberg_id, 1);
+    assert_eq!(
+        book.titles,
+        vec!["The Declaration of Independence of the United States of America"]
+    );
+    assert_eq!(book.authors, vec!["Jefferson, Thomas"]);
+    assert_eq!(book.languages, vec!["en"]);
+    assert_eq!(
+        book.subjects,
+        vec![
+            "United States -- History -- Revolution, 1775-1783 -- Sources",
+            "United States. Declaration of Independence",
+        ]
+    );
+    assert_eq!(book.lcc, vec!["E201", "JK"]);
+    assert_eq!(
+        book.bookshelves,
+        vec![
+            "Politics",
+            "American Revolutionary War",
+            "United States Law"
+        ]
+    );
+    assert_eq!(book.rights.as_deref(), Some("Public domain in the USA."));
+    assert_eq!(book.publisher.as_deref(), Some("Project Gutenberg"));
+    assert_eq!(book.date_issued.as_deref(), Some("1971-12-01"));
+    assert_eq!(book.num_downloads, 1184);
+    assert!(book.files["text/plain"]
+        .iter()
+        .any(|x| x == "https://www.gutenberg.org/ebooks/1.txt.utf-8"));
+    assert_eq!(book.files.values().map(|x| x.len()).sum::<usize>(), 15);
+
+    assert!(x.get_book(2).unwrap().is_none());
+
+    let books = x.get_books(&[1000, 2, 90907]).unwrap();
+    assert_eq!(books.len(), 2);
+    assert_eq!(books[0].gutenberg_id, 1000);
+    assert_eq!(books[0].authors, vec!["Dante Alighieri"]);
+    assert_eq!(books[1].gutenberg_id, 90907);
+    assert!(books[1].titles.is_empty());
+    assert!(books[1].authors.is_empty());
+
+    let json = serde_json::to_value(&books[0]).unwrap();
+    assert_eq!(json["languages"], json!(["it"]));
+    assert_eq!(json["gutenberg_id"], json!(1000));
+}
+
+#[test]
+fn test_file_metadata() {
+    let documents = vec![SAMPLE_1.to_string(), SAMPLE_4.to_string()];
+    let mut x = build_cache(documents);
+
+    let book = x.get_book(1).unwrap().unwrap();
+    assert_eq!(book.downloads.len(), 15);
+    let zipped_html = book
+        .downloads
+        .iter()
+        .find(|x| x.url == "https://www.gutenberg.org/files/1/1-h.zip")
+        .unwrap();
+    assert_eq!(zipped_html.formats, vec!["text/html", "application/zip"]);
+    assert_eq!(zipped_html.extent, Some(2189844));
+    assert_eq!(zipped_html.modified.as_deref(), Some("2021-01-28T05:38:53"));
+    assert!(zipped_html.is_zipped());
+    // the zipped html is still listed under its first format
+    assert!(book.files["text/html"].contains(&zipped_html.url));
+
+    // the zip is smaller than the plain text but it is skipped
+    let smallest = x.smallest_file(1, "text/plain").unwrap().unwrap();
+    assert_eq!(smallest.url, "https://www.gutenberg.org/files/1/1-0.txt");
+    assert_eq!(smallest.extent, Some(120943));
+    assert!(x.smallest_file(1, "audio/").unwrap().is_none());
+    assert!(x.smallest_file(2, "text/plain").unwrap().is_none());
+
+    // every format of a file can be queried
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().download_links_type("application/zip"),
+        vec![1, 1000],
+    );
+
+    let changed = x.files_modified_since("2022-10-01T03:30:12").unwrap();
+    assert!(changed.contains(&"https://www.gutenberg.org/ebooks/1.epub.images".to_string()));
+    assert!(!changed.contains(&"https://www.gutenberg.org/ebooks/1.txt.utf-8".to_string()));
+}
+
+#[test]
+fn test_subject_vocabularies() {
+    let documents = vec![
+        SAMPLE_1.to_string(),
+        SAMPLE_2.to_string(),
+        SAMPLE_4.to_string(),
+        SAMPLE_6.to_string(),
+    ];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    let subjects = &parsing_results.field_dictionaries[ParseType::Subject as usize];
+    let vocabulary = |name: &str| {
+        parsing_results
+            .subject_vocabularies
+            .get(&subjects.get_index_of(name).unwrap())
+            .cloned()
+    };
+    assert_eq!(vocabulary("JK").as_deref(), Some("LCC"));
+    assert_eq!(vocabulary("Epic poetry, Italian").as_deref(), Some("LCSH"));
+
+    let mut x = cache_from(&parsing_results);
+    let book = x.get_book(1000).unwrap().unwrap();
+    assert_eq!(book.lcc, vec!["PQ"]);
+    assert!(!book.subjects.contains(&"PQ".to_string()));
+
+    compare_find_results(&mut x, &BookQuery::new().lcc_prefix("PR"), vec![41418]);
+    compare_find_results(&mut x, &BookQuery::new().lcc_prefix("P"), vec![1000, 41418]);
+    compare_find_results(&mut x, &BookQuery::new().lcc("G"), vec![25]);
+    // the subject field still matches both vocabularies
+    compare_find_results(&mut x, &BookQuery::new().subject("JK"), vec![1]);
+
+    let classes = x
+        .facet_counts(QueryField::Lcc, None, None)
+        .unwrap()
+        .iter()
+        .map(|x| lcc_class_name(&x.value).unwrap())
+        .collect::<HashSet<&str>>();
+    assert_eq!(
+        classes,
+        HashSet::from([
+            "History of the Americas",
+            "Political Science",
+            "Geography, Anthropology, Recreation",
+            "Language and Literature"
+        ])
+    );
+    assert_eq!(lcc_class_name("pr"), Some("Language and Literature"));
+    assert_eq!(lcc_class_name("I"), None);
+    assert_eq!(lcc_class_name(""), None);
+
+    // the vocabulary of a subject may come after its value
+    let swapped = SAMPLE_4.replace(
+        "<dcam:memberOf rdf:resource=\"http://purl.org/dc/terms/LCC\"/>\n        <rdf:value>PQ</rdf:value>",
+        "<rdf:value>PQ</rdf:value>\n        <dcam:memberOf rdf:resource=\"http://purl.org/dc/terms/LCC\"/>",
+    );
+    assert_ne!(swapped, SAMPLE_4);
+    let options = ParseOptions {
+        custom_fields: vec![CustomField::path(
+            "lcc",
+            "rdf:Description[dcam:memberOf/@rdf:resource='http://purl.org/dc/terms/LCC']/rdf:value",
+        )
+        .unwrap()],
+        ..Default::default()
+    };
+    let (parsing_results, _) = parse_rdfs_from_content_with(&[swapped], &options, false).unwrap();
+    let book = cache_from(&parsing_results)
+        .get_book(1000)
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.lcc, vec!["PQ"]);
+    assert!(!book.subjects.contains(&"PQ".to_string()));
+    assert_eq!(book.custom_fields["lcc"], vec!["PQ"]);
+}
+
+#[test]
+fn test_descriptions_and_types() {
+    // an audio book made out of pg1, with the fields our samples don't have
+    let audio_book = SAMPLE_1.replace("ebooks/1\"", "ebooks/90001\"").replace(
+        "<dcterms:license rdf:resource=\"license\"/>",
+        "<dcterms:license rdf:resource=\"license\"/>
+            <dcterms:tableOfContents>Part one -- Part two</dcterms:tableOfContents>
+            <pgterms:marc520>A reading of the declaration.</pgterms:marc520>
+            <dcterms:type>
+              <rdf:Description rdf:nodeID=\"N1\">
+                <rdf:value>Sound</rdf:value>
+                <dcam:memberOf rdf:resource=\"http://purl.org/dc/terms/DCMIType\"/>
+              </rdf:Description>
+            </dcterms:type>",
+    );
+    let documents = vec![
+        SAMPLE_1.to_string(),
+        SAMPLE_4.to_string(),
+        SAMPLE_5.to_string(),
+        audio_book,
+    ];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    let mut x = cache_from(&parsing_results);
+
+    let book = x.get_book(1).unwrap().unwrap();
+    // the description of the author webpage at the end of the rdf is not the book's
+    assert_eq!(
+        book.descriptions,
+        vec!["This is the original PG edition.\r\nSee also our revised edition: #16780\r\nSee also #300"]
+    );
+    assert!(book.table_of_contents.is_empty());
+    assert_eq!(book.book_type, None);
+    assert_eq!(book.license.as_deref(), Some("license"));
+    assert_eq!(
+        x.get_book(90907).unwrap().unwrap().book_type.as_deref(),
+        Some("Text")
+    );
+
+    let audio = x.get_book(90001).unwrap().unwrap();
+    assert_eq!(audio.table_of_contents, vec!["Part one -- Part two"]);
+    assert_eq!(audio.summaries, vec!["A reading of the declaration."]);
+    assert_eq!(audio.book_type.as_deref(), Some("Sound"));
+
+    compare_find_results(&mut x, &BookQuery::new().book_type("Sound"), vec![90001]);
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().exclude_sound_and_images(),
+        vec![1, 1000, 90907],
+    );
+
+    // the descriptions are searchable
+    let hits = x
+        .search_match("descriptions:revised", 10)
+        .unwrap()
+        .iter()
+        .map(|r| r.gutenberg_id)
+        .collect::<HashSet<i32>>();
+    assert_eq!(hits, HashSet::from([1, 90001]));
+    assert_eq!(
+        x.search("reading declaration", 10).unwrap()[0].gutenberg_id,
+        90001
+    );
+
+    // an update keeps them in sync
+    let report = x
+        .update_cache(&parsing_results, false, None, false)
+        .unwrap();
+    assert_eq!(report.unchanged, 4);
+}
+
+#[test]
+fn test_lenient_parsing() {
+    let mismatched_end = SAMPLE_4.replacen("</dcterms:title>", "</dcterms:titel>", 1);
+    let bad_id = SAMPLE_2.replace("ebooks/25\"", "ebooks/x/25\"");
+    let documents = vec![
+        SAMPLE_1.to_string(),
+        mismatched_end,
+        SAMPLE_3.to_string(),
+        bad_id,
+    ];
+
+    // by default the first invalid rdf fails the parse, with where it happened
+    match parse_rdfs_from_content(&documents, false) {
+        Err(Error::InvalidRdfFile {
+            file,
+            position,
+            reason,
+        }) => {
+            assert_eq!(file, "content #1");
+            assert!(position > 0 && position < documents[1].len());
+            assert!(reason.contains("dcterms:titel"));
+        }
+        _ => panic!("expected an rdf file error"),
+    }
+
+    let (parsing_results, report) = parse_rdfs_from_content_lenient(&documents, false).unwrap();
+    assert_eq!(report.parsed, 2);
+    assert_eq!(
+        report
+            .skipped
+            .iter()
+            .map(|x| x.file.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["content #1", "content #3"]
+    );
+    assert!(report.skipped[1].reason.contains("ebooks/x/25"));
+    assert_eq!(
+        parsing_results
+            .books
+            .iter()
+            .map(|x| x.gutenberg_book_id)
+            .collect::<Vec<usize>>(),
+        vec![1, 732]
+    );
+
+    // nothing is left from the skipped rdfs
+    let valid = vec![SAMPLE_1.to_string(), SAMPLE_3.to_string()];
+    let expected = parse_rdfs_from_content(&valid, false).unwrap();
+    for (lenient, strict) in parsing_results
+        .field_dictionaries
+        .iter()
+        .zip(expected.field_dictionaries.iter())
+    {
+        assert_eq!(
+            lenient.keys().collect::<Vec<&String>>(),
+            strict.keys().collect::<Vec<&String>>()
+        );
+        for (a, b) in lenient.values().zip(strict.values()) {
+            assert_eq!(a.book_links, b.book_links);
+        }
+    }
+    assert_eq!(
+        parsing_results.files_dictionary.len(),
+        expected.files_dictionary.len()
+    );
+    assert_eq!(
+        parsing_results.file_types_dictionary.len(),
+        expected.file_types_dictionary.len()
+    );
+    assert_eq!(
+        parsing_results.subject_vocabularies,
+        expected.subject_vocabularies
+    );
+
+    let mut x = cache_from(&parsing_results);
+    assert_eq!(x.find(&BookQuery::new()).unwrap().len(), 2);
+
+    // an rdf without an ebook id is not a book 0
+    let no_ebook = vec![
+        SAMPLE_1.to_string(),
+        "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\"></rdf:RDF>".to_string(),
+    ];
+    match parse_rdfs_from_content(&no_ebook, false) {
+        Err(Error::InvalidRdfFile { file, reason, .. }) => {
+            assert_eq!(file, "content #1");
+            assert!(reason.contains("pgterms:ebook"));
+        }
+        _ => panic!("expected an rdf file error"),
+    }
+    let (parsing_results, report) = parse_rdfs_from_content_lenient(&no_ebook, false).unwrap();
+    assert_eq!(parsing_results.books.len(), 1);
+    assert_eq!(parsing_results.books[0].gutenberg_book_id, 1);
+    assert_eq!(report.skipped.len(), 1);
+
+    // files are reported by path
+    let folder = std::env::temp_dir().join(format!("gutenberg_rs_lenient_{}", std::process::id()));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(folder.join("pg1.rdf"), SAMPLE_1).unwrap();
+    std::fs::write(folder.join("pg1000.rdf"), &documents[1]).unwrap();
+    let folder_name = folder.display().to_string();
+    match parse_rdfs_from_folder(&folder_name, false) {
+        Err(Error::InvalidRdfFile { file, .. }) => assert!(file.ends_with("pg1000.rdf")),
+        _ => panic!("expected an rdf file error"),
+    }
+    let (parsing_results, report) = parse_rdfs_from_folder_lenient(&folder_name, false).unwrap();
+    assert_eq!(parsing_results.books.len(), 1);
+    assert_eq!(report.skipped.len(), 1);
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_lenient_parsing_keeps_agents() {
+    // the first book only knows the name of agent 507
+    let known = SAMPLE_4
+        .lines()
+        .filter(|x| {
+            !x.contains("pgterms:birthdate")
+                && !x.contains("pgterms:deathdate")
+                && !x.contains("pgterms:alias")
+                && !x.contains("pgterms:webpage")
+        })
+        .collect::<Vec<&str>>()
+        .join("\n");
+    // the rejected books describe it in full, their description must not be merged
+    let mismatched_end = SAMPLE_4.replace("ebooks/1000\"", "ebooks/2000\"").replacen(
+        "</dcterms:title>",
+        "</dcterms:titel>",
+        1,
+    );
+    let bad_downloads = SAMPLE_4
+        .replace("ebooks/1000\"", "ebooks/3000\"")
+        .replace(">389</pgterms:downloads>", ">many</pgterms:downloads>");
+    let documents = vec![known, mismatched_end, bad_downloads];
+    let (parsing_results, report) = parse_rdfs_from_content_lenient(&documents, false).unwrap();
+    assert_eq!(report.parsed, 1);
+    assert_eq!(report.skipped.len(), 2);
+    let dante = &parsing_results.agents_dictionary["507"];
+    assert_eq!(dante.name, "Dante Alighieri");
+    assert_eq!((dante.birth_year, dante.death_year), (None, None));
+    assert!(dante.aliases.is_empty() && dante.webpages.is_empty());
+    assert_eq!(dante.book_links, vec![1]);
+
+    let mut x = cache_from(&parsing_results);
+    let author = x.get_author(507).unwrap().unwrap();
+    assert_eq!(author.birth_year, None);
+    assert!(author.aliases.is_empty());
+}
+
+fn assert_same_parse(a: &ParseResult, b: &ParseResult) {
+    assert_eq!(a.books, b.books);
+    for (x, y) in a.field_dictionaries.iter().zip(b.field_dictionaries.iter()) {
+        assert_eq!(x.iter().collect::<Vec<_>>(), y.iter().collect::<Vec<_>>());
+    }
+    assert_eq!(
+        a.files_dictionary.iter().collect::<Vec<_>>(),
+        b.files_dictionary.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        a.file_types_dictionary.iter().collect::<Vec<_>>(),
+        b.file_types_dictionary.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        a.agents_dictionary.iter().collect::<Vec<_>>(),
+        b.agents_dictionary.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(a.subject_vocabularies, b.subject_vocabularies);
+    assert_eq!(
+        a.custom_dictionaries.keys().collect::<Vec<_>>(),
+        b.custom_dictionaries.keys().collect::<Vec<_>>()
+    );
+    for (x, y) in a
+        .custom_dictionaries
+        .values()
+        .zip(b.custom_dictionaries.values())
+    {
+        assert_eq!(x.iter().collect::<Vec<_>>(), y.iter().collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_parallel_parsing() {
+    // every sample twice, so values are shared across the thread chunks
+    let samples = fixture_documents();
+    let documents = samples
+        .iter()
+        .chain(samples.iter().rev())
+        .cloned()
+        .collect::<Vec<String>>();
+    let sequential = parse_rdfs_from_content(&documents, false).unwrap();
+    for threads in [1, 2, 3, 5, 12, 40] {
+        let options = ParseOptions {
+            threads,
+            ..Default::default()
+        };
+        let (parallel, report) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+        assert_same_parse(&parallel, &sequential);
+        assert_eq!(report.parsed, documents.len());
+    }
+    let (parallel, _) =
+        parse_rdfs_from_content_with(&documents, &ParseOptions::default(), false).unwrap();
+    assert_same_parse(&parallel, &sequential);
+
+    // the errors are the ones of the sequential parse
+    let mut documents = documents;
+    documents[4] = SAMPLE_5.replace("ebooks/90907\"", "ebooks/x/90907\"");
+    documents[9] = SAMPLE_3.replacen("</dcterms:title>", "</dcterms:titel>", 1);
+    let options = ParseOptions {
+        threads: 4,
+        ..Default::default()
+    };
+    match parse_rdfs_from_content_with(&documents, &options, false) {
+        Err(Error::InvalidRdfFile { file, .. }) => assert_eq!(file, "content #4"),
+        _ => panic!("expected an rdf file error"),
+    }
+    let (sequential, sequential_report) =
+        parse_rdfs_from_content_lenient(&documents, false).unwrap();
+    let options = ParseOptions {
+        threads: 4,
+        lenient: true,
+        ..Default::default()
+    };
+    let (parallel, report) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+    assert_same_parse(&parallel, &sequential);
+    assert_eq!(report.parsed, sequential_report.parsed);
+    assert_eq!(
+        report
+            .skipped
+            .iter()
+            .map(|x| (x.file.as_str(), x.position))
+            .collect::<Vec<_>>(),
+        vec![
+            ("content #4", sequential_report.skipped[0].position),
+            ("content #9", sequential_report.skipped[1].position)
+        ]
+    );
+
+    assert!(parse_rdfs_from_content_with(&[], &options, false)
+        .unwrap()
+        .0
+        .books
+        .is_empty());
+}
+
+/// a tar.bz2 archive with the given (path, content) entries
+fn tar_bz(entries: &[(&str, &str)]) -> Vec<u8> {
+    let encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+    for (path, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, content.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+#[test]
+fn test_namespace_prefixes() {
+    // other prefixes for the same namespaces, a default namespace and absolute ebook urls
+    let renamed = SAMPLE_1
+        .replace("pgterms:", "pg:")
+        .replace("xmlns:pgterms=", "xmlns:pg=")
+        .replace("rdf:", "r:")
+        .replace("xmlns:rdf=", "xmlns:r=")
+        .replace("\"ebooks/1\"", "\"http://www.gutenberg.org/ebooks/1\"");
+    let default_namespace = SAMPLE_3
+        .replace("dcterms:", "")
+        .replace("xmlns:dcterms=", "xmlns=")
+        .replace("marcrel:", "relator:")
+        .replace("xmlns:marcrel=", "xmlns:relator=")
+        .replace("\"ebooks/732\"", "\"https://www.gutenberg.org/ebooks/732\"");
+    assert!(!renamed.contains("pgterms:") && !default_namespace.contains("<dcterms:"));
+    let options = ParseOptions {
+        threads: 1,
+        custom_fields: vec![CustomField::attribute(
+            "license",
+            "pgterms:ebook/dcterms:license",
+            "rdf:resource",
+        )
+        .unwrap()],
+        ..Default::default()
+    };
+    let (parsed, _) = parse_rdfs_from_content_with(
+        &[renamed, default_namespace, SAMPLE_2.to_string()],
+        &options,
+        false,
+    )
+    .unwrap();
+    let (expected, _) = parse_rdfs_from_content_with(
+        &[
+            SAMPLE_1.to_string(),
+            SAMPLE_3.to_string(),
+            SAMPLE_2.to_string(),
+        ],
+        &options,
+        false,
+    )
+    .unwrap();
+    assert_same_parse(&parsed, &expected);
+    assert_eq!(
+        parsed
+            .books
+            .iter()
+            .map(|x| x.gutenberg_book_id)
+            .collect::<Vec<_>>(),
+        vec![1, 732, 25]
+    );
+    assert_eq!(parsed.custom_dictionaries["license"].len(), 1);
+
+    let invalid = [
+        SAMPLE_1.replace("\"ebooks/1\"", "\"http://www.gutenberg.org/books/1\""),
+        SAMPLE_1.replace("\"ebooks/1\"", "\"ebooks/\""),
+        SAMPLE_1.replace("rdf:RDF", "rdf:Graph"),
+        "<pgterms:ebook rdf:about=\"ebooks/1\"/>".to_string(),
+        String::new(),
+    ];
+    for document in invalid {
+        match parse_rdfs_from_content(&[document], false) {
+            Err(Error::InvalidRdfFile { file, .. }) => assert_eq!(file, "content #0"),
+            _ => panic!("expected an rdf file error"),
+        }
+    }
+}
+
+#[test]
+fn test_archive_parsing() {
+    let documents = fixture_documents();
+    let names = ["1", "25", "732", "1000", "90907", "41418"]
+        .iter()
+        .map(|x| format!("cache/epub/{}/pg{}.rdf", x, x))
+        .collect::<Vec<String>>();
+    let mut entries = names
+        .iter()
+        .map(String::as_str)
+        .zip(documents.iter().map(String::as_str))
+        .collect::<Vec<(&str, &str)>>();
+    // only the rdfs are parsed
+    entries.insert(2, ("cache/epub/README.txt", "not an rdf"));
+    let archive = tar_bz(&entries);
+
+    let expected = parse_rdfs_from_content(&documents, false).unwrap();
+    for threads in [1, 3] {
+        let options = ParseOptions {
+            threads,
+            ..Default::default()
+        };
+        let (parsing_results, report) =
+            parse_rdfs_from_tar_bz(archive.as_slice(), &options, false).unwrap();
+        assert_same_parse(&parsing_results, &expected);
+        assert_eq!(report.parsed, documents.len());
+        assert!(report.skipped.is_empty());
+    }
+
+    // the errors name the entry of the archive
+    let broken = SAMPLE_3.replacen("</dcterms:title>", "</dcterms:titel>", 1);
+    entries[3] = (names[2].as_str(), broken.as_str());
+    let archive = tar_bz(&entries);
+    match parse_rdfs_from_tar_bz(archive.as_slice(), &ParseOptions::default(), false) {
+        Err(Error::InvalidRdfFile { file, .. }) => assert_eq!(file, names[2]),
+        _ => panic!("expected an rdf file error"),
+    }
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let (parsing_results, report) =
+        parse_rdfs_from_tar_bz(archive.as_slice(), &options, false).unwrap();
+    assert_eq!(parsing_results.books.len(), documents.len() - 1);
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.skipped[0].file, names[2]);
+
+    let archive = tar_bz(&[]);
+    assert!(
+        parse_rdfs_from_tar_bz(archive.as_slice(), &ParseOptions::default(), false)
+            .unwrap()
+            .0
+            .books
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_streaming_books() {
+    let documents = fixture_documents();
+    let mut records = Vec::new();
+    let report = stream_books_from_content(&documents, &ParseOptions::default(), |record| {
+        records.push(record);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(report.parsed, documents.len());
+    assert_eq!(
+        records.iter().map(|x| x.gutenberg_id).collect::<Vec<i32>>(),
+        vec![1, 25, 732, 1000, 90907, 41418]
+    );
+
+    // the streamed books are the ones stored in the cache
+    let parsing_results = parse_rdfs_from_content(&documents, false).unwrap();
+    let mut x = cache_from(&parsing_results);
+    for record in &records {
+        assert_eq!(
+            Some(record),
+            x.get_book(record.gutenberg_id).unwrap().as_ref()
+        );
+    }
+
+    let entries = documents
+        .iter()
+        .enumerate()
+        .map(|(idx, x)| (format!("pg{}.rdf", idx), x.as_str()))
+        .collect::<Vec<(String, &str)>>();
+    let archive = tar_bz(
+        &entries
+            .iter()
+            .map(|(name, content)| (name.as_str(), *content))
+            .collect::<Vec<(&str, &str)>>(),
+    );
+    let mut archive_records = Vec::new();
+    stream_books_from_tar_bz(archive.as_slice(), &ParseOptions::default(), |record| {
+        archive_records.push(record);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(archive_records, records);
+
+    // the callback stops the parse
+    let mut seen = 0;
+    let result = stream_books_from_content(&documents, &ParseOptions::default(), |_| {
+        seen += 1;
+        match seen {
+            2 => Err(Error::InvalidResult("enough".to_string())),
+            _ => Ok(()),
         }
+    });
+    assert!(matches!(result, Err(Error::InvalidResult(_))));
+    assert_eq!(seen, 2);
+
+    // invalid rdfs are skipped in lenient mode
+    let mut documents = documents;
+    documents[2] = SAMPLE_3.replacen("</dcterms:title>", "</dcterms:titel>", 1);
+    match stream_books_from_content(&documents, &ParseOptions::default(), |_| Ok(())) {
+        Err(Error::InvalidRdfFile { file, .. }) => assert_eq!(file, "content #2"),
+        _ => panic!("expected an rdf file error"),
+    }
+    let mut lenient_records = Vec::new();
+    let lenient = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let report = stream_books_from_content(&documents, &lenient, |record| {
+        lenient_records.push(record);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(report.skipped.len(), 1);
+    records.remove(2);
+    assert_eq!(lenient_records, records);
+}
+
+/// a custom parser collecting the resource of every `cc:license` node
+#[derive(Default)]
+struct LicenseResourceParser {
+    in_license: bool,
+    result: ParseItemResult,
+}
+
+impl FSTParser for LicenseResourceParser {
+    fn text(&mut self, _: &str, _: &mut ParseResult, _: i32) -> Result<(), Error> {
+        Ok(())
+    }
+    fn reset(&mut self) {
+        self.in_license = false;
+        self.result.reset();
+    }
+    fn start_node(&mut self, node_name: &str) {
+        self.in_license = node_name == "cc:license";
+    }
+    fn attribute(
+        &mut self,
+        attribute_name: &str,
+        attribute_value: &str,
+        parse_result: &mut ParseResult,
+        book_id: i32,
+    ) -> Result<(), Error> {
+        if self.in_license && attribute_name == "rdf:resource" {
+            let idx = parse_result.add_custom_field(
+                "cc_license",
+                attribute_value.to_string(),
+                book_id,
+            )?;
+            self.result.item_links.push(idx);
+        }
+        Ok(())
+    }
+    fn end_node(&mut self, _: &str) {
+        self.in_license = false;
+    }
+    fn is_found(&self) -> bool {
+        self.in_license
+    }
+    fn has_results(&self) -> bool {
+        !self.result.item_links.is_empty()
     }
+    fn get_parse_type(&self) -> ParseType {
+        ParseType::Custom
+    }
+    fn get_result(&self) -> Result<&ParseItemResult, Error> {
+        Ok(&self.result)
+    }
+    fn get_files(&self) -> Result<Vec<GutenbergFileEntry>, Error> {
+        Err(Error::InvalidResult("no results".to_string()))
+    }
+    fn get_custom_key(&self) -> Option<&str> {
+        Some("cc_license")
+    }
+}
+
+#[test]
+fn test_custom_fields() {
+    let with_lccn = SAMPLE_1.replacen(
+        "<dcterms:publisher>",
+        "<pgterms:marc010>2001012345</pgterms:marc010><dcterms:publisher>",
+        1,
+    );
+    let documents = vec![
+        with_lccn,
+        SAMPLE_2.to_string(),
+        SAMPLE_3.to_string(),
+        SAMPLE_4.to_string(),
+    ];
+    let custom_fields = vec![
+        CustomField::path("lccn", "pgterms:ebook/pgterms:marc010").unwrap(),
+        CustomField::path("alias", "pgterms:agent/pgterms:alias").unwrap(),
+        CustomField::attribute("dcterms_license", "dcterms:license", "rdf:resource").unwrap(),
+        CustomField::parser("cc_license", || Box::<LicenseResourceParser>::default()),
+    ];
+    let options = ParseOptions {
+        custom_fields: custom_fields.clone(),
+        ..Default::default()
+    };
+    let (parsing_results, _) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+    assert_eq!(
+        parsing_results
+            .custom_dictionaries
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["lccn", "alias", "dcterms_license", "cc_license"]
+    );
+    assert_eq!(parsing_results.custom_dictionaries["cc_license"].len(), 1);
+    let book = &parsing_results.books[0];
+    assert_eq!(book.custom_fields["lccn"], vec![0]);
+    assert!(!parsing_results.books[1].custom_fields.contains_key("lccn"));
+    // the built in fields are parsed as before
+    let plain = parse_rdfs_from_content(&documents, false).unwrap();
+    assert!(plain.custom_dictionaries.is_empty());
+    for (custom, plain) in parsing_results.books.iter().zip(plain.books.iter()) {
+        let mut custom = custom.clone();
+        custom.custom_fields.clear();
+        assert_eq!(&custom, plain);
+    }
+
+    for threads in [2, 3] {
+        let options = ParseOptions {
+            threads,
+            custom_fields: custom_fields.clone(),
+            ..Default::default()
+        };
+        let (parallel, _) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+        assert_same_parse(&parallel, &parsing_results);
+    }
+
+    let mut x = cache_from(&parsing_results);
+    let book = x.get_book(1).unwrap().unwrap();
+    assert_eq!(book.custom_fields["lccn"], vec!["2001012345".to_string()]);
+    assert_eq!(
+        book.custom_fields["cc_license"],
+        vec!["https://creativecommons.org/publicdomain/zero/1.0/".to_string()]
+    );
+    assert!(!x
+        .get_book(25)
+        .unwrap()
+        .unwrap()
+        .custom_fields
+        .contains_key("lccn"));
+    // the streamed books get the custom fields too
+    stream_books_from_content(&documents, &options, |record| {
+        assert_eq!(
+            Some(&record),
+            x.get_book(record.gutenberg_id).unwrap().as_ref()
+        );
+        Ok(())
+    })
+    .unwrap();
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.unchanged, documents.len());
+
+    // a book that loses a custom value is updated, the value is pruned
+    let mut documents = documents;
+    documents[0] = SAMPLE_1.to_string();
+    let (parsing_results, _) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.updated, vec![1]);
+    assert!(!x
+        .get_book(1)
+        .unwrap()
+        .unwrap()
+        .custom_fields
+        .contains_key("lccn"));
+
+    let settings = GutenbergCacheSettings::from(&json!({
+        "CustomFields": [
+            {"Key": "lccn", "Path": "pgterms:ebook/pgterms:marc010"},
+            {"Key": "license", "Path": "cc:Work/cc:license", "Attribute": "rdf:resource"}
+        ]
+    }))
+    .unwrap();
+    assert_eq!(
+        settings
+            .custom_fields
+            .iter()
+            .map(|x| x.key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["lccn", "license"]
+    );
+    assert!(GutenbergCacheSettings::from(&json!({"CustomFields": [{"Key": "lccn"}]})).is_err());
+}
+
+#[test]
+fn test_custom_field_paths() {
+    let documents = vec![SAMPLE_1.to_string(), SAMPLE_4.to_string()];
+    let custom_fields = vec![
+        CustomField::path("languages", "dcterms:language/*/rdf:value").unwrap(),
+        CustomField::path("subjects", "pgterms:ebook//dcterms:subject//rdf:value").unwrap(),
+        CustomField::path("not_a_child", "pgterms:ebook/rdf:value").unwrap(),
+        CustomField::attribute(
+            "lcc",
+            "dcterms:subject/rdf:Description/dcam:memberOf[@rdf:resource='http://purl.org/dc/terms/LCC']",
+            "rdf:resource",
+        )
+        .unwrap(),
+        CustomField::attribute(
+            "lcsh",
+            "dcam:memberOf[@rdf:resource=http://purl.org/dc/terms/LCSH]",
+            "rdf:resource",
+        )
+        .unwrap(),
+        CustomField::path(
+            "typed_languages",
+            "rdf:value[@rdf:datatype=\"http://purl.org/dc/terms/RFC4646\"]",
+        )
+        .unwrap(),
+        CustomField::path("downloads", "pgterms:ebook/pgterms:downloads[@rdf:datatype]").unwrap(),
+        CustomField::path("no_downloads", "pgterms:downloads[@rdf:about]").unwrap(),
+        CustomField::path(
+            "lcc_codes",
+            "rdf:Description[dcam:memberOf/@rdf:resource='http://purl.org/dc/terms/LCC']/rdf:value",
+        )
+        .unwrap(),
+        CustomField::path(
+            "described_subjects",
+            "dcterms:subject/rdf:Description[dcam:memberOf][rdf:value]/rdf:value",
+        )
+        .unwrap(),
+        CustomField::path(
+            "no_vocabulary",
+            "rdf:Description[dcam:memberOf/@rdf:resource=LCC]/rdf:value",
+        )
+        .unwrap(),
+        CustomField::path(
+            "languages_or_downloads",
+            "dcterms:language/*/rdf:value | pgterms:ebook/pgterms:downloads",
+        )
+        .unwrap(),
+    ];
+    let options = ParseOptions {
+        custom_fields,
+        ..Default::default()
+    };
+    let (parsing_results, _) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+    let plain = parse_rdfs_from_content(&documents, false).unwrap();
+    let mut x = cache_from(&parsing_results);
+    let mut plain_cache = cache_from(&plain);
+    for id in [1, 1000] {
+        let book = x.get_book(id).unwrap().unwrap();
+        let expected = plain_cache.get_book(id).unwrap().unwrap();
+        let custom = |key: &str| book.custom_fields.get(key).cloned().unwrap_or_default();
+        assert_eq!(custom("languages"), expected.languages);
+        assert_eq!(custom("typed_languages"), expected.languages);
+        let mut subjects = custom("subjects");
+        subjects.sort();
+        let mut expected_subjects = [expected.subjects.clone(), expected.lcc.clone()].concat();
+        expected_subjects.sort();
+        assert_eq!(subjects, expected_subjects);
+        assert_eq!(custom("not_a_child"), Vec::<String>::new());
+        assert_eq!(custom("lcc_codes"), expected.lcc);
+        let mut described = custom("described_subjects");
+        described.sort();
+        assert_eq!(described, expected_subjects);
+        assert_eq!(custom("no_vocabulary"), Vec::<String>::new());
+        assert_eq!(
+            custom("lcc"),
+            vec!["http://purl.org/dc/terms/LCC".to_string(); expected.lcc.len()]
+        );
+        assert_eq!(
+            custom("lcsh"),
+            vec!["http://purl.org/dc/terms/LCSH".to_string(); expected.subjects.len()]
+        );
+        assert_eq!(
+            custom("downloads"),
+            vec![expected.num_downloads.to_string()]
+        );
+        assert_eq!(custom("no_downloads"), Vec::<String>::new());
+        let mut either = custom("languages_or_downloads");
+        either.sort();
+        let mut expected_either = [
+            expected.languages.clone(),
+            vec![expected.num_downloads.to_string()],
+        ]
+        .concat();
+        expected_either.sort();
+        assert_eq!(either, expected_either);
+    }
+
+    for path in [
+        "", "a//", "a[@x", "a[x", "a[@=y]", "a[@x='y]", "a[@x]b", "a|", "|a", "a||b", "a[]",
+        "a[x/]", "a[x/y]", "a[x=y]", "a[/@y]",
+    ] {
+        match CustomField::path("key", path) {
+            Err(Error::InvalidPath(_)) => {}
+            _ => panic!("expected a path error for {}", path),
+        }
+    }
+    assert!(GutenbergCacheSettings::from(&json!({
+        "CustomFields": [{"Key": "key", "Path": "a[@x"}]
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_alternative_titles_and_subtitles() {
+    let split = |title: &str| -> (String, Option<String>) { split_title(title) };
+    let owned =
+        |title: &str, subtitle: Option<&str>| (title.to_string(), subtitle.map(String::from));
+    assert_eq!(
+        split("Contraband; Or, A Losing Hazard"),
+        owned("Contraband", Some("A Losing Hazard"))
+    );
+    assert_eq!(
+        split("Frankenstein; or, the Modern Prometheus"),
+        owned("Frankenstein", Some("the Modern Prometheus"))
+    );
+    assert_eq!(
+        split("La Divina Commedia di Dante: Complete"),
+        owned("La Divina Commedia di Dante", Some("Complete"))
+    );
+    // a new line comes before the other conventions
+    assert_eq!(
+        split("Alice: A Story\r\nWith Pictures:  In Colour"),
+        owned("Alice: A Story", Some("With Pictures: In Colour"))
+    );
+    assert_eq!(
+        split("History of the Decline and Fall of the Roman Empire — Volume 2"),
+        owned(
+            "History of the Decline and Fall of the Roman Empire — Volume 2",
+            None
+        )
+    );
+    assert_eq!(split("Title:"), owned("Title", None));
+    assert_eq!(split(": Subtitle"), owned(": Subtitle", None));
+    assert_eq!(
+        split("Ünïcödé; OR, Sübtitle"),
+        owned("Ünïcödé", Some("Sübtitle"))
+    );
+
+    // the alternative title comes first in the document, the main title is still the main title
+    let with_alternative = SAMPLE_1.replacen(
+        "<dcterms:title>",
+        "<dcterms:alternative>Declaration of Independence</dcterms:alternative><dcterms:title>",
+        1,
+    );
+    let documents = vec![with_alternative, SAMPLE_4.to_string(), SAMPLE_6.to_string()];
+    let parsing_results = parse_rdfs_from_content(&documents, false).unwrap();
+    let book = &parsing_results.books[0];
+    let titles = &parsing_results.field_dictionaries[ParseType::Title as usize];
+    let alternatives = &parsing_results.field_dictionaries[ParseType::AlternativeTitle as usize];
+    assert_eq!(
+        titles.get_index(book.title_id as usize).unwrap().0,
+        "The Declaration of Independence of the United States of America"
+    );
+    assert_eq!(book.title_ids.len(), 1);
+    assert_eq!(
+        alternatives
+            .get_index(book.alternative_title_ids[0])
+            .unwrap()
+            .0,
+        "Declaration of Independence"
+    );
+
+    let mut x = cache_from(&parsing_results);
+    let book = x.get_book(1).unwrap().unwrap();
+    assert_eq!(
+        book.titles,
+        vec![
+            "The Declaration of Independence of the United States of America".to_string(),
+            "Declaration of Independence".to_string()
+        ]
+    );
+    assert_eq!(
+        book.title.as_deref(),
+        Some("The Declaration of Independence of the United States of America")
+    );
+    assert_eq!(book.subtitle, None);
+    assert_eq!(
+        book.alternative_titles,
+        vec!["Declaration of Independence".to_string()]
+    );
+    let book = x.get_book(41418).unwrap().unwrap();
+    assert_eq!(book.title.as_deref(), Some("Contraband"));
+    assert_eq!(book.subtitle.as_deref(), Some("A Losing Hazard"));
+    assert!(book.alternative_titles.is_empty());
+
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().alternative_title("Declaration of Independence"),
+        vec![1],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().title("Declaration of Independence"),
+        vec![1],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().subtitle("A Losing Hazard"),
+        vec![41418],
+    );
+    compare_find_results(&mut x, &BookQuery::new().subtitle("Complete"), vec![1000]);
+    let hits = x
+        .search_match("titles:hazard", 10)
+        .unwrap()
+        .iter()
+        .map(|r| r.gutenberg_id)
+        .collect::<Vec<i32>>();
+    assert_eq!(hits, vec![41418]);
+
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.unchanged, documents.len());
+}
+
+#[test]
+fn test_language_codes() {
+    assert_eq!(normalize_language_tag("en"), "en");
+    assert_eq!(normalize_language_tag(" ENG "), "en");
+    assert_eq!(normalize_language_tag("fre"), "fr");
+    assert_eq!(normalize_language_tag("en_us"), "en-US");
+    assert_eq!(normalize_language_tag("zho-hant-tw"), "zh-Hant-TW");
+    assert_eq!(normalize_language_tag("es-419"), "es-419");
+    assert_eq!(normalize_language_tag("enm"), "enm");
+    assert_eq!(normalize_language_tag("gla"), "gd");
+    assert_eq!(normalize_language_tag("English"), "en");
+    assert_eq!(normalize_language_tag("Serbo-Croatian"), "sh");
+    assert_eq!(normalize_language_tag("XX-yy"), "xx-YY");
+    assert_eq!(normalize_language_tag(""), "");
+
+    for value in ["en", "eng", "EN-gb", "English", "english"] {
+        assert_eq!(language_code(value), "eng", "{}", value);
+    }
+    assert_eq!(language_code("Deutsch"), "deu");
+    assert_eq!(language_code("ger"), "deu");
+    assert_eq!(language_code("français"), "fra");
+    assert_eq!(language_code("xx-YY"), "xx");
+    assert_eq!(macrolanguage_code("yue"), "zho");
+    assert_eq!(macrolanguage_code("zh-TW"), "zho");
+    assert_eq!(macrolanguage_code("nb"), "nor");
+    assert_eq!(macrolanguage_code("it"), "ita");
+    // a name shared with the members is the macrolanguage
+    assert_eq!(find_language("eesti").unwrap().code, "est");
+    let chinese = find_language("Chinese").unwrap();
+    assert_eq!((chinese.tag(), chinese.native_name), ("zh", "中文"));
+    assert_eq!(find_language("Klingon"), None);
+    let mut codes = HashSet::new();
+    for language in LANGUAGES.iter() {
+        assert!(codes.insert(language.code), "{}", language.code);
+        if let Some(code) = language.macrolanguage {
+            assert!(LANGUAGES.iter().any(|x| x.code == code), "{}", code);
+        }
+    }
+
+    let language_value = |language: &str| format!(">{}</rdf:value>", language);
+    let documents = vec![
+        SAMPLE_1.replace(&language_value("en"), &language_value("en_US")),
+        SAMPLE_2.to_string(),
+        SAMPLE_4.replace(&language_value("it"), &language_value("ita")),
+        SAMPLE_5
+            .replace(&language_value("en"), &language_value("yue"))
+            .replace("ebooks/90907\"", "ebooks/90908\""),
+        SAMPLE_5.replace(&language_value("en"), &language_value("cmn")),
+    ];
+    let parsing_results = parse_rdfs_from_content(&documents, false).unwrap();
+    assert_eq!(
+        parsing_results.field_dictionaries[ParseType::Language as usize]
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["en-US", "en", "it", "yue", "cmn"]
+    );
+    let mut x = cache_from(&parsing_results);
+    assert_eq!(x.get_book(1).unwrap().unwrap().languages, vec!["en-US"]);
+    assert_eq!(x.get_book(1000).unwrap().unwrap().languages, vec!["it"]);
+    for value in ["en", "eng", "English", "en-GB"] {
+        compare_find_results(&mut x, &BookQuery::new().language(value), vec![1, 25]);
+    }
+    compare_find_results(&mut x, &BookQuery::new().language("italiano"), vec![1000]);
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().with(QueryExpr::is(QueryField::Language, "ITA").ignore_case()),
+        vec![1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().prefix(QueryField::Language, "en-"),
+        vec![1],
+    );
+    compare_find_results(&mut x, &BookQuery::new().language("zh"), vec![]);
+    compare_find_results(&mut x, &BookQuery::new().language("yue"), vec![90908]);
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().macrolanguage("Chinese"),
+        vec![90907, 90908],
+    );
+    compare_find_results(&mut x, &BookQuery::new().macrolanguage("en"), vec![1, 25]);
+    compare_query_results(
+        &mut x,
+        &json!({"macrolanguage": ["zh"]}),
+        vec![90907, 90908],
+    );
+    let counts = x
+        .facet_counts(QueryField::MacroLanguage, None, None)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.value, c.count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counts,
+        vec![
+            ("eng".to_string(), 2),
+            ("zho".to_string(), 2),
+            ("ita".to_string(), 1)
+        ]
+    );
+    let names: (String, String) = x
+        .connection
+        .query_row(
+            "SELECT englishname, nativename FROM languages WHERE name = 'yue'",
+            (),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(names, ("Yue Chinese".to_string(), "粵語".to_string()));
+
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.unchanged, documents.len());
+}
+
+#[test]
+fn test_dates_and_shared_titles() {
+    // a copy of the first book under another id, the two books share a title
+    let copy = SAMPLE_1.replace("rdf:about=\"ebooks/1\"", "rdf:about=\"ebooks/2\"");
+    let documents = vec![
+        SAMPLE_2.to_string(),
+        SAMPLE_1.to_string(),
+        SAMPLE_3.to_string(),
+        copy,
+        SAMPLE_4.to_string(),
+        SAMPLE_5.to_string(),
+        SAMPLE_6.to_string(),
+    ];
+    let mut x = build_cache(documents);
+
+    let title = "The Declaration of Independence of the United States of America";
+    compare_find_results(&mut x, &BookQuery::new().title(title), vec![1, 2]);
+    assert_eq!(x.get_book(2).unwrap().unwrap().titles, vec![title]);
+    assert_eq!(
+        x.get_book(25).unwrap().unwrap().titles,
+        vec!["The 1991 CIA World Factbook"]
+    );
+    let title_rows: i32 = x
+        .connection
+        .query_row(
+            "SELECT COUNT(*) FROM titles WHERE name = ?",
+            (title,),
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(title_rows, 2);
+
+    let date_type: String = x
+        .connection
+        .query_row(
+            "SELECT typeof(dateissued) FROM books WHERE gutenbergbookid = 1000",
+            (),
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(date_type, "text");
+    assert_eq!(
+        x.get_book(1000).unwrap().unwrap().date_issued.as_deref(),
+        Some("1997-08-01")
+    );
+
+    compare_find_results(
+        &mut x,
+        &BookQuery::new()
+            .issued_between(Some("1990"), Some("1999"))
+            .unwrap(),
+        vec![25, 1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new()
+            .issued_between(None, Some("1992-02"))
+            .unwrap(),
+        vec![1, 2, 25],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new()
+            .issued_between(Some("2012-11-20"), None)
+            .unwrap(),
+        vec![41418, 90907],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().downloads_between(Some(100), Some(1000)),
+        vec![25, 1000],
+    );
+    assert!(BookQuery::new().issued_between(Some("90s"), None).is_err());
+    compare_query_results(
+        &mut x,
+        &json!({"language": ["en"], "dateissued": {"min": "2000"}, "downloads": {"max": 20}}),
+        vec![41418, 90907],
+    );
+    assert!(x.query(&json!({"dateissued": {"min": 1}})).is_err());
+    assert!(x.query(&json!({"downloads": {}})).is_err());
+}
+#[test]
+fn test_facets_and_stats() {
+    let mut x = build_cache(fixture_documents());
+
+    let facet = |counts: Vec<FacetCount>| -> Vec<(String, i64)> {
+        counts.into_iter().map(|c| (c.value, c.count)).collect()
+    };
+    let pairs = |values: &[(&str, i64)]| -> Vec<(String, i64)> {
+        values.iter().map(|(v, c)| (v.to_string(), *c)).collect()
+    };
+    assert_eq!(
+        facet(x.facet_counts(QueryField::Language, None, None).unwrap()),
+        pairs(&[("en", 5), ("it", 1)])
+    );
+    assert_eq!(
+        facet(
+            x.facet_counts(
+                QueryField::Subject,
+                Some(&BookQuery::new().language("it")),
+                None
+            )
+            .unwrap()
+        ),
+        pairs(&[
+            ("Epic poetry, Italian", 1),
+            ("Italian poetry -- To 1400", 1),
+            ("PQ", 1)
+        ])
+    );
+    assert_eq!(
+        facet(
+            x.facet_counts(QueryField::Bookshelf, None, Some(2))
+                .unwrap()
+        ),
+        pairs(&[
+            ("American Revolutionary War", 1),
+            ("Banned Books from Anne Haight's list", 1)
+        ])
+    );
+    assert_eq!(
+        facet(
+            x.issued_year_counts(Some(&BookQuery::new().language("en")))
+                .unwrap()
+        ),
+        pairs(&[
+            ("1971", 1),
+            ("1992", 1),
+            ("2008", 1),
+            ("2012", 1),
+            ("2019", 1)
+        ])
+    );
+
+    let stats = x.catalog_stats(None).unwrap();
+    assert_eq!(stats.books, 6);
+    assert_eq!(stats.languages, 2);
+    assert_eq!(stats.subjects, 17);
+    assert_eq!(stats.files, 75);
+    assert_eq!(stats.total_downloads, 1814);
+    assert_eq!(stats.first_issued.as_deref(), Some("1971-12-01"));
+    assert_eq!(stats.last_issued.as_deref(), Some("2019-12-12"));
+
+    let stats = x
+        .catalog_stats(Some(&BookQuery::new().language("it")))
+        .unwrap();
+    assert_eq!(stats.books, 1);
+    assert_eq!(stats.authors, 1);
+    assert_eq!(stats.bookshelves, 2);
+    assert_eq!(stats.files, 14);
+    assert_eq!(stats.total_downloads, 389);
+}
+#[test]
+fn test_update_cache() {
+    let documents = vec![
+        SAMPLE_1.to_string(),
+        SAMPLE_2.to_string(),
+        SAMPLE_3.to_string(),
+        SAMPLE_4.to_string(),
+    ];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    let mut x = cache_from(&parsing_results);
+    let snapshot = x.latest_snapshot().unwrap().unwrap();
+    assert_eq!(snapshot.books, 4);
+    assert_eq!(snapshot.added, 4);
+    assert_eq!(snapshot.source, None);
+
+    // the next catalog: new download count for book 1 and the new shelf of book 1, book 25 unchanged,
+    // 732 and 1000 gone, 90907 and 41418 new
+    let changed = SAMPLE_1.replace(">1184<", ">1200<").replace(
+        "<rdf:value>Politics</rdf:value>",
+        "<rdf:value>History</rdf:value>",
+    );
+    let catalog = vec![
+        changed,
+        SAMPLE_2.to_string(),
+        SAMPLE_5.to_string(),
+        SAMPLE_6.to_string(),
+    ];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&catalog, false).unwrap();
+    let report = x
+        .update_cache(&parsing_results, true, Some("test catalog"), false)
+        .unwrap();
+    assert_eq!(report.added, vec![90907, 41418]);
+    assert_eq!(report.updated, vec![1]);
+    assert_eq!(report.removed, vec![732, 1000]);
+    assert_eq!(report.unchanged, 1);
+
+    // the updated cache holds the same books as a cache built from the new catalog
+    let mut fresh = cache_from(&parsing_results);
+    for id in [1, 25, 41418, 90907] {
+        assert_eq!(x.get_book(id).unwrap(), fresh.get_book(id).unwrap());
+    }
+    assert_eq!(x.get_book(732).unwrap(), None);
+    assert_eq!(x.get_book(1).unwrap().unwrap().num_downloads, 1200);
+    compare_find_results(&mut x, &BookQuery::new().bookshelf("History"), vec![1]);
+    compare_find_results(&mut x, &BookQuery::new().bookshelf("Politics"), vec![]);
+    compare_find_results(&mut x, &BookQuery::new().language("it"), vec![]);
+    assert_eq!(
+        x.catalog_stats(None).unwrap(),
+        fresh.catalog_stats(None).unwrap()
+    );
+    let hits = x
+        .search("contraband", 10)
+        .unwrap()
+        .iter()
+        .map(|r| r.gutenberg_id)
+        .collect::<Vec<i32>>();
+    assert_eq!(hits, vec![41418]);
+    assert!(x.search("Dante", 10).unwrap().is_empty());
+
+    // applying the same catalog again changes nothing
+    let report = x
+        .update_cache(&parsing_results, true, Some("test catalog"), false)
+        .unwrap();
+    assert!(report.added.is_empty() && report.updated.is_empty() && report.removed.is_empty());
+    assert_eq!(report.unchanged, 4);
+
+    let snapshots = x.snapshots().unwrap();
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(snapshots[1].source.as_deref(), Some("test catalog"));
+    assert_eq!(snapshots[1].last_issued.as_deref(), Some("2019-12-12"));
+    assert_eq!(
+        (
+            snapshots[1].books,
+            snapshots[1].added,
+            snapshots[1].updated,
+            snapshots[1].removed
+        ),
+        (4, 2, 1, 2)
+    );
+
+    // only the stored content hashes are compared, a book without one is rewritten
+    x.connection
+        .execute(
+            "UPDATE books SET contenthash = NULL WHERE gutenbergbookid = 25",
+            (),
+        )
+        .unwrap();
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.updated, vec![25]);
+    assert_eq!(report.unchanged, 3);
+    let book = x.get_book(25).unwrap().unwrap();
+    assert_eq!(book, fresh.get_book(25).unwrap().unwrap());
+    assert_eq!(
+        book.content_hash(),
+        x.connection
+            .query_row(
+                "SELECT contenthash FROM books WHERE gutenbergbookid = 25",
+                (),
+                |r| r.get::<_, String>(0)
+            )
+            .unwrap()
+    );
+    assert_ne!(
+        book.content_hash(),
+        x.get_book(1).unwrap().unwrap().content_hash()
+    );
+
+    assert_eq!(x.remove_books(&[25, 12345]).unwrap(), 1);
+    compare_find_results(&mut x, &BookQuery::new(), vec![1, 41418, 90907]);
+}
+#[test]
+fn test_schema_version_and_migrations() {
+    let filename = std::env::temp_dir()
+        .join(format!("gutenberg_rs_migration_{}.db", std::process::id()))
+        .display()
+        .to_string();
+    let settings = GutenbergCacheSettings {
+        cache_filename: filename.clone(),
+        ..Default::default()
+    };
+    let documents = vec![SAMPLE_1.to_string(), SAMPLE_4.to_string()];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    let x = SQLiteCache::create_cache(&parsing_results, &settings, true, false).unwrap();
+    assert_eq!(x.schema_version().unwrap(), SCHEMA_VERSION);
+    drop(x);
+
+    // turn the cache into one built before the schema was versioned
+    {
+        let connection = rusqlite::Connection::open(&filename).unwrap();
+        connection
+            .execute_batch(
+                "DROP TABLE book_search; DROP TABLE snapshots; DROP INDEX books_dateissued_idx;
+                ALTER TABLE books DROP COLUMN dateissued;
+                DROP TABLE author_aliases; DROP TABLE author_webpages;
+                DROP INDEX authors_agentid_idx; DROP INDEX authors_birthdate_idx; DROP INDEX authors_deathdate_idx;
+                ALTER TABLE authors DROP COLUMN agentid; ALTER TABLE authors DROP COLUMN birthdate;
+                ALTER TABLE authors DROP COLUMN deathdate; DROP TABLE book_contributors;
+                DROP TABLE downloadlink_formats; DROP INDEX downloadlinks_bookid_idx;
+                ALTER TABLE downloadlinks DROP COLUMN extent; ALTER TABLE downloadlinks DROP COLUMN modified;
+                DROP INDEX subjects_vocabulary_idx; ALTER TABLE subjects DROP COLUMN vocabulary;
+                DROP TABLE descriptions; DROP INDEX books_type_idx;
+                ALTER TABLE books DROP COLUMN type; ALTER TABLE books DROP COLUMN license;
+                DROP TABLE customfields; DROP TABLE book_customfields;
+                DROP INDEX titles_bookid_idx; ALTER TABLE titles DROP COLUMN kind;
+                DROP INDEX books_subtitle_idx; ALTER TABLE books DROP COLUMN title;
+                ALTER TABLE books DROP COLUMN subtitle;
+                DROP INDEX languages_code_idx; DROP INDEX languages_macrolanguage_idx;
+                ALTER TABLE languages DROP COLUMN code; ALTER TABLE languages DROP COLUMN macrolanguage;
+                ALTER TABLE languages DROP COLUMN englishname; ALTER TABLE languages DROP COLUMN nativename;
+                DROP TABLE author_names; ALTER TABLE authors DROP COLUMN surname;
+                ALTER TABLE authors DROP COLUMN givennames; ALTER TABLE authors DROP COLUMN fullername;
+                ALTER TABLE authors DROP COLUMN displayname; ALTER TABLE authors DROP COLUMN corporate;
+                ALTER TABLE books DROP COLUMN contenthash; DROP TABLE cachestate;
+                PRAGMA user_version = 0;",
+            )
+            .unwrap();
+    }
+    let mut x = SQLiteCache::get_cache(&settings).unwrap();
+    assert_eq!(x.schema_version().unwrap(), SCHEMA_VERSION);
+    // the migrations could not fill in everything, the cache waits for a rebuild or an update
+    assert!(x.is_stale().unwrap());
+    compare_find_results(&mut x, &BookQuery::new().language("it"), vec![1000]);
+    compare_find_results(&mut x, &BookQuery::new().language("Italian"), vec![1000]);
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().author("Thomas Jefferson"),
+        vec![1],
+    );
+    // nothing is guessed from the old rows: no search index, no title kinds, no issue dates
+    assert!(x.search("dante", 10).unwrap().is_empty());
+    assert_eq!(x.get_book(1).unwrap().unwrap().title, None);
+    assert_eq!(x.get_book(1).unwrap().unwrap().date_issued, None);
+    // an update with the whole catalog rewrites every book and brings them back
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.updated, vec![1, 1000]);
+    assert!(!x.is_stale().unwrap());
+    let hits = x
+        .search("dante", 10)
+        .unwrap()
+        .iter()
+        .map(|r| r.gutenberg_id)
+        .collect::<Vec<i32>>();
+    assert_eq!(hits, vec![1000]);
+    assert_eq!(
+        x.get_book(1).unwrap().unwrap().title.as_deref(),
+        Some("The Declaration of Independence of the United States of America")
+    );
+    assert_eq!(
+        x.get_book(1).unwrap().unwrap().date_issued.as_deref(),
+        Some("1971-12-01")
+    );
+    // the file formats were migrated, the sizes come back with the update
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().download_links_type("application/zip"),
+        vec![1, 1000],
+    );
+    assert_eq!(
+        x.smallest_file(1, "text/plain").unwrap().unwrap().extent,
+        Some(120943)
+    );
+    // the subject vocabularies come back with the update too
+    compare_find_results(&mut x, &BookQuery::new().lcc_prefix("PQ"), vec![1000]);
+    assert_eq!(x.get_book(1).unwrap().unwrap().lcc, vec!["E201", "JK"]);
+    // the authors kept their rows and got their agent metadata
+    assert_eq!(x.get_author(1638).unwrap().unwrap().death_year, Some(1826));
+    assert_eq!(
+        x.connection
+            .query_row("SELECT COUNT(*) FROM authors", (), |r| r.get::<_, i64>(0))
+            .unwrap(),
+        2
+    );
+    assert_eq!(x.migrate().unwrap(), SCHEMA_VERSION);
+    drop(x);
+
+    // the migrations from version 9 fill in all of their data, the cache is not stale
+    {
+        let connection = rusqlite::Connection::open(&filename).unwrap();
+        connection
+            .execute_batch(
+                "DROP INDEX languages_code_idx; DROP INDEX languages_macrolanguage_idx;
+                ALTER TABLE languages DROP COLUMN code; ALTER TABLE languages DROP COLUMN macrolanguage;
+                ALTER TABLE languages DROP COLUMN englishname; ALTER TABLE languages DROP COLUMN nativename;
+                DROP TABLE author_names; ALTER TABLE authors DROP COLUMN surname;
+                ALTER TABLE authors DROP COLUMN givennames; ALTER TABLE authors DROP COLUMN fullername;
+                ALTER TABLE authors DROP COLUMN displayname; ALTER TABLE authors DROP COLUMN corporate;
+                ALTER TABLE books DROP COLUMN contenthash; DROP TABLE cachestate;
+                DROP INDEX book_contributors_authorid_idx; ALTER TABLE book_contributors DROP COLUMN authorid;
+                PRAGMA user_version = 9;",
+            )
+            .unwrap();
+    }
+    let mut x = SQLiteCache::get_cache(&settings).unwrap();
+    assert!(!x.is_stale().unwrap());
+    compare_find_results(&mut x, &BookQuery::new().language("Italian"), vec![1000]);
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().author("Dante Alighieri"),
+        vec![1000],
+    );
+    drop(x);
+
+    // a cache from a newer crate version is reported instead of breaking queries later
+    {
+        let connection = rusqlite::Connection::open(&filename).unwrap();
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+    }
+    match SQLiteCache::get_cache(&settings) {
+        Err(Error::InvalidCacheSchema(message)) => assert!(message.contains("schema version")),
+        _ => panic!("expected a schema error"),
+    }
+
+    // so is a database that is not a cache at all
+    std::fs::remove_file(&filename).unwrap();
+    rusqlite::Connection::open(&filename)
+        .unwrap()
+        .execute_batch("CREATE TABLE other(id INTEGER);")
+        .unwrap();
+    assert!(matches!(
+        SQLiteCache::get_cache(&settings),
+        Err(Error::InvalidCacheSchema(_))
+    ));
+    std::fs::remove_file(&filename).unwrap();
+}
+#[tokio::test]
+async fn test_setup_rebuilds_stale_cache() {
+    let directory = std::env::temp_dir().join(format!("gutenberg_rs_setup_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let archive = directory.join("rdf-files.tar.bz2").display().to_string();
+    let invalid = SAMPLE_3.replacen("</dcterms:title>", "</dcterms:titel>", 1);
+    let entries = [
+        ("cache/epub/1/pg1.rdf", SAMPLE_1),
+        ("cache/epub/25/pg25.rdf", SAMPLE_2),
+        ("cache/epub/732/pg732.rdf", invalid.as_str()),
+    ];
+    std::fs::write(&archive, tar_bz(&entries)).unwrap();
+    let settings = GutenbergCacheSettings {
+        cache_filename: directory.join("gutenbergindex.db").display().to_string(),
+        cache_rdf_archive_name: archive,
+        lenient_parsing: true,
+        ..Default::default()
+    };
+    // the skipped rdfs are reported
+    let (x, report) = setup_sqlite(&settings, false, false).await.unwrap();
+    assert_eq!(report.parsed, 2);
+    assert_eq!(
+        report
+            .skipped
+            .iter()
+            .map(|x| x.file.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["cache/epub/732/pg732.rdf"]
+    );
+    assert!(!x.is_stale().unwrap());
+    x.connection
+        .execute_batch(
+            "UPDATE cachestate SET stale = 1; DELETE FROM books WHERE gutenbergbookid = 25;",
+        )
+        .unwrap();
+    drop(x);
+
+    let (mut x, report) = setup_sqlite(&settings, false, false).await.unwrap();
+    assert_eq!(report.skipped.len(), 1);
+    assert!(!x.is_stale().unwrap());
+    compare_find_results(&mut x, &BookQuery::new(), vec![1, 25]);
+    drop(x);
+
+    // nothing is parsed when the cache is already there
+    let (_, report) = setup_sqlite(&settings, false, false).await.unwrap();
+    assert_eq!(report.parsed, 0);
+    assert!(report.skipped.is_empty());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_agents() {
+    let documents = fixture_documents();
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    let agents = &parsing_results.agents_dictionary;
+    // the five creators and the commentator of 732
+    assert_eq!(agents.len(), 6);
+    let jefferson = &agents["1638"];
+    assert_eq!(jefferson.agent_id, Some(1638));
+    assert_eq!(jefferson.name, "Jefferson, Thomas");
+    assert_eq!(
+        (jefferson.birth_year, jefferson.death_year),
+        (Some(1743), Some(1826))
+    );
+    assert_eq!(
+        jefferson.aliases,
+        vec!["United States President (1801-1809)"]
+    );
+    assert_eq!(
+        jefferson.webpages,
+        vec!["https://en.wikipedia.org/wiki/Thomas_Jefferson"]
+    );
+    assert_eq!(agents["8"].aliases.len(), 3);
+    assert_eq!(agents["8"].birth_year, None);
+    assert_eq!(parsing_results.books[4].agent_ids.len(), 0);
+
+    let mut x = cache_from(&parsing_results);
+    let dante = x.get_author(507).unwrap().unwrap();
+    assert_eq!(dante.name, "Dante Alighieri");
+    assert_eq!(
+        (dante.birth_year, dante.death_year),
+        (Some(1265), Some(1321))
+    );
+    assert_eq!(dante.aliases, vec!["Alighieri, Dante"]);
+    assert_eq!(
+        dante.webpages,
+        vec![
+            "https://it.wikipedia.org/wiki/Dante_Alighieri",
+            "https://en.wikipedia.org/wiki/Dante_Alighieri"
+        ]
+    );
+    assert_eq!(x.get_author(99999).unwrap(), None);
+    let authors = x.get_book_authors(732).unwrap();
+    assert_eq!(authors.len(), 1);
+    assert_eq!(authors[0].agent_id, Some(375));
+    assert_eq!(authors[0].webpages.len(), 2);
+
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().authors_died_before(1900),
+        vec![1, 732, 1000, 41418],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().author_died_between(Some(1300), Some(1330)),
+        vec![1000],
+    );
+
+    // an update only writes the agents that are new or changed
+    x.connection
+        .execute_batch(
+            "CREATE TEMP TABLE author_writes(authorid INTEGER);
+            CREATE TEMP TRIGGER author_updates AFTER UPDATE ON authors
+            BEGIN INSERT INTO author_writes VALUES (new.id); END;",
+        )
+        .unwrap();
+    let written = |x: &SQLiteCache| -> Vec<Option<i64>> {
+        let mut stmt = x
+            .connection
+            .prepare(
+                "SELECT DISTINCT authors.agentid FROM author_writes, authors
+                WHERE authors.id = author_writes.authorid ORDER BY authors.agentid",
+            )
+            .unwrap();
+        let rows = stmt.query_map((), |r| r.get(0)).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    };
+    x.update_cache(&parsing_results, false, None, false)
+        .unwrap();
+    assert!(written(&x).is_empty());
+    let documents = vec![SAMPLE_4.replace(">1321<", ">1322<")];
+    let changed: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    x.update_cache(&changed, false, None, false).unwrap();
+    assert_eq!(written(&x), vec![Some(507)]);
+    assert_eq!(x.get_author(507).unwrap().unwrap().death_year, Some(1322));
+
+    // a creator given as a reference to an agent described in another rdf
+    let reference = SAMPLE_1
+        .replace("rdf:about=\"ebooks/1\"", "rdf:about=\"ebooks/2\"")
+        .replace(
+            "<dcterms:creator>\n      <pgterms:agent rdf:about=\"2009/agents/1638\">",
+            "<dcterms:creator rdf:resource=\"2009/agents/1638\"/>\n    <dcterms:other>\n      <pgterms:agent>",
+        )
+        .replace("</dcterms:creator>", "</dcterms:other>");
+    let documents = vec![reference, SAMPLE_1.to_string()];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    assert_eq!(parsing_results.agents_dictionary.len(), 1);
+    assert_eq!(
+        parsing_results.agents_dictionary["1638"].book_links,
+        vec![1, 2]
+    );
+    let mut x = cache_from(&parsing_results);
+    assert_eq!(
+        x.get_book(2).unwrap().unwrap().authors,
+        vec!["Jefferson, Thomas"]
+    );
+}
+#[test]
+fn test_author_names() {
+    let name = |surname: &str, given_names: &str, fuller_name: Option<&str>, display_name: &str| {
+        AuthorName {
+            surname: Some(surname.to_string()),
+            given_names: Some(given_names.to_string()),
+            fuller_name: fuller_name.map(String::from),
+            display_name: display_name.to_string(),
+            corporate: false,
+        }
+    };
+    assert_eq!(
+        parse_author_name("Whyte-Melville, G. J. (George John)"),
+        name(
+            "Whyte-Melville",
+            "G. J.",
+            Some("George John Whyte-Melville"),
+            "G. J. Whyte-Melville"
+        )
+    );
+    assert_eq!(
+        parse_author_name("Jefferson, Thomas"),
+        name("Jefferson", "Thomas", None, "Thomas Jefferson")
+    );
+    assert_eq!(
+        parse_author_name("King,  Martin Luther, Jr."),
+        name("King", "Martin Luther", None, "Martin Luther King, Jr.")
+    );
+    assert_eq!(
+        parse_author_name("Dumas, Alexandre (père)"),
+        name("Dumas", "Alexandre", None, "Alexandre Dumas (père)")
+    );
+    assert_eq!(
+        parse_author_name("Smith, John (John Alfred)"),
+        name("Smith", "John", Some("John Alfred Smith"), "John Smith")
+    );
+    let dante = parse_author_name("Dante Alighieri");
+    assert_eq!(
+        (dante.surname, dante.display_name.as_str(), dante.corporate),
+        (None, "Dante Alighieri", false)
+    );
+    assert!(!parse_author_name("St. Augustine").corporate);
+    assert!(!parse_author_name("Homer").corporate);
+    for corporate in [
+        "United States. Central Intelligence Agency",
+        "Catholic Church. Pope (1878-1903 : Leo XIII)",
+        "Library of Congress",
+        "Royal Society of London",
+    ] {
+        let parsed = parse_author_name(corporate);
+        assert!(parsed.corporate, "{}", corporate);
+        assert_eq!(parsed.display_name, corporate);
+        assert_eq!(parsed.surname, None);
+    }
+
+    let documents = vec![
+        SAMPLE_1.to_string(),
+        SAMPLE_2.to_string(),
+        SAMPLE_3.to_string(),
+        SAMPLE_6.to_string(),
+    ];
+    let parsing_results = parse_rdfs_from_content(&documents, false).unwrap();
+    let mut x = cache_from(&parsing_results);
+    let author = &x.get_book_authors(41418).unwrap()[0];
+    assert_eq!(author.name, "Whyte-Melville, G. J. (George John)");
+    assert_eq!(author.surname.as_deref(), Some("Whyte-Melville"));
+    assert_eq!(author.given_names.as_deref(), Some("G. J."));
+    assert_eq!(
+        author.fuller_name.as_deref(),
+        Some("George John Whyte-Melville")
+    );
+    assert_eq!(author.display_name, "G. J. Whyte-Melville");
+    assert!(!author.corporate);
+    let author = &x.get_book_authors(25).unwrap()[0];
+    assert!(author.corporate);
+    assert_eq!(
+        author.display_name,
+        "United States. Central Intelligence Agency"
+    );
+
+    for form in [
+        "Whyte-Melville, G. J. (George John)",
+        "G. J. Whyte-Melville",
+        "George John Whyte-Melville",
+    ] {
+        compare_find_results(&mut x, &BookQuery::new().author(form), vec![41418]);
+    }
+    compare_find_results(&mut x, &BookQuery::new().author("Whyte-Melville"), vec![]);
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().with(QueryExpr::prefix(QueryField::Author, "george john").ignore_case()),
+        vec![41418],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().author_any(["Thomas Jefferson", "Edward Gibbon"]),
+        vec![1, 732],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().contributor("Henry Hart Milman"),
+        vec![732],
+    );
+    compare_query_results(&mut x, &json!({"author": ["Thomas Jefferson"]}), vec![1]);
+    // the facets still count the catalog names, once per author
+    assert_eq!(
+        x.facet_counts(QueryField::Author, None, None)
+            .unwrap()
+            .iter()
+            .map(|c| c.value.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "Gibbon, Edward",
+            "Jefferson, Thomas",
+            "United States. Central Intelligence Agency",
+            "Whyte-Melville, G. J. (George John)"
+        ]
+    );
+    assert_eq!(x.catalog_stats(None).unwrap().authors, 4);
+
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.unchanged, documents.len());
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().author("Thomas Jefferson"),
+        vec![1],
+    );
+}
+
+#[test]
+fn test_contributors() {
+    // 732 has a commentator, make 1000 a translation with an illustrator
+    let translated = SAMPLE_4.replace(
+        "    </dcterms:creator>",
+        "    </dcterms:creator>
+    <marcrel:trl>
+      <pgterms:agent rdf:about=\"2009/agents/1399\">
+        <pgterms:name>Longfellow, Henry Wadsworth</pgterms:name>
+        <pgterms:birthdate rdf:datatype=\"http://www.w3.org/2001/XMLSchema#integer\">1807</pgterms:birthdate>
+        <pgterms:deathdate rdf:datatype=\"http://www.w3.org/2001/XMLSchema#integer\">1882</pgterms:deathdate>
+      </pgterms:agent>
+    </marcrel:trl>
+    <marcrel:ill rdf:resource=\"2009/agents/8722\"/>",
+    );
+    let documents = vec![SAMPLE_1.to_string(), SAMPLE_3.to_string(), translated];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    assert_eq!(parsing_results.books[1].contributors.len(), 1);
+    assert_eq!(parsing_results.books[1].contributors[0].1, "cmm");
+    assert_eq!(parsing_results.books[2].contributors.len(), 2);
+    // contributors are not authors
+    assert_eq!(parsing_results.books[2].agent_ids.len(), 1);
+
+    let mut x = cache_from(&parsing_results);
+    let book = x.get_book(1000).unwrap().unwrap();
+    assert_eq!(book.authors, vec!["Dante Alighieri"]);
+    assert_eq!(
+        book.contributors["trl"],
+        vec!["Longfellow, Henry Wadsworth"]
+    );
+    assert_eq!(book.contributors["ill"], vec!["Milman, Henry Hart"]);
+    assert!(x.get_book(1).unwrap().unwrap().contributors.is_empty());
+
+    // `marcrel:*` matches the nodes of every role
+    let options = ParseOptions {
+        custom_fields: vec![CustomField::path(
+            "contributor_names",
+            "marcrel:*/pgterms:agent/pgterms:name",
+        )
+        .unwrap()],
+        ..Default::default()
+    };
+    let (custom_results, _) = parse_rdfs_from_content_with(&documents, &options, false).unwrap();
+    let mut custom = cache_from(&custom_results);
+    assert_eq!(
+        custom.get_book(732).unwrap().unwrap().custom_fields["contributor_names"],
+        vec!["Milman, Henry Hart"]
+    );
+    assert_eq!(
+        custom.get_book(1000).unwrap().unwrap().custom_fields["contributor_names"],
+        vec!["Longfellow, Henry Wadsworth"]
+    );
+
+    let contributors = x.get_book_contributors(732).unwrap();
+    assert_eq!(contributors.len(), 1);
+    assert_eq!(contributors[0].role, "cmm");
+    assert_eq!(role_name(&contributors[0].role), Some("Commentator"));
+    assert_eq!(contributors[0].author.agent_id, Some(8722));
+    assert_eq!(contributors[0].author.death_year, Some(1868));
+
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().contributor_role("trl"),
+        vec![1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().translator("Longfellow, Henry Wadsworth"),
+        vec![1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().contributor("Milman, Henry Hart"),
+        vec![732, 1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().illustrator("Milman, Henry Hart"),
+        vec![1000],
+    );
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().author("Milman, Henry Hart"),
+        vec![],
+    );
+    compare_query_results(
+        &mut x,
+        &json!({"not": {"contributorrole": ["trl"]}}),
+        vec![1, 732],
+    );
+    let roles = x
+        .facet_counts(QueryField::ContributorRole, None, None)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.value, c.count))
+        .collect::<Vec<(String, i64)>>();
+    assert_eq!(
+        roles,
+        vec![
+            ("cmm".to_string(), 1),
+            ("ill".to_string(), 1),
+            ("trl".to_string(), 1)
+        ]
+    );
+
+    // removing the commentated book keeps the commentator, who is still the illustrator of 1000
+    x.remove_books(&[732]).unwrap();
+    assert!(x.get_author(8722).unwrap().is_some());
+    x.remove_books(&[1000]).unwrap();
+    assert!(x.get_author(8722).unwrap().is_none());
+}
+
+#[test]
+fn test_contributors_without_agent_id() {
+    let translated = SAMPLE_4.replace(
+        "    </dcterms:creator>",
+        "    </dcterms:creator>
+    <marcrel:trl>
+      <pgterms:agent>
+        <pgterms:name>Cary, Henry Francis</pgterms:name>
+      </pgterms:agent>
+    </marcrel:trl>",
+    );
+    let documents = vec![SAMPLE_1.to_string(), translated];
+    let parsing_results: ParseResult = parse_rdfs_from_content(&documents, false).unwrap();
+    let mut x = cache_from(&parsing_results);
+
+    let book = x.get_book(1000).unwrap().unwrap();
+    assert_eq!(book.contributors["trl"], vec!["Cary, Henry Francis"]);
+    let contributors = x.get_book_contributors(1000).unwrap();
+    assert_eq!(contributors.len(), 1);
+    assert_eq!(contributors[0].author.agent_id, None);
+    assert_eq!(contributors[0].author.name, "Cary, Henry Francis");
+    compare_find_results(
+        &mut x,
+        &BookQuery::new().translator("Cary, Henry Francis"),
+        vec![1000],
+    );
+
+    // the synthetic agent is kept as long as a book links it
+    let report = x.update_cache(&parsing_results, true, None, false).unwrap();
+    assert_eq!(report.unchanged, documents.len());
+    assert_eq!(x.get_book_contributors(1000).unwrap().len(), 1);
+    x.remove_books(&[1000]).unwrap();
+    assert_eq!(x.catalog_stats(None).unwrap().authors, 1);
 }
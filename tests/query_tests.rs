@@ -0,0 +1,239 @@
+mod common;
+
+use common::{build_cache, fixture_documents};
+use gutenberg_rs::book_query::{BookQuery, QueryField, RangeField};
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashSet;
+
+const ALL_BOOKS: [i32; 6] = [1, 25, 732, 1000, 41418, 90907];
+
+/// (query, expected gutenberg ids)
+fn query_cases() -> Vec<(Value, Vec<i32>)> {
+    vec![
+        (
+            json!({"language": "\"en\""}),
+            vec![1, 25, 732, 41418, 90907],
+        ),
+        (json!({"language": ["it"]}), vec![1000]),
+        (json!({"language": ["fr"]}), vec![]),
+        (
+            json!({"language": ["eng"], "author": "Dante Alighieri"}),
+            vec![],
+        ),
+        (json!({"language": ["Italian"]}), vec![1000]),
+        (
+            json!({"language": {"prefix": "eng"}}),
+            vec![1, 25, 732, 41418, 90907],
+        ),
+        (json!({"language": {"prefix": "it"}}), vec![1000]),
+        (
+            json!({"language": {"contains": "Engl"}}),
+            vec![1, 25, 732, 41418, 90907],
+        ),
+        (json!({"language": {"contains": "engl"}}), vec![]),
+        (
+            json!({"language": {"contains": "engl", "ignore_case": true}}),
+            vec![1, 25, 732, 41418, 90907],
+        ),
+        (json!({"language": {"contains": "Ital"}}), vec![1000]),
+        (json!({"macrolanguage": {"prefix": "ita"}}), vec![1000]),
+        (
+            json!({"macrolanguage": {"contains": "nglish"}}),
+            vec![1, 25, 732, 41418, 90907],
+        ),
+        (
+            json!({"macrolanguage": ["it", "English"]}),
+            ALL_BOOKS.to_vec(),
+        ),
+        (json!({"author": "'Gibbon, Edward'"}), vec![732]),
+        (json!({"author": ["Dante Alighieri"]}), vec![1000]),
+        (json!({"author": ["Edward Gibbon"]}), vec![732]),
+        (json!({"translator": ["Henry Hart Milman"]}), vec![]),
+        (
+            json!({"author": ["United States. Central Intelligence Agency", "Jefferson, Thomas"]}),
+            vec![1, 25],
+        ),
+        (json!({"title": ["The 1991 CIA World Factbook"]}), vec![25]),
+        (
+            json!({"title": ["La Divina Commedia di Dante: Complete"]}),
+            vec![1000],
+        ),
+        (
+            json!({"subtitle": ["Complete", "A Losing Hazard"]}),
+            vec![1000, 41418],
+        ),
+        (json!({"subtitle": {"prefix": "A "}}), vec![41418]),
+        (json!({"alternativetitle": {"contains": "a"}}), vec![]),
+        (json!({"subject": ["Epic poetry, Italian"]}), vec![1000]),
+        (
+            json!({"subject": ["England -- Social life and customs -- 19th century -- Fiction"]}),
+            vec![41418],
+        ),
+        (
+            json!({"subject": ["Byzantine Empire -- History -- To 527"]}),
+            vec![732],
+        ),
+        (json!({"subject": ["E201"]}), vec![1]),
+        (
+            json!({"subject": ["Geography -- Handbooks, manuals, etc.", "Italian poetry -- To 1400"]}),
+            vec![25, 1000],
+        ),
+        (
+            json!({"publisher": ["Project Gutenberg"]}),
+            ALL_BOOKS.to_vec(),
+        ),
+        (json!({"publisher": ["Someone Else"]}), vec![]),
+        (json!({"bookshelve": ["CIA World Factbooks"]}), vec![25]),
+        (
+            json!({"bookshelve": "'Politics','IT Poesia'"}),
+            vec![1, 1000],
+        ),
+        (
+            json!({"rights": ["Public domain in the USA."]}),
+            ALL_BOOKS.to_vec(),
+        ),
+        (
+            json!({"downloadlinkstype": ["text/plain; charset=utf-8"]}),
+            vec![732, 1000],
+        ),
+        (
+            json!({"downloadlinkstype": ["text/html; charset=iso-8859-1"]}),
+            vec![41418],
+        ),
+        (
+            json!({"downloadlinkstype": ["application/rdf+xml"]}),
+            ALL_BOOKS.to_vec(),
+        ),
+        (
+            json!({"dateissued": {"min": "1990", "max": "2000"}}),
+            vec![25, 1000],
+        ),
+        (json!({"downloads": {"min": 1000}}), vec![1]),
+        (json!({"type": ["Text"]}), vec![90907]),
+        (
+            json!({"not": {"type": ["Sound", "Image"]}}),
+            ALL_BOOKS.to_vec(),
+        ),
+        (json!({"lcc": ["PR"]}), vec![41418]),
+        (json!({"lcc": {"prefix": "P"}}), vec![1000, 41418]),
+        (json!({"lcc": ["Epic poetry, Italian"]}), vec![]),
+        (json!({"lcsh": ["Epic poetry, Italian"]}), vec![1000]),
+        (json!({"lcsh": ["JK"]}), vec![]),
+        (json!({"contributorrole": ["cmm"]}), vec![732]),
+        (json!({"contributor": ["Milman, Henry Hart"]}), vec![732]),
+        (json!({"translator": {"prefix": "Milman"}}), vec![]),
+        (json!({"illustrator": ["Milman, Henry Hart"]}), vec![]),
+        (json!({"editor": {"contains": "a"}}), vec![]),
+        (json!({"authorbirth": {"max": 1750}}), vec![1, 732, 1000]),
+        (
+            json!({"authordeath": {"min": 1800, "max": 1900}}),
+            vec![1, 41418],
+        ),
+        (json!({"authordeath": {"max": 1399}}), vec![1000]),
+        (
+            json!({"not": {"authordeath": {"min": 1800}}, "language": ["en"]}),
+            vec![25, 732, 90907],
+        ),
+        (
+            json!({"language": ["en"], "subject": ["Rome -- History -- Empire, 30 B.C.-476 A.D."]}),
+            vec![732],
+        ),
+        (
+            json!({"language": ["it"], "subject": ["Rome -- History -- Empire, 30 B.C.-476 A.D."]}),
+            vec![],
+        ),
+    ]
+}
+
+#[test]
+fn test_query_cases() {
+    let mut cache = build_cache(fixture_documents());
+    for (query, expected) in query_cases() {
+        let result: HashSet<i32> = cache.query(&query).unwrap().into_iter().collect();
+        let expected: HashSet<i32> = expected.into_iter().collect();
+        assert_eq!(result, expected, "query {}", query);
+    }
+}
+
+#[test]
+fn test_query_cases_do_not_depend_on_parse_order() {
+    // row ids in the db follow the parse order, subjects and links must not be confused with them
+    let mut documents = fixture_documents();
+    documents.reverse();
+    let mut cache = build_cache(documents);
+    for (query, expected) in query_cases() {
+        let result: HashSet<i32> = cache.query(&query).unwrap().into_iter().collect();
+        let expected: HashSet<i32> = expected.into_iter().collect();
+        assert_eq!(result, expected, "query {}", query);
+    }
+}
+
+#[test]
+fn test_query_cases_cover_every_key() {
+    let cases = query_cases();
+    let keys: HashSet<String> = cases
+        .iter()
+        .flat_map(|(query, _)| query.as_object().unwrap().keys().cloned())
+        .collect();
+    for field in QueryField::ALL {
+        assert!(
+            keys.contains(field.json_key()),
+            "{} not tested",
+            field.json_key()
+        );
+    }
+    for field in RangeField::ALL {
+        assert!(
+            keys.contains(field.json_key()),
+            "{} not tested",
+            field.json_key()
+        );
+    }
+}
+
+#[test]
+fn test_builder_matches_json() {
+    let mut cache = build_cache(fixture_documents());
+    let builder_cases = vec![
+        (
+            BookQuery::new().subject("Epic poetry, Italian"),
+            json!({"subject": ["Epic poetry, Italian"]}),
+        ),
+        (
+            BookQuery::new().language("en").bookshelf("Politics"),
+            json!({"language": "\"en\"", "bookshelve": "\"Politics\""}),
+        ),
+        (
+            BookQuery::new().download_links_type("image/jpeg"),
+            json!({"downloadlinkstype": ["image/jpeg"]}),
+        ),
+        (
+            BookQuery::new()
+                .rights("Public domain in the USA.")
+                .publisher("Project Gutenberg"),
+            json!({"rights": ["Public domain in the USA."], "publisher": ["Project Gutenberg"]}),
+        ),
+        (
+            BookQuery::new()
+                .lcc_prefix("E")
+                .lcsh("United States. Declaration of Independence"),
+            json!({"lcc": {"prefix": "E"}, "lcsh": ["United States. Declaration of Independence"]}),
+        ),
+        (
+            BookQuery::new().authors_died_before(1800),
+            json!({"authordeath": {"max": 1799}, "not": {"authordeath": {"min": 1800}}}),
+        ),
+        (
+            BookQuery::new().author_born_between(Some(1700), Some(1800)),
+            json!({"authorbirth": {"min": 1700, "max": 1800}}),
+        ),
+    ];
+    for (builder, query) in builder_cases {
+        let mut a = cache.find(&builder).unwrap();
+        let mut b = cache.query(&query).unwrap();
+        a.sort();
+        b.sort();
+        assert_eq!(a, b, "query {}", query);
+    }
+}